@@ -1,14 +1,281 @@
-
 use gl_generator::{Registry, Api, Profile, Fallbacks, GlobalGenerator};
 use std::env;
-use std::fs::File;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[path = "src/gl/preprocessor.rs"]
+#[allow(dead_code)]
+mod preprocessor;
+
+
+const SHADER_DIR: &str = "src/shaders";
+
+/// Shader file suffixes, and the glslang stage each corresponds to
+const SHADER_STAGES: &[(&str, &str)] = &[
+	(".vert.glsl", "vert"),
+	(".frag.glsl", "frag"),
+	(".mesh.glsl", "mesh"),
+	(".task.glsl", "task"),
+	(".compute.glsl", "comp"),
+];
+
+/// Stand-ins for the defines subsystems inject at runtime, so that stages can be syntax checked.
+/// Values only need to be plausible, not match what is used at runtime.
+const VALIDATION_DEFINES: &[(&str, &str)] = &[
+	("WORKGROUP_SIZE", "32"),
+	("PARTICLES_PER_WORKGROUP", "32"),
+	("QUAD_SPAN_PER_PATCH", "4"),
+	("SUBDIVISIONS", "8"),
+	("MAX_MESHLET_VERTICES", "64"),
+	("MAX_MESHLET_TRIANGLES", "124"),
+	("MAX_VERTICES", "128"),
+	("MAX_PRIMITIVES", "64"),
+];
+
+/// Stages mentioning this are checked both with and without it defined.
+const VARIANT_DEFINE: &str = "VERTEX_PIPELINE";
+
+
+struct ShaderFile {
+	path: PathBuf,
+	file_name: String,
+
+	/// The glslang stage name, or None for files that are only ever imported
+	stage: Option<&'static str>,
+
+	/// Source with all imports resolved
+	processed: preprocessor::Processed,
+}
+
 
 fn main() {
-    let dest = env::var("OUT_DIR").unwrap();
-    let mut file = File::create(&Path::new(&dest).join("gl_bindings.rs")).unwrap();
+	let dest = env::var("OUT_DIR").unwrap();
+	let mut file = File::create(&Path::new(&dest).join("gl_bindings.rs")).unwrap();
+
+	Registry::new(Api::Gl, (4, 5), Profile::Core, Fallbacks::All, ["GL_NV_mesh_shader", "GL_ARB_gl_spirv", "GL_ARB_pipeline_statistics_query"])
+		.write_bindings(GlobalGenerator, &mut file)
+		.unwrap();
+
+	println!("cargo:rerun-if-changed=build.rs");
+	println!("cargo:rerun-if-changed=src/gl/preprocessor.rs");
+	println!("cargo:rerun-if-changed={}", SHADER_DIR);
+	println!("cargo:rerun-if-env-changed=GLSLANG_VALIDATOR");
+	println!("cargo:rerun-if-env-changed=SKIP_SHADER_VALIDATION");
+
+	let shaders = preprocess_shaders().unwrap_or_else(|errors| fail("Failed to preprocess shaders", &errors));
+
+	let glslang = find_glslang();
+	let processed_dir = Path::new(&dest).join("shaders");
+	fs::create_dir_all(&processed_dir).unwrap();
+
+	if let Some(glslang) = &glslang {
+		if env::var_os("SKIP_SHADER_VALIDATION").is_none() {
+			validate_shaders(glslang, &processed_dir, &shaders)
+				.unwrap_or_else(|errors| fail("Shader validation failed", &errors));
+		}
+	}
+
+	let modules = match &glslang {
+		Some(glslang) => compile_spirv(glslang, &processed_dir, &shaders),
+		None => Vec::new(),
+	};
+
+	write_spirv_table(&Path::new(&dest).join("spirv_shaders.rs"), &modules).unwrap();
+}
+
+
+fn fail(message: &str, errors: &[String]) -> ! {
+	for error in errors {
+		eprintln!("{}", error);
+	}
+
+	panic!("{} ({} errors)", message, errors.len());
+}
+
+
+/// Runs the same import preprocessor used at runtime over every file in `SHADER_DIR`,
+/// so that unresolved or cyclic imports fail the build.
+fn preprocess_shaders() -> Result<Vec<ShaderFile>, Vec<String>> {
+	let shader_dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(SHADER_DIR);
+
+	let mut preprocessor = preprocessor::Preprocessor::new();
+	preprocessor.add_search_dir(&shader_dir);
+
+	let mut paths = fs::read_dir(&shader_dir).unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().is_some_and(|ext| ext == "glsl"))
+		.collect::<Vec<_>>();
+
+	paths.sort();
+
+	let mut shaders = Vec::new();
+	let mut errors = Vec::new();
+
+	for path in paths {
+		let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+		let stage = SHADER_STAGES.iter()
+			.find(|(suffix, _)| file_name.ends_with(suffix))
+			.map(|&(_, stage)| stage);
+
+		let source = match fs::read_to_string(&path) {
+			Ok(source) => source,
+			Err(error) => {
+				errors.push(format!("{}: {}", path.display(), error));
+				continue
+			}
+		};
+
+		match preprocessor.process(&file_name, &source) {
+			Ok(processed) => shaders.push(ShaderFile { path, file_name, stage, processed }),
+			Err(error) => errors.push(error.to_string()),
+		}
+	}
+
+	match errors.is_empty() {
+		true => Ok(shaders),
+		false => Err(errors),
+	}
+}
+
+
+/// `GLSLANG_VALIDATOR` if set, otherwise `glslangValidator` from `PATH`.
+fn find_glslang() -> Option<PathBuf> {
+	if let Some(path) = env::var_os("GLSLANG_VALIDATOR") {
+		return Some(path.into())
+	}
+
+	let exe_name = format!("glslangValidator{}", env::consts::EXE_SUFFIX);
+
+	env::split_paths(&env::var_os("PATH")?)
+		.map(|dir| dir.join(&exe_name))
+		.find(|path| path.is_file())
+}
+
+
+/// Syntax checks every stage with glslang, using `VALIDATION_DEFINES` in place of runtime defines.
+fn validate_shaders(glslang: &Path, processed_dir: &Path, shaders: &[ShaderFile]) -> Result<(), Vec<String>> {
+	let mut errors = Vec::new();
+
+	for shader in shaders {
+		let stage = match shader.stage {
+			Some(stage) => stage,
+			None => continue,
+		};
+
+		let processed_path = processed_dir.join(&shader.file_name);
+		fs::write(&processed_path, &shader.processed.source).unwrap();
+
+		let mut variants = vec![false];
+		if shader.processed.source.contains(VARIANT_DEFINE) {
+			variants.push(true);
+		}
+
+		for variant in variants {
+			let mut command = Command::new(glslang);
+			command.arg("-S").arg(stage);
+
+			for (name, value) in VALIDATION_DEFINES {
+				command.arg(format!("-D{}={}", name, value));
+			}
+
+			if variant {
+				command.arg(format!("-D{}=1", VARIANT_DEFINE));
+			}
+
+			let output = match command.arg(&processed_path).output() {
+				Ok(output) => output,
+				Err(error) => {
+					println!("cargo:warning=Failed to run {}, skipping shader validation: {}", glslang.display(), error);
+					return Ok(())
+				}
+			};
+
+			if !output.status.success() {
+				let variant_name = match variant {
+					true => format!(" with {}", VARIANT_DEFINE),
+					false => String::new(),
+				};
+
+				// glslang reports errors on stdout, by source string number
+				errors.push(format!("{}{}:\n{}\nSource strings: {}", shader.path.display(), variant_name,
+					String::from_utf8_lossy(&output.stdout).trim_end(), shader.processed.describe_files()));
+			}
+		}
+	}
+
+	match errors.is_empty() {
+		true => Ok(()),
+		false => Err(errors),
+	}
+}
+
+
+/// Compiles every stage to SPIR-V, returning (source path, module path) pairs.
+///
+/// Stages that rely on defines supplied at runtime are skipped, as they can only be compiled from GLSL.
+/// Any other stage that fails is left out with a warning, and is compiled from GLSL at runtime instead.
+fn compile_spirv(glslang: &Path, processed_dir: &Path, shaders: &[ShaderFile]) -> Vec<(PathBuf, PathBuf)> {
+	let mut modules = Vec::new();
+
+	for shader in shaders {
+		let stage = match shader.stage {
+			Some(stage) => stage,
+			None => continue,
+		};
+
+		let source = &shader.processed.source;
+		let needs_defines = source.contains(VARIANT_DEFINE)
+			|| VALIDATION_DEFINES.iter().any(|(name, _)| source.contains(name));
+
+		if needs_defines { continue }
+
+		let processed_path = processed_dir.join(&shader.file_name);
+		let module_path = processed_dir.join(format!("{}.spv", shader.file_name));
+
+		fs::write(&processed_path, &shader.processed.source).unwrap();
+
+		let result = Command::new(glslang)
+			.arg("-G")
+			.arg("-S").arg(stage)
+			.arg("-o").arg(&module_path)
+			.arg(&processed_path)
+			.output();
+
+		match result {
+			Ok(output) if output.status.success() => modules.push((shader.path.clone(), module_path)),
+			Ok(output) => {
+				println!("cargo:warning=Failed to precompile {} to SPIR-V, it will be compiled from GLSL at runtime:", shader.file_name);
+
+				let stdout = String::from_utf8_lossy(&output.stdout);
+				let stderr = String::from_utf8_lossy(&output.stderr);
+
+				for line in stdout.lines().chain(stderr.lines()).filter(|line| !line.trim().is_empty()) {
+					println!("cargo:warning=    {}", line);
+				}
+			}
+			Err(error) => {
+				println!("cargo:warning=Failed to run {}: {}", glslang.display(), error);
+				return Vec::new()
+			}
+		}
+	}
+
+	modules
+}
+
+
+/// Generates the table read by `gl::SpirvModule::precompiled`.
+fn write_spirv_table(path: &Path, modules: &[(PathBuf, PathBuf)]) -> std::io::Result<()> {
+	let mut file = File::create(path)?;
+
+	writeln!(file, "pub static MODULES: &[(&str, &[u8])] = &[")?;
+
+	for (source_path, module_path) in modules {
+		writeln!(file, "\t(include_str!({:?}), include_bytes!({:?}) as &[u8]),", source_path, module_path)?;
+	}
 
-	Registry::new(Api::Gl, (4, 5), Profile::Core, Fallbacks::All, ["GL_NV_mesh_shader"])
-	    .write_bindings(GlobalGenerator, &mut file)
-	    .unwrap();
-}
\ No newline at end of file
+	writeln!(file, "];")
+}
@@ -0,0 +1,75 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields};
+
+
+/// Implements `gl::layout::GpuLayout` for a struct with named fields, computing std140 and std430
+/// offsets for each field from the layouts of their types.
+#[proc_macro_derive(GpuLayout)]
+pub fn derive_gpu_layout(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+
+	match expand(&input) {
+		Ok(tokens) => tokens.into(),
+		Err(err) => err.to_compile_error().into(),
+	}
+}
+
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+	let fields = match &input.data {
+		Data::Struct(data) => match &data.fields {
+			Fields::Named(fields) => &fields.named,
+			_ => return Err(syn::Error::new_spanned(&input.ident, "GpuLayout can only be derived for structs with named fields")),
+		}
+
+		_ => return Err(syn::Error::new_spanned(&input.ident, "GpuLayout can only be derived for structs")),
+	};
+
+	if fields.is_empty() {
+		return Err(syn::Error::new_spanned(&input.ident, "GpuLayout can't be derived for empty structs"))
+	}
+
+	if !input.generics.params.is_empty() {
+		return Err(syn::Error::new_spanned(&input.generics, "GpuLayout can't be derived for generic structs"))
+	}
+
+	let name = &input.ident;
+	let layout = quote!(crate::gl::layout);
+
+	let idents = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect::<Vec<_>>();
+	let names = idents.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+	let types = fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+	let indices = (0..fields.len()).collect::<Vec<_>>();
+
+	let std140_fields = quote!([#(<#types as #layout::GpuLayout>::STD140),*]);
+	let std430_fields = quote!([#(<#types as #layout::GpuLayout>::STD430),*]);
+
+	Ok(quote! {
+		impl #layout::GpuLayout for #name {
+			const STD140: #layout::TypeLayout = #layout::struct_layout(&#std140_fields, #layout::Layout::Std140);
+			const STD430: #layout::TypeLayout = #layout::struct_layout(&#std430_fields, #layout::Layout::Std430);
+
+			const FIELDS_STD140: &'static [#layout::FieldLayout] = &[#(
+				#layout::FieldLayout {
+					name: #names,
+					offset: #layout::field_offset(&#std140_fields, #indices),
+				}
+			),*];
+
+			const FIELDS_STD430: &'static [#layout::FieldLayout] = &[#(
+				#layout::FieldLayout {
+					name: #names,
+					offset: #layout::field_offset(&#std430_fields, #indices),
+				}
+			),*];
+
+			fn write_bytes(&self, layout: #layout::Layout, bytes: &mut [u8]) {
+				let fields = <Self as #layout::GpuLayout>::fields(layout);
+				#(
+					#layout::GpuLayout::write_bytes(&self.#idents, layout, &mut bytes[fields[#indices].offset..]);
+				)*
+			}
+		}
+	})
+}
@@ -0,0 +1,29 @@
+// Command line arguments are parsed by each mode in turn, with a `take_from_args(&mut Vec<String>)`
+// that removes only the arguments it recognises. Whatever is left once all have run is unknown.
+
+
+/// Fails on the first argument that no parser took.
+pub fn reject_remaining(args: &[String]) -> Result<(), String> {
+	match args.first() {
+		Some(arg) => Err(format!("Unknown argument '{}'", arg)),
+		None => Ok(()),
+	}
+}
+
+
+#[cfg(test)]
+pub fn owned(list: &[&str]) -> Vec<String> {
+	list.iter().map(|&arg| arg.to_owned()).collect()
+}
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn rejects_arguments_left_over() {
+		assert!(reject_remaining(&[]).is_ok());
+		assert_eq!(reject_remaining(&owned(&["--frobnicate", "--other"])).unwrap_err(), "Unknown argument '--frobnicate'");
+	}
+}
@@ -0,0 +1,852 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use common::math::*;
+use crate::{gl, headless, perf, Camera, Scene, SceneToggles};
+use crate::perf::sink::csv_field;
+
+
+const SUBSYSTEM_NAMES: &[&str] = &["scene", "particles", "paint", "terrain"];
+
+/// Section name used for whole frame statistics in reports
+const FRAME_SECTION: &str = "(frame)";
+
+const CSV_HEADER: &str = "section,metric,count,min,max,mean,median,p95,p99";
+
+
+/// Flies a scripted camera path with a fixed set of subsystems, and reports per-section statistics
+/// for the measured frames. Optionally compares against a baseline report, failing on regressions.
+///
+/// Enabled with `--benchmark [--warmup N] [--bench-frames N] [--subsystems LIST] [--report PATH]
+/// [--baseline PATH] [--threshold PERCENT] [--min-delta MS]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchmarkOptions {
+	pub warmup_frames: u32,
+	pub frames: u32,
+	pub width: u32,
+	pub height: u32,
+	pub toggles: SceneToggles,
+
+	/// Written as JSON if the extension is `.json`, otherwise as CSV
+	pub report_path: Option<PathBuf>,
+
+	/// A report from a previous run, read as JSON if the extension is `.json`, otherwise as CSV
+	pub baseline_path: Option<PathBuf>,
+	pub thresholds: Thresholds,
+}
+
+/// How much slower a timing can get before it counts as a regression.
+/// Both limits must be exceeded, so that tiny sections don't fail on noise.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Thresholds {
+	/// Fraction of the baseline median
+	pub relative: f64,
+	pub absolute_ms: f64,
+}
+
+/// Summaries of every metric recorded during a benchmark, in a form that can be saved and compared.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+	pub frames: usize,
+	pub rows: Vec<ReportRow>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportRow {
+	pub section: String,
+	pub metric: String,
+	pub summary: perf::Summary,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+	pub section: String,
+	pub metric: String,
+	pub baseline_ms: f64,
+
+	/// None if the section wasn't recorded in the current run, which counts as a regression
+	pub current_ms: Option<f64>,
+	pub regressed: bool,
+}
+
+
+impl BenchmarkOptions {
+	/// Removes all benchmark arguments from `args`, leaving any others in place.
+	/// Returns None if `--benchmark` isn't present.
+	pub fn take_from_args(args: &mut Vec<String>) -> Result<Option<BenchmarkOptions>, String> {
+		let mut options = BenchmarkOptions::default();
+		let mut benchmark = false;
+		let mut first_option = None;
+		let mut index = 0;
+
+		while index < args.len() {
+			let takes_value = match args[index].as_str() {
+				"--benchmark" => {
+					args.remove(index);
+					benchmark = true;
+					continue
+				}
+
+				"--warmup" | "--bench-frames" | "--subsystems" | "--report" | "--baseline" | "--threshold" | "--min-delta" => true,
+				_ => false,
+			};
+
+			if !takes_value {
+				index += 1;
+				continue
+			}
+
+			let arg = args.remove(index);
+			if index >= args.len() {
+				return Err(format!("{} expects a value", arg))
+			}
+
+			let value = args.remove(index);
+
+			if first_option.is_none() {
+				first_option = Some(arg.clone());
+			}
+
+			match arg.as_str() {
+				"--warmup" => { options.warmup_frames = parse_number(&arg, &value)? }
+				"--bench-frames" => { options.frames = parse_number(&arg, &value)? }
+				"--subsystems" => { options.toggles = parse_subsystems(&value)? }
+				"--report" => { options.report_path = Some(value.into()) }
+				"--baseline" => { options.baseline_path = Some(value.into()) }
+				"--threshold" => { options.thresholds.relative = parse_number::<f64>(&arg, &value)? / 100.0 }
+				"--min-delta" => { options.thresholds.absolute_ms = parse_number(&arg, &value)? }
+				_ => unreachable!(),
+			}
+		}
+
+		if options.frames == 0 {
+			return Err("--bench-frames must be at least 1".into())
+		}
+
+		match (benchmark, first_option) {
+			(true, _) => Ok(Some(options)),
+			(false, Some(option)) => Err(format!("{} requires --benchmark", option)),
+			(false, None) => Ok(None),
+		}
+	}
+}
+
+impl Default for BenchmarkOptions {
+	fn default() -> Self {
+		BenchmarkOptions {
+			warmup_frames: 60,
+			frames: 300,
+			width: 700,
+			height: 700,
+			toggles: SceneToggles::default(),
+			report_path: None,
+			baseline_path: None,
+			thresholds: Thresholds {
+				relative: 0.1,
+				absolute_ms: 0.05,
+			},
+		}
+	}
+}
+
+
+pub fn run(gl_ctx: &gl::Context, scene: &mut Scene, inst: &mut perf::Instrumenter, options: &BenchmarkOptions) -> Result<(), Box<dyn Error>> {
+	scene.toggles = options.toggles;
+
+	inst.set_report_interval(None);
+	inst.set_stats_window(options.frames as usize);
+
+	log::info!("Benchmarking {:?} for {} frames after {} warm-up frames", options.toggles, options.frames, options.warmup_frames);
+
+	// Warm-up holds at the start of the path, so that measured frames are the same however long it runs
+	for _ in 0..options.warmup_frames {
+		render_frame(gl_ctx, scene, inst, 0.0);
+	}
+
+	inst.flush();
+	inst.reset_stats();
+
+	for frame in 0..options.frames {
+		render_frame(gl_ctx, scene, inst, frame as f32 / options.frames as f32);
+	}
+
+	inst.flush();
+	inst.finish_trace();
+	inst.report();
+
+	let report = Report::from_stats(inst.stats());
+
+	if let Some(path) = &options.report_path {
+		report.save(path)?;
+		log::info!("Wrote benchmark report to {}", path.display());
+	}
+
+	if let Some(path) = &options.baseline_path {
+		let baseline = Report::load(path)?;
+		let comparisons = report.compare(&baseline, options.thresholds);
+
+		print_comparisons(&comparisons, options.thresholds);
+
+		let regressions = comparisons.iter().filter(|comparison| comparison.regressed).count();
+		if regressions > 0 {
+			return Err(format!("{} regressions or missing sections against baseline {}", regressions, path.display()).into())
+		}
+	}
+
+	Ok(())
+}
+
+fn render_frame(gl_ctx: &gl::Context, scene: &mut Scene, inst: &mut perf::Instrumenter, t: f32) {
+	scene.paint_system.paint(headless::scripted_brush_pos(t));
+	scene.render(gl_ctx, inst, &scripted_camera(t), true);
+	inst.end_frame();
+}
+
+
+/// Positions on the ground plane with yaw, pitch and zoom, visited in order.
+const CAMERA_PATH: &[([f32; 2], f32, f32, f32)] = &[
+	([0.0, 0.0], 0.0, -PI / 5.0, 12.0),
+	([20.0, 0.0], PI / 2.0, -PI / 4.0, 30.0),
+	([20.0, 20.0], PI, -PI / 3.0, 60.0),
+	([-20.0, 20.0], 3.0 * PI / 2.0, -PI / 8.0, 8.0),
+	([-20.0, -20.0], 2.0 * PI, -PI / 5.0, 20.0),
+];
+
+/// Flies from the first to the last keyframe of `CAMERA_PATH` as `t` goes from 0 to 1.
+fn scripted_camera(t: f32) -> Camera {
+	let num_segments = CAMERA_PATH.len() - 1;
+	let position = t.clamp(0.0, 1.0) * num_segments as f32;
+	let index = (position.floor() as usize).min(num_segments - 1);
+	let f = position - index as f32;
+
+	let (start_pos, start_yaw, start_pitch, start_zoom) = CAMERA_PATH[index];
+	let (end_pos, end_yaw, end_pitch, end_zoom) = CAMERA_PATH[index + 1];
+
+	let lerp = |a: f32, b: f32| a + (b - a) * f;
+
+	Camera {
+		pos: Vec3::new(lerp(start_pos[0], end_pos[0]), 2.0, lerp(start_pos[1], end_pos[1])),
+		yaw: lerp(start_yaw, end_yaw),
+		pitch: lerp(start_pitch, end_pitch),
+		zoom: lerp(start_zoom, end_zoom),
+	}
+}
+
+
+impl Report {
+	pub fn from_stats(stats: &perf::Statistics) -> Report {
+		let mut rows = Vec::new();
+
+		let mut push = |section: &str, metric: &str, window: &perf::stats::RollingWindow| {
+			if let Some(summary) = window.summary() {
+				rows.push(ReportRow {
+					section: section.into(),
+					metric: metric.into(),
+					summary,
+				});
+			}
+		};
+
+		for scope in stats.scopes() {
+			push(&scope.path, "gpu_ms", &scope.gpu_time_ms);
+			push(&scope.path, "gpu_self_ms", &scope.gpu_self_time_ms);
+			push(&scope.path, "cpu_ms", &scope.cpu_time_ms);
+			push(&scope.path, "cpu_self_ms", &scope.cpu_self_time_ms);
+			push(&scope.path, "triangles", &scope.triangles);
+
+			for &counter in perf::Counter::ALL.iter() {
+				push(&scope.path, &counter.name().replace(' ', "_"), scope.counter(counter));
+			}
+		}
+
+		let frame = stats.frame();
+		push(FRAME_SECTION, "gpu_ms", &frame.gpu_time_ms);
+		push(FRAME_SECTION, "cpu_ms", &frame.cpu_time_ms);
+		push(FRAME_SECTION, "cpu_busy_ms", &frame.cpu_busy_ms);
+		push(FRAME_SECTION, "swap_wait_ms", &frame.swap_wait_ms);
+		push(FRAME_SECTION, "triangles", &frame.triangles);
+
+		Report {
+			frames: frame.cpu_time_ms.len(),
+			rows,
+		}
+	}
+
+	pub fn row(&self, section: &str, metric: &str) -> Option<&ReportRow> {
+		self.rows.iter().find(|row| row.section == section && row.metric == metric)
+	}
+
+	pub fn save(&self, path: &Path) -> std::io::Result<()> {
+		let mut file = BufWriter::new(File::create(path)?);
+
+		match path.extension().map_or(false, |ext| ext == "json") {
+			true => self.write_json(&mut file)?,
+			false => self.write_csv(&mut file)?,
+		}
+
+		file.flush()
+	}
+
+	pub fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+		writeln!(out, "{}", CSV_HEADER)?;
+
+		for row in self.rows.iter() {
+			let s = &row.summary;
+			writeln!(out, "{},{},{},{},{},{},{},{},{}", csv_field(&row.section), csv_field(&row.metric),
+				s.count, s.min, s.max, s.mean, s.median, s.p95, s.p99)?;
+		}
+
+		Ok(())
+	}
+
+	pub fn write_json(&self, out: &mut impl Write) -> std::io::Result<()> {
+		writeln!(out, "{{\"frames\":{},\"rows\":[", self.frames)?;
+
+		for (index, row) in self.rows.iter().enumerate() {
+			let s = &row.summary;
+
+			write!(out, "{{\"section\":")?;
+			perf::trace::write_json_string(out, &row.section)?;
+			write!(out, ",\"metric\":")?;
+			perf::trace::write_json_string(out, &row.metric)?;
+			write!(out, ",\"count\":{}", s.count)?;
+
+			for &(key, value) in [("min", s.min), ("max", s.max), ("mean", s.mean), ("median", s.median), ("p95", s.p95), ("p99", s.p99)].iter() {
+				// JSON has no NaN or infinity, so non-finite values are written as null
+				match value.is_finite() {
+					true => write!(out, ",\"{}\":{}", key, value)?,
+					false => write!(out, ",\"{}\":null", key)?,
+				}
+			}
+
+			write!(out, "}}")?;
+
+			match index + 1 < self.rows.len() {
+				true => writeln!(out, ",")?,
+				false => writeln!(out)?,
+			}
+		}
+
+		writeln!(out, "]}}")
+	}
+
+	/// Reads a report written by `save`, choosing the format from the extension in the same way.
+	pub fn load(path: &Path) -> Result<Report, String> {
+		let contents = std::fs::read_to_string(path)
+			.map_err(|error| format!("Failed to read baseline {}: {}", path.display(), error))?;
+
+		let report = match path.extension().map_or(false, |ext| ext == "json") {
+			true => Report::parse_json(&contents),
+			false => Report::parse_csv(&contents),
+		};
+
+		report.map_err(|error| format!("{}: {}", path.display(), error))
+	}
+
+	pub fn parse_csv(contents: &str) -> Result<Report, String> {
+		let mut lines = contents.lines().enumerate();
+
+		match lines.next() {
+			Some((_, header)) if header.trim() == CSV_HEADER => {}
+			_ => return Err("Not a CSV benchmark report".into()),
+		}
+
+		let mut rows = Vec::new();
+
+		for (line_index, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+			let fields = split_csv_line(line);
+			let error = || format!("Malformed row on line {}", line_index + 1);
+
+			if fields.len() != 9 {
+				return Err(error())
+			}
+
+			let number = |index: usize| fields[index].parse().map_err(|_| error());
+
+			rows.push(ReportRow {
+				section: fields[0].clone(),
+				metric: fields[1].clone(),
+				summary: perf::Summary {
+					count: fields[2].parse().map_err(|_| error())?,
+					min: number(3)?,
+					max: number(4)?,
+					mean: number(5)?,
+					median: number(6)?,
+					p95: number(7)?,
+					p99: number(8)?,
+				},
+			});
+		}
+
+		let frames = rows.iter()
+			.find(|row| row.section == FRAME_SECTION)
+			.map_or(0, |row| row.summary.count);
+
+		Ok(Report { frames, rows })
+	}
+
+	/// Parses the output of `write_json`. Keys within an object may be in any order, and unknown keys are ignored.
+	pub fn parse_json(contents: &str) -> Result<Report, String> {
+		let mut reader = JsonReader::new(contents);
+		let mut frames = None;
+		let mut rows = None;
+
+		reader.object(|reader, key| {
+			match key.as_str() {
+				"frames" => { frames = Some(reader.number()? as usize) }
+				"rows" => { rows = Some(reader.array(JsonReader::report_row)?) }
+				_ => reader.skip_value()?,
+			}
+
+			Ok(())
+		})?;
+
+		reader.end()?;
+
+		match (frames, rows) {
+			(Some(frames), Some(rows)) => Ok(Report { frames, rows }),
+			_ => Err("Not a JSON benchmark report".into()),
+		}
+	}
+
+	/// Compares the median of every timing in the baseline against the current run.
+	pub fn compare(&self, baseline: &Report, thresholds: Thresholds) -> Vec<Comparison> {
+		baseline.rows.iter()
+			.filter(|row| row.metric.ends_with("_ms"))
+			.map(|baseline_row| {
+				let baseline_ms = baseline_row.summary.median;
+				let current_ms = self.row(&baseline_row.section, &baseline_row.metric)
+					.map(|current| current.summary.median);
+
+				let regressed = match current_ms {
+					Some(current_ms) => {
+						let delta_ms = current_ms - baseline_ms;
+						delta_ms > thresholds.absolute_ms && delta_ms > baseline_ms * thresholds.relative
+					}
+
+					None => true,
+				};
+
+				Comparison {
+					section: baseline_row.section.clone(),
+					metric: baseline_row.metric.clone(),
+					baseline_ms,
+					current_ms,
+					regressed,
+				}
+			})
+			.collect()
+	}
+}
+
+
+fn print_comparisons(comparisons: &[Comparison], thresholds: Thresholds) {
+	println!("{:<30} {:<14} {:>10} {:>10} {:>8}", "baseline comparison", "metric", "baseline", "current", "change");
+
+	for comparison in comparisons {
+		let current_ms = match comparison.current_ms {
+			Some(current_ms) => current_ms,
+			None => {
+				println!("{:<30} {:<14} {:>10.3} {:>10} {:>8} MISSING", comparison.section, comparison.metric,
+					comparison.baseline_ms, "-", "-");
+				continue
+			}
+		};
+
+		let change = match comparison.baseline_ms > 0.0 {
+			true => format!("{:+.1}%", (current_ms / comparison.baseline_ms - 1.0) * 100.0),
+			false => "-".into(),
+		};
+
+		let marker = match comparison.regressed {
+			true => " REGRESSED",
+			false => "",
+		};
+
+		println!("{:<30} {:<14} {:>10.3} {:>10.3} {:>8}{}", comparison.section, comparison.metric,
+			comparison.baseline_ms, current_ms, change, marker);
+	}
+
+	println!("Regressions are medians more than {:.1}% and {:.3}ms slower than the baseline",
+		thresholds.relative * 100.0, thresholds.absolute_ms);
+}
+
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+	value.parse()
+		.map_err(|_| format!("Invalid value '{}' for {}", value, arg))
+}
+
+/// Parses a comma separated list of subsystem names, enabling only those.
+fn parse_subsystems(list: &str) -> Result<SceneToggles, String> {
+	let mut toggles = SceneToggles {
+		scene_view: false,
+		particles: false,
+		paint: false,
+		terrain: false,
+	};
+
+	for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+		match name {
+			"scene" => { toggles.scene_view = true }
+			"particles" => { toggles.particles = true }
+			"paint" => { toggles.paint = true }
+			"terrain" => { toggles.terrain = true }
+			_ => return Err(format!("Unknown subsystem '{}', expected one of {}", name, SUBSYSTEM_NAMES.join(", "))),
+		}
+	}
+
+	Ok(toggles)
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+	let mut fields = vec![String::new()];
+	let mut quoted = false;
+	let mut chars = line.chars().peekable();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'"' if quoted && chars.peek() == Some(&'"') => {
+				chars.next();
+				fields.last_mut().unwrap().push('"');
+			}
+
+			'"' => { quoted = !quoted }
+			',' if !quoted => fields.push(String::new()),
+			c => fields.last_mut().unwrap().push(c),
+		}
+	}
+
+	fields
+}
+
+
+/// Just enough of a JSON parser to read back reports written by `Report::write_json`.
+struct JsonReader<'a> {
+	chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonReader<'a> {
+	fn new(contents: &'a str) -> Self {
+		JsonReader { chars: contents.chars().peekable() }
+	}
+
+	fn peek(&mut self) -> Option<char> {
+		while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+			self.chars.next();
+		}
+
+		self.chars.peek().copied()
+	}
+
+	fn expect(&mut self, expected: char) -> Result<(), String> {
+		match self.peek() {
+			Some(c) if c == expected => { self.chars.next(); Ok(()) }
+			Some(c) => Err(format!("Expected '{}' but found '{}'", expected, c)),
+			None => Err(format!("Expected '{}' but found the end of the report", expected)),
+		}
+	}
+
+	fn end(&mut self) -> Result<(), String> {
+		match self.peek() {
+			None => Ok(()),
+			Some(c) => Err(format!("Unexpected '{}' after the end of the report", c)),
+		}
+	}
+
+	/// Calls `member` for each key, which must consume the value that follows it.
+	fn object(&mut self, mut member: impl FnMut(&mut Self, String) -> Result<(), String>) -> Result<(), String> {
+		self.expect('{')?;
+
+		if self.peek() == Some('}') {
+			self.chars.next();
+			return Ok(())
+		}
+
+		loop {
+			let key = self.string()?;
+			self.expect(':')?;
+			member(self, key)?;
+
+			match self.peek() {
+				Some(',') => { self.chars.next(); }
+				_ => return self.expect('}'),
+			}
+		}
+	}
+
+	fn array<T>(&mut self, mut element: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+		self.expect('[')?;
+		let mut elements = Vec::new();
+
+		if self.peek() == Some(']') {
+			self.chars.next();
+			return Ok(elements)
+		}
+
+		loop {
+			elements.push(element(self)?);
+
+			match self.peek() {
+				Some(',') => { self.chars.next(); }
+				_ => return self.expect(']').map(|_| elements),
+			}
+		}
+	}
+
+	fn string(&mut self) -> Result<String, String> {
+		self.expect('"')?;
+		let mut value = String::new();
+
+		loop {
+			match self.chars.next() {
+				Some('"') => return Ok(value),
+				Some('\\') => {
+					let escaped = match self.chars.next() {
+						Some('n') => '\n',
+						Some('t') => '\t',
+						Some('r') => '\r',
+						Some('u') => {
+							let hex = self.chars.by_ref().take(4).collect(): String;
+							u32::from_str_radix(&hex, 16).ok()
+								.and_then(std::char::from_u32)
+								.ok_or_else(|| format!("Invalid escape '\\u{}'", hex))?
+						}
+
+						Some(c) => c,
+						None => break,
+					};
+
+					value.push(escaped);
+				}
+
+				Some(c) => value.push(c),
+				None => break,
+			}
+		}
+
+		Err("Unterminated string".into())
+	}
+
+	fn number(&mut self) -> Result<f64, String> {
+		self.peek();
+
+		let mut text = String::new();
+		while let Some(&c) = self.chars.peek() {
+			if !(c.is_ascii_digit() || "+-.eE".contains(c)) { break }
+
+			text.push(c);
+			self.chars.next();
+		}
+
+		text.parse().map_err(|_| format!("Invalid number '{}'", text))
+	}
+
+	/// A number, or NaN for null.
+	fn number_or_null(&mut self) -> Result<f64, String> {
+		match self.peek() {
+			Some('n') => self.literal("null").map(|_| f64::NAN),
+			_ => self.number(),
+		}
+	}
+
+	fn literal(&mut self, expected: &str) -> Result<(), String> {
+		self.peek();
+
+		for expected_char in expected.chars() {
+			if self.chars.next() != Some(expected_char) {
+				return Err(format!("Expected '{}'", expected))
+			}
+		}
+
+		Ok(())
+	}
+
+	fn skip_value(&mut self) -> Result<(), String> {
+		match self.peek() {
+			Some('{') => self.object(|reader, _| reader.skip_value()),
+			Some('[') => self.array(JsonReader::skip_value).map(|_| ()),
+			Some('"') => self.string().map(|_| ()),
+			Some('n') => self.literal("null"),
+			Some('t') => self.literal("true"),
+			Some('f') => self.literal("false"),
+			_ => self.number().map(|_| ()),
+		}
+	}
+
+	fn report_row(&mut self) -> Result<ReportRow, String> {
+		let mut section = None;
+		let mut metric = None;
+		let mut values = [None; 7];
+
+		self.object(|reader, key| {
+			let index = match key.as_str() {
+				"section" => { section = Some(reader.string()?); return Ok(()) }
+				"metric" => { metric = Some(reader.string()?); return Ok(()) }
+				"count" => 0,
+				"min" => 1,
+				"max" => 2,
+				"mean" => 3,
+				"median" => 4,
+				"p95" => 5,
+				"p99" => 6,
+				_ => return reader.skip_value(),
+			};
+
+			values[index] = Some(match index {
+				0 => reader.number()?,
+				_ => reader.number_or_null()?,
+			});
+			Ok(())
+		})?;
+
+		match (section, metric, values) {
+			(Some(section), Some(metric), [Some(count), Some(min), Some(max), Some(mean), Some(median), Some(p95), Some(p99)]) => Ok(ReportRow {
+				section,
+				metric,
+				summary: perf::Summary { count: count as usize, min, max, mean, median, p95, p99 },
+			}),
+
+			_ => Err("Report row is missing a field".into()),
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::args::owned as args;
+
+	fn summary(median: f64) -> perf::Summary {
+		perf::Summary { count: 10, min: median, max: median, mean: median, median, p95: median, p99: median }
+	}
+
+	fn report(rows: &[(&str, &str, f64)]) -> Report {
+		Report {
+			frames: 10,
+			rows: rows.iter()
+				.map(|&(section, metric, median)| ReportRow { section: section.into(), metric: metric.into(), summary: summary(median) })
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn takes_benchmark_args() {
+		let mut list = args(&["--trace", "--benchmark", "--bench-frames", "100", "--subsystems", "terrain,scene", "--threshold", "5"]);
+		let options = BenchmarkOptions::take_from_args(&mut list).unwrap().unwrap();
+
+		assert_eq!(list, args(&["--trace"]));
+		assert_eq!(options.frames, 100);
+		assert_eq!(options.warmup_frames, 60);
+		assert!(options.toggles.terrain && options.toggles.scene_view);
+		assert!(!options.toggles.paint && !options.toggles.particles);
+		assert_eq!(options.thresholds.relative, 0.05);
+
+		assert_eq!(BenchmarkOptions::take_from_args(&mut args(&["--headless"])).unwrap(), None);
+		assert!(BenchmarkOptions::take_from_args(&mut args(&["--benchmark", "--subsystems", "sky"])).is_err());
+		assert!(BenchmarkOptions::take_from_args(&mut args(&["--benchmark", "--report"])).is_err());
+
+		assert_eq!(BenchmarkOptions::take_from_args(&mut args(&["--report", "out.json"])).unwrap_err(), "--report requires --benchmark");
+	}
+
+	#[test]
+	fn csv_reports_round_trip() {
+		let report = report(&[
+			("draw/terrain", "gpu_ms", 1.25),
+			("odd, \"name\"", "triangles", 5000.0),
+			(FRAME_SECTION, "cpu_ms", 16.5),
+		]);
+
+		let mut csv = Vec::new();
+		report.write_csv(&mut csv).unwrap();
+
+		let parsed = Report::parse_csv(std::str::from_utf8(&csv).unwrap()).unwrap();
+		assert_eq!(parsed, report);
+
+		assert!(Report::parse_csv("not,a,report").is_err());
+		assert!(Report::parse_csv(&format!("{}\nterrain,gpu_ms,1", CSV_HEADER)).is_err());
+	}
+
+	#[test]
+	fn json_reports_round_trip() {
+		let report = report(&[
+			("draw/terrain", "gpu_ms", 1.25),
+			("odd, \"name\"\n\u{1}", "triangles", 5000.0),
+			(FRAME_SECTION, "cpu_ms", f64::NAN),
+		]);
+
+		let mut json = Vec::new();
+		report.write_json(&mut json).unwrap();
+
+		let json = String::from_utf8(json).unwrap();
+		assert!(json.contains("\"median\":null") && !json.contains("NaN"));
+
+		let parsed = Report::parse_json(&json).unwrap();
+		assert_eq!(parsed.rows[..2], report.rows[..2]);
+		assert!(parsed.rows[2].summary.median.is_nan());
+
+		assert!(Report::parse_json(&format!("{}\n{}", CSV_HEADER, "terrain,gpu_ms,10,1,1,1,1,1,1")).is_err());
+		assert!(Report::parse_json("{\"frames\":10,\"rows\":[{\"section\":\"terrain\",\"metric\":\"gpu_ms\"}]}").is_err());
+		assert!(Report::parse_json("{\"frames\":10,\"rows\":[]} trailing").is_err());
+		assert!(Report::parse_json("{\"frames\":10,\"rows\":[{\"section\":\"a\",\"metric\":\"b\",\"count\":1,\"min\":NaN,\"max\":1,\"mean\":1,\"median\":1,\"p95\":1,\"p99\":1}]}").is_err());
+		assert!(Report::parse_json("{\"frames\":10,\"rows\":[],\"extra\":[null,true,false,{\"a\":1.5e3}]}").is_ok());
+	}
+
+	#[test]
+	fn flags_regressions_beyond_both_thresholds() {
+		let baseline = report(&[
+			("terrain", "gpu_ms", 2.0),
+			("paint", "gpu_ms", 0.01),
+			("scene", "gpu_ms", 1.0),
+			("terrain", "triangles", 1000.0),
+			("removed", "gpu_ms", 1.0),
+		]);
+
+		let current = report(&[
+			("terrain", "gpu_ms", 2.5),
+			("paint", "gpu_ms", 0.03),
+			("scene", "gpu_ms", 1.05),
+			("terrain", "triangles", 9000.0),
+		]);
+
+		let thresholds = Thresholds { relative: 0.1, absolute_ms: 0.05 };
+		let comparisons = current.compare(&baseline, thresholds);
+
+		let regressed = comparisons.iter()
+			.map(|comparison| (comparison.section.as_str(), comparison.regressed))
+			.collect(): Vec<_>;
+
+		// Paint triples, but by less than the absolute threshold. Scene slows by less than the relative one.
+		assert_eq!(regressed, [("terrain", true), ("paint", false), ("scene", false), ("removed", true)]);
+	}
+
+	#[test]
+	fn missing_sections_count_as_regressions() {
+		let baseline = report(&[("terrain", "gpu_ms", 2.0), ("renamed", "cpu_ms", 1.0)]);
+		let current = report(&[("terrain", "gpu_ms", 2.0), ("new name", "cpu_ms", 1.0)]);
+
+		let comparisons = current.compare(&baseline, Thresholds { relative: 0.1, absolute_ms: 0.05 });
+
+		assert_eq!(comparisons.len(), 2);
+		assert_eq!((comparisons[0].current_ms, comparisons[0].regressed), (Some(2.0), false));
+		assert_eq!(comparisons[1].section, "renamed");
+		assert_eq!((comparisons[1].current_ms, comparisons[1].regressed), (None, true));
+	}
+
+	#[test]
+	fn camera_path_visits_keyframes_in_order() {
+		let start = scripted_camera(0.0);
+		let end = scripted_camera(1.0);
+
+		assert_eq!((start.pos.x, start.pos.z, start.zoom), (0.0, 0.0, 12.0));
+		assert_eq!((end.pos.x, end.pos.z, end.zoom), (-20.0, -20.0, 20.0));
+
+		// Halfway between the first two keyframes
+		let camera = scripted_camera(0.125);
+		assert_eq!((camera.pos.x, camera.zoom), (10.0, 21.0));
+		assert!((scripted_camera(0.5).zoom - scripted_camera(0.5001).zoom).abs() < 0.1);
+	}
+}
@@ -1,5 +1,33 @@
+use std::cell::Cell;
 use std::collections::HashMap;
-
+use std::error::Error;
+use common::math::Vec4;
+
+pub mod preprocessor;
+pub mod interface;
+pub mod layout;
+pub mod pipeline;
+pub mod framebuffer;
+pub mod texture;
+pub mod debug;
+pub mod capabilities;
+pub mod spirv;
+pub mod program_cache;
+pub mod backend;
+pub mod recording;
+
+pub use preprocessor::Defines;
+pub use interface::{ExpectedResource, ProgramInterface};
+pub use layout::{GpuLayout, Layout};
+pub use pipeline::{PipelineState, BlendMode, CullMode, PolygonMode};
+pub use framebuffer::{Framebuffer, RenderTarget, RenderTargetDesc};
+pub use texture::{Texture, TextureDesc, TextureKind, TextureFormat, TextureRegion, Sampler, SamplerDesc, Filter, WrapMode};
+pub use debug::{DebugPolicy, DebugAction, DebugSeverity, DebugType};
+pub use capabilities::{Capabilities, Feature, RenderPath};
+pub use spirv::{SpirvModule, SpecializationConstants};
+pub use program_cache::ProgramCache;
+pub use backend::Backend;
+pub use recording::{RecordingBackend, Command};
 
 pub mod raw {
 	include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
@@ -8,92 +36,129 @@ pub mod raw {
 
 pub struct Context {
 	_sdl_ctx: sdl2::video::GLContext,
-	imports: HashMap<String, String>,
+	capabilities: Capabilities,
+	preprocessor: preprocessor::Preprocessor,
+	program_cache: Option<ProgramCache>,
+
+	/// Boxed so the debug callback can keep a pointer to it
+	debug_policy: Box<Cell<DebugPolicy>>,
+
+	/// Bound for draws that fetch their vertices from storage buffers rather than attributes
+	empty_vertex_array: VertexArray,
+
+	/// The last pipeline state applied, or None if the GL state is unknown
+	pipeline_state: Cell<Option<PipelineState>>,
+	wireframe_enabled: Cell<bool>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Program (u32);
 
+/// Lazily compiled variants of a program, keyed by the set of defines they were compiled with.
+pub struct ShaderPermutations {
+	label: String,
+	shaders: Vec<(u32, &'static str)>,
+	resources: Vec<ExpectedResource>,
+	programs: HashMap<Defines, Program>,
+}
+
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum BufferUsage {
 	Static,
 	Dynamic,
 	Stream,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Buffer (u32);
 
-#[derive(Copy, Clone, Debug)]
-pub struct Texture (u32);
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VertexArray (u32);
 
 
 
 impl Context {
 	pub fn new(sdl_ctx: sdl2::video::GLContext) -> Self {
+		let mut ctx = Context {
+			_sdl_ctx: sdl_ctx,
+			capabilities: Capabilities::query(),
+			preprocessor: preprocessor::Preprocessor::new(),
+			program_cache: None,
+			debug_policy: Box::new(Cell::new(DebugPolicy::DEFAULT)),
+			empty_vertex_array: VertexArray(0),
+
+			pipeline_state: Cell::new(None),
+			wireframe_enabled: Cell::new(false),
+		};
+
 		unsafe {
-			raw::DebugMessageCallback(Some(gl_message_callback), std::ptr::null());
-			raw::Enable(raw::DEBUG_OUTPUT_SYNCHRONOUS);
+			debug::install_callback(&ctx.debug_policy);
 			raw::Enable(raw::PROGRAM_POINT_SIZE);
+		}
 
-			raw::Enable(raw::DEPTH_TEST);
-			raw::Enable(raw::BLEND);
-			raw::BlendFunc(raw::DST_COLOR, raw::ZERO);
-			raw::BlendEquation(raw::FUNC_ADD);
-
-			// Disable performance messages
-			raw::DebugMessageControl(
-				raw::DONT_CARE,
-				raw::DEBUG_TYPE_PERFORMANCE,
-				raw::DONT_CARE,
-				0, std::ptr::null(),
-				0 // false
-			);
+		ctx.set_debug_policy(DebugPolicy::DEFAULT);
+		ctx.empty_vertex_array = ctx.new_vertex_array("empty");
+		ctx
+	}
 
-			// Disable notification messages
-			raw::DebugMessageControl(
-				raw::DONT_CARE,
-				raw::DONT_CARE,
-				raw::DEBUG_SEVERITY_NOTIFICATION,
-				0, std::ptr::null(),
-				0 // false
-			);
+
+	pub fn capabilities(&self) -> &Capabilities {
+		&self.capabilities
+	}
+
+	/// Forces all subsequent draws to render as wireframe, regardless of the pipeline state they request.
+	pub fn set_wireframe(&self, wireframe_enabled: bool) {
+		self.wireframe_enabled.set(wireframe_enabled);
+	}
+
+	pub fn set_pipeline_state(&self, state: &PipelineState) {
+		let mut state = *state;
+		if self.wireframe_enabled.get() {
+			state.polygon = PolygonMode::Line;
 		}
 
-		Context {
-			_sdl_ctx: sdl_ctx,
-			imports: HashMap::new(),
+		let previous = self.pipeline_state.get();
+		if previous == Some(state) {
+			return
 		}
+
+		state.apply(previous.as_ref());
+		self.pipeline_state.set(Some(state));
 	}
 
+	/// Should be called after anything modifies GL state outside of `set_pipeline_state`.
+	pub fn invalidate_pipeline_state(&self) {
+		self.pipeline_state.set(None);
+	}
 
-	pub fn set_wireframe(&self, wireframe_enabled: bool) {
-		let mode = match wireframe_enabled {
-			false => raw::FILL,
-			true => raw::LINE,
-		};
+	pub fn clear(&self, color: Vec4) {
+		// Depth writes must be enabled for depth clears to take effect
+		let mut state = self.pipeline_state.get().unwrap_or_default();
+		state.depth_write = true;
+		self.set_pipeline_state(&state);
 
 		unsafe {
-			raw::PolygonMode(raw::FRONT_AND_BACK, mode);
+			raw::ClearColor(color.x, color.y, color.z, color.w);
+			raw::Clear(raw::COLOR_BUFFER_BIT | raw::DEPTH_BUFFER_BIT);
 		}
 	}
 
-	pub fn new_buffer(&self) -> Buffer {
+	pub fn new_buffer(&self, label: &str) -> Buffer {
 		unsafe {
 			let mut buf = 0;
 			raw::CreateBuffers(1, &mut buf);
+			debug::set_label(raw::BUFFER, buf, label);
 			Buffer(buf)
 		}
 	}
 
-	pub fn new_texture(&self, width: u32, height: u32, format: u32) -> Texture {
+	pub fn new_vertex_array(&self, label: &str) -> VertexArray {
 		unsafe {
-			let mut tex = 0;
-			raw::CreateTextures(raw::TEXTURE_2D, 1, &mut tex);
-			raw::TextureStorage2D(tex, 1, format, width as i32, height as i32);
-			raw::TextureParameteri(tex, raw::TEXTURE_MIN_FILTER, raw::LINEAR as _);
-			Texture(tex)
+			let mut vao = 0;
+			raw::CreateVertexArrays(1, &mut vao);
+			debug::set_label(raw::VERTEX_ARRAY, vao, label);
+			VertexArray(vao)
 		}
 	}
 
@@ -109,119 +174,195 @@ impl Context {
 		}
 	}
 
-	pub fn bind_image_rw(&self, binding: u32, texture: Texture, format: u32) {
-		unsafe {
-			let (level, layered, layer) = (0, 0, 0);
-			raw::BindImageTexture(binding, texture.0, level, layered, layer, raw::READ_WRITE, format);
-		}
-	}
 
-	pub fn bind_texture(&self, binding: u32, texture: Texture) {
-		unsafe {
-			raw::BindTextureUnit(binding, texture.0);
+	/// Programs compiled from GLSL after this are saved to and restored from `cache`.
+	/// Ignored if the driver supports no program binary formats.
+	pub fn set_program_cache(&mut self, cache: Option<ProgramCache>) {
+		if cache.is_some() && self.capabilities.num_program_binary_formats == 0 {
+			log::warn!("Driver doesn't support program binaries, program cache disabled");
+			return
 		}
-	}
 
+		self.program_cache = cache;
+	}
 
-	pub fn add_shader_import(&mut self, name: impl Into<String>, src: impl Into<String>) {
-		let existing_import_src = self.imports.insert(name.into(), src.into());
-		assert!(existing_import_src.is_none());
+	pub fn add_shader_import(&mut self, name: impl Into<String>, src: impl Into<String>) -> Result<(), preprocessor::Error> {
+		self.preprocessor.add_import(name, src)
 	}
 
-	fn resolve_imports(&self, mut src: &str) -> String {
-		let search_pattern = "#import";
-		let mut result = String::with_capacity(src.len());
+	pub fn add_shader_import_dir(&mut self, path: impl Into<std::path::PathBuf>) {
+		self.preprocessor.add_search_dir(path);
+	}
 
-		while !src.is_empty() {
-			let (prefix, suffix) = match src.split_once(search_pattern) {
-				Some(pair) => pair,
-				None => {
-					result.push_str(src);
-					break
-				}
-			};
+	/// Compiles and links a program, and checks that its interface matches `resources`.
+	/// `label` names the program in debug messages and external GPU debuggers.
+	///
+	/// If the device supports SPIR-V, no defines are given, and build.rs precompiled every stage,
+	/// the precompiled modules are used instead of compiling `shaders` from source.
+	pub fn new_shader(&self, label: &str, shaders: &[(u32, &str)], defines: &Defines, resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+		use std::ffi::CString;
 
-			let (import_name, suffix) = suffix.split_once('\n')
-				.expect("Expected '#common <name>'");
-			src = suffix;
+		if self.capabilities.spirv && defines.is_empty() {
+			let modules = shaders.iter()
+				.map(|&(ty, src)| Some((ty, SpirvModule::precompiled(src)?)))
+				.collect(): Option<Vec<_>>;
 
-			let import_name = import_name.trim();
-			let import_str = self.imports.get(import_name)
-				.expect("Unknown import");
+			if let Some(modules) = modules {
+				let modules = modules.iter().map(|(ty, module)| (*ty, module)).collect(): Vec<_>;
 
-			result.push_str(prefix);
-			result.push_str(import_str);
+				match self.new_spirv_shader(label, &modules, &SpecializationConstants::new(), resources) {
+					Ok(program) => return Ok(program),
+					Err(error) => log::warn!("Precompiled SPIR-V for '{}' was rejected, compiling from source: {}", label, error),
+				}
+			}
 		}
 
-		result
-	}
-
-	pub fn new_shader(&self, shaders: &[(u32, &str)]) -> Program {
-		use std::ffi::CString;
-		use std::str;
+		let processed = shaders.iter()
+			.map(|&(ty, src)| self.preprocessor.process(shader_type_name(ty), src))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let sources = shaders.iter().zip(processed.iter())
+			.map(|(&(ty, _), processed)| {
+				let src = preprocessor::inject_defines(&processed.source, defines);
+				Ok((ty, CString::new(src)?))
+			})
+			.collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+		let stage_types = shaders.iter().map(|&(ty, _)| ty).collect(): Vec<_>;
+		let cache_key = self.program_cache.as_ref()
+			.map(|cache| cache.key(&sources, defines));
+
+		if let (Some(cache), Some(key)) = (&self.program_cache, cache_key) {
+			if let Some(binary) = cache.load(key) {
+				unsafe {
+					let program_handle = raw::CreateProgram();
+					binary.load_into(program_handle);
+
+					match check_program(program_handle, label, &stage_types, resources) {
+						Ok(program) => return Ok(program),
+						Err(error) => {
+							log::info!("Cached binary for '{}' was rejected, recompiling: {}", label, error);
+							cache.evict(key);
+						}
+					}
+				}
+			}
+		}
 
 		unsafe {
 			let program_handle = raw::CreateProgram();
 
-			for &(ty, src) in shaders {
-				let src = self.resolve_imports(&src);
-				let src = CString::new(src.as_bytes()).unwrap();
+			if self.program_cache.is_some() {
+				raw::ProgramParameteri(program_handle, raw::PROGRAM_BINARY_RETRIEVABLE_HINT, raw::TRUE as _);
+			}
 
+			for ((ty, src), processed) in sources.into_iter().zip(processed.iter()) {
 				let shader_handle = raw::CreateShader(ty);
 
 				raw::ShaderSource(shader_handle, 1, &src.as_ptr(), std::ptr::null());
 				raw::CompileShader(shader_handle);
 
-				let mut status = 0;
-				raw::GetShaderiv(shader_handle, raw::COMPILE_STATUS, &mut status);
+				if let Err(error) = check_compile_status(shader_handle, ty, processed) {
+					raw::DeleteProgram(program_handle);
+					return Err(error)
+				}
+
+				raw::AttachShader(program_handle, shader_handle);
+				raw::DeleteShader(shader_handle);
+			}
+
+			let program = link_program(program_handle, label, &stage_types, resources)?;
+
+			if let (Some(cache), Some(key)) = (&self.program_cache, cache_key) {
+				if let Some(binary) = program_cache::ProgramBinary::retrieve(program_handle) {
+					cache.store(key, &binary);
+				}
+			}
 
-				if status == 0 {
-					let mut length = 0;
-					raw::GetShaderiv(shader_handle, raw::INFO_LOG_LENGTH, &mut length);
+			Ok(program)
+		}
+	}
 
-					let mut buffer = vec![0u8; length as usize];
-					raw::GetShaderInfoLog(
-						shader_handle,
-						length,
-						std::ptr::null_mut(),
-						buffer.as_mut_ptr() as *mut _
-					);
+	/// Links a program from SPIR-V modules, specializing each with the subset of `constants` it declares.
+	/// Every module must use `main` as its entry point. Requires `Feature::Spirv`.
+	pub fn new_spirv_shader(&self, label: &str, shaders: &[(u32, &SpirvModule)], constants: &SpecializationConstants, resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+		self.capabilities.require(Feature::Spirv, "new_spirv_shader")?;
 
-					let error = str::from_utf8(&buffer[..buffer.len()-1]).unwrap();
+		unsafe {
+			let program_handle = raw::CreateProgram();
 
-					panic!("Shader compile failed!\n{}", error);
+			for &(ty, module) in shaders {
+				let shader_handle = raw::CreateShader(ty);
+
+				raw::ShaderBinary(
+					1, &shader_handle,
+					raw::SHADER_BINARY_FORMAT_SPIR_V_ARB,
+					module.words().as_ptr() as *const _,
+					module.size_in_bytes() as _
+				);
+
+				let (constant_ids, constant_values) = constants.for_module(module);
+
+				raw::SpecializeShaderARB(
+					shader_handle,
+					b"main\0".as_ptr() as *const _,
+					constant_ids.len() as _,
+					constant_ids.as_ptr(),
+					constant_values.as_ptr()
+				);
+
+				if let Err(error) = check_compile_status(shader_handle, ty) {
+					raw::DeleteProgram(program_handle);
+					return Err(error)
 				}
 
 				raw::AttachShader(program_handle, shader_handle);
 				raw::DeleteShader(shader_handle);
 			}
 
-			raw::LinkProgram(program_handle);
+			let stage_types = shaders.iter().map(|&(ty, _)| ty).collect(): Vec<_>;
+			link_program(program_handle, label, &stage_types, resources)
+		}
+	}
+
+	pub fn use_program(&self, program: Program) {
+		unsafe {
+			raw::UseProgram(program.0);
+		}
+	}
 
-			let mut status = 0;
-			raw::GetProgramiv(program_handle, raw::LINK_STATUS, &mut status);
+	/// Counts above `max_draw_mesh_tasks_count` are split over several draws.
+	/// Each draw starts where the last ended, so `gl_WorkGroupID` is the same as for a single draw.
+	pub fn draw_mesh_tasks(&self, state: &PipelineState, offset: u32, count: u32) {
+		self.set_pipeline_state(state);
 
-			if status == 0 {
-				let mut buf = [0u8; 1024];
-				let mut len = 0;
-				raw::GetProgramInfoLog(program_handle, buf.len() as _, &mut len, buf.as_mut_ptr() as _);
+		let max_count = self.capabilities.max_draw_mesh_tasks_count.max(1);
 
-				panic!("shader link failed: {}", std::str::from_utf8(&buf[..len as usize]).unwrap());
+		for first in (offset..offset + count).step_by(max_count as usize) {
+			unsafe {
+				raw::DrawMeshTasksNV(first, max_count.min(offset + count - first));
 			}
-
-			Program(program_handle)
 		}
 	}
 
-	pub fn use_program(&self, program: Program) {
+	/// Draws `instance_count` instances of `vertex_count` unindexed vertices, with no vertex attributes bound.
+	/// Vertex shaders are expected to fetch their data using `gl_VertexID` and `gl_InstanceID`.
+	pub fn draw_instanced(&self, state: &PipelineState, vertex_count: u32, instance_count: u32) {
+		self.set_pipeline_state(state);
+
 		unsafe {
-			raw::UseProgram(program.0);
+			raw::BindVertexArray(self.empty_vertex_array.0);
+			raw::DrawArraysInstanced(raw::TRIANGLES, 0, vertex_count as _, instance_count as _);
 		}
 	}
 
-	pub fn draw_mesh_tasks(&self, offset: u32, count: u32) {
+	/// Draws triangles using the u32 index buffer attached to `vertex_array`.
+	pub fn draw_indexed(&self, state: &PipelineState, vertex_array: VertexArray, index_count: u32) {
+		self.set_pipeline_state(state);
+
 		unsafe {
-			raw::DrawMeshTasksNV(offset, count);
+			raw::BindVertexArray(vertex_array.0);
+			raw::DrawElements(raw::TRIANGLES, index_count as _, raw::UNSIGNED_INT, std::ptr::null());
 		}
 	}
 
@@ -230,6 +371,41 @@ impl Context {
 			raw::DispatchCompute(x, y, z);
 		}
 	}
+
+	/// `barriers` is a combination of `raw::*_BARRIER_BIT`s
+	pub fn memory_barrier(&self, barriers: u32) {
+		unsafe {
+			raw::MemoryBarrier(barriers);
+		}
+	}
+}
+
+
+impl ShaderPermutations {
+	pub fn new(label: impl Into<String>, shaders: &[(u32, &'static str)], resources: &[ExpectedResource]) -> Self {
+		ShaderPermutations {
+			label: label.into(),
+			shaders: shaders.to_vec(),
+			resources: resources.to_vec(),
+			programs: HashMap::new(),
+		}
+	}
+
+	pub fn get(&mut self, gl_ctx: &impl Backend, defines: &Defines) -> Result<Program, Box<dyn Error>> {
+		if let Some(&program) = self.programs.get(defines) {
+			return Ok(program)
+		}
+
+		let define_list = defines.iter()
+			.map(|(name, value)| format!("{}={}", name, value))
+			.collect(): Vec<_>;
+
+		let label = format!("{} [{}]", self.label, define_list.join(", "));
+
+		let program = gl_ctx.new_shader(&label, &self.shaders, defines, &self.resources)?;
+		self.programs.insert(defines.clone(), program);
+		Ok(program)
+	}
 }
 
 
@@ -252,90 +428,106 @@ impl Buffer {
 			);
 		}
 	}
+
+	/// Uploads `data` laid out following std140 rules, for use as a uniform block.
+	pub fn upload_std140<T: GpuLayout>(&self, data: &[T], usage: BufferUsage) {
+		self.upload(&layout::to_bytes(data, Layout::Std140), usage);
+	}
+
+	/// Uploads `data` laid out following std430 rules, for use as a shader storage block.
+	pub fn upload_std430<T: GpuLayout>(&self, data: &[T], usage: BufferUsage) {
+		self.upload(&layout::to_bytes(data, Layout::Std430), usage);
+	}
 }
 
 
-impl Texture {
-	pub fn clear(&self) {
+impl VertexArray {
+	pub fn set_index_buffer(&self, buffer: Buffer) {
 		unsafe {
-			raw::ClearTexImage(self.0, 0, raw::RED, raw::FLOAT, &0.0f32 as *const f32 as _);
+			raw::VertexArrayElementBuffer(self.0, buffer.0);
 		}
 	}
+}
 
-	pub fn set_filter(&self, min_linear: bool, mag_linear: bool) {
-		let min = match min_linear {
-			true => raw::LINEAR,
-			false => raw::NEAREST,
-		};
 
-		let mag = match mag_linear {
-			true => raw::LINEAR,
-			false => raw::NEAREST,
-		};
+/// Deletes the shader and returns its info log if compilation or specialization failed.
+/// Errors refer to lines by source string number, so are followed by the file each number refers to.
+unsafe fn check_compile_status(shader_handle: u32, ty: u32, processed: &preprocessor::Processed) -> Result<(), Box<dyn Error>> {
+	let mut status = 0;
+	raw::GetShaderiv(shader_handle, raw::COMPILE_STATUS, &mut status);
 
-		unsafe {
-			raw::TextureParameteri(self.0, raw::TEXTURE_MIN_FILTER, min as _);
-			raw::TextureParameteri(self.0, raw::TEXTURE_MAG_FILTER, mag as _);
-		}
+	if status != 0 {
+		return Ok(())
 	}
 
-	pub fn set_wrap(&self, wrap: bool) {
-		let mode = match wrap {
-			true => raw::REPEAT,
-			false => raw::CLAMP_TO_EDGE,
-		};
+	let mut length = 0;
+	raw::GetShaderiv(shader_handle, raw::INFO_LOG_LENGTH, &mut length);
 
-		unsafe {
-			raw::TextureParameteri(self.0, raw::TEXTURE_WRAP_S, mode as _);
-			raw::TextureParameteri(self.0, raw::TEXTURE_WRAP_T, mode as _);
-		}
-	}
+	let mut buffer = vec![0u8; length.max(1) as usize];
+	raw::GetShaderInfoLog(
+		shader_handle,
+		length,
+		std::ptr::null_mut(),
+		buffer.as_mut_ptr() as *mut _
+	);
+
+	raw::DeleteShader(shader_handle);
+
+	let error = std::str::from_utf8(&buffer[..buffer.len()-1])?;
+	Err(format!("{} compile failed!\n{}\nSource strings: {}", shader_type_name(ty), error, processed.describe_files()).into())
 }
 
+/// Links a program with all stages attached, labels it, and checks its interface against `resources`.
+/// The program is deleted on failure.
+unsafe fn link_program(program_handle: u32, label: &str, stage_types: &[u32], resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+	raw::LinkProgram(program_handle);
+	check_program(program_handle, label, stage_types, resources)
+}
 
+/// Checks the result of linking or loading a program binary, then labels it and checks its interface.
+/// The program is deleted on failure.
+unsafe fn check_program(program_handle: u32, label: &str, stage_types: &[u32], resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+	let mut status = 0;
+	raw::GetProgramiv(program_handle, raw::LINK_STATUS, &mut status);
 
+	if status == 0 {
+		let mut buf = [0u8; 1024];
+		let mut len = 0;
+		raw::GetProgramInfoLog(program_handle, buf.len() as _, &mut len, buf.as_mut_ptr() as _);
 
+		raw::DeleteProgram(program_handle);
 
-extern "system" fn gl_message_callback(source: u32, ty: u32, _id: u32, severity: u32,
-	_length: i32, msg: *const i8, _ud: *mut std::ffi::c_void)
-{
-	let severity = match severity {
-		raw::DEBUG_SEVERITY_LOW => "low",
-		raw::DEBUG_SEVERITY_MEDIUM => "medium",
-		raw::DEBUG_SEVERITY_HIGH => "high",
-		raw::DEBUG_SEVERITY_NOTIFICATION => "notification",
-		_ => panic!("Unknown severity {}", severity),
-	};
-
-	let ty = match ty {
-		raw::DEBUG_TYPE_ERROR => "error",
-		raw::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behaviour",
-		raw::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behaviour",
-		raw::DEBUG_TYPE_PORTABILITY => "portability",
-		raw::DEBUG_TYPE_PERFORMANCE => "performance",
-		raw::DEBUG_TYPE_OTHER => "other",
-		_ => panic!("Unknown type {}", ty),
-	};
-
-	let source = match source {
-		raw::DEBUG_SOURCE_API => "api",
-		raw::DEBUG_SOURCE_WINDOW_SYSTEM => "window system",
-		raw::DEBUG_SOURCE_SHADER_COMPILER => "shader compiler",
-		raw::DEBUG_SOURCE_THIRD_PARTY => "third party",
-		raw::DEBUG_SOURCE_APPLICATION => "application",
-		raw::DEBUG_SOURCE_OTHER => "other",
-		_ => panic!("Unknown source {}", source),
-	};
-
-	eprintln!("GL ERROR!");
-	eprintln!("Source:   {}", source);
-	eprintln!("Severity: {}", severity);
-	eprintln!("Type:     {}", ty);
-
-	unsafe {
-		let msg = std::ffi::CStr::from_ptr(msg as _).to_str().unwrap();
-		eprintln!("Message: {}", msg);
+		return Err(format!("shader link failed: {}", std::str::from_utf8(&buf[..len as usize])?).into());
 	}
 
-	panic!("GL ERROR!");
-}
\ No newline at end of file
+	debug::set_label(raw::PROGRAM, program_handle, label);
+
+	let program = Program(program_handle);
+	let mismatches = program.interface().validate(resources);
+
+	if !mismatches.is_empty() {
+		raw::DeleteProgram(program_handle);
+
+		let stage_names = stage_types.iter()
+			.map(|&ty| shader_type_name(ty))
+			.collect(): Vec<_>;
+
+		return Err(interface::InterfaceError {
+			program: format!("{} ({})", label, stage_names.join(" + ")),
+			mismatches,
+		}.into());
+	}
+
+	Ok(program)
+}
+
+fn shader_type_name(ty: u32) -> &'static str {
+	match ty {
+		raw::VERTEX_SHADER => "vertex shader",
+		raw::FRAGMENT_SHADER => "fragment shader",
+		raw::COMPUTE_SHADER => "compute shader",
+		raw::TASK_SHADER_NV => "task shader",
+		raw::MESH_SHADER_NV => "mesh shader",
+		_ => "shader",
+	}
+}
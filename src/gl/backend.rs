@@ -0,0 +1,102 @@
+use std::error::Error;
+use common::math::Vec4;
+use super::*;
+
+
+/// The parts of `Context` that render subsystems use, so that they can be run against
+/// a `RecordingBackend` in tests instead of a real GL context.
+pub trait Backend {
+	fn capabilities(&self) -> &Capabilities;
+
+	fn new_shader(&self, label: &str, shaders: &[(u32, &str)], defines: &Defines, resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>>;
+	fn new_spirv_shader(&self, label: &str, shaders: &[(u32, &SpirvModule)], constants: &SpecializationConstants, resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>>;
+	fn use_program(&self, program: Program);
+
+	fn new_buffer(&self, label: &str) -> Buffer;
+	fn upload_buffer(&self, buffer: Buffer, data: &[u8], usage: BufferUsage);
+	fn bind_uniform_buffer(&self, binding: u32, buffer: Buffer);
+	fn bind_shader_storage_buffer(&self, binding: u32, buffer: Buffer);
+
+	fn new_vertex_array(&self, label: &str) -> VertexArray;
+	fn set_index_buffer(&self, vertex_array: VertexArray, buffer: Buffer);
+
+	fn new_texture(&self, label: &str, desc: &TextureDesc) -> Texture;
+	fn clear_texture(&self, texture: Texture, value: Vec4);
+	fn new_sampler(&self, desc: &SamplerDesc) -> Sampler;
+	fn bind_texture(&self, binding: u32, texture: Texture);
+	fn bind_sampler(&self, binding: u32, sampler: Option<Sampler>);
+	fn bind_image_rw(&self, binding: u32, texture: Texture);
+
+	fn draw_mesh_tasks(&self, state: &PipelineState, offset: u32, count: u32);
+	fn draw_instanced(&self, state: &PipelineState, vertex_count: u32, instance_count: u32);
+	fn draw_indexed(&self, state: &PipelineState, vertex_array: VertexArray, index_count: u32);
+	fn dispatch_compute(&self, x: u32, y: u32, z: u32);
+
+	/// `barriers` is a combination of `raw::*_BARRIER_BIT`s
+	fn memory_barrier(&self, barriers: u32);
+
+
+	fn upload<T: Copy>(&self, buffer: Buffer, data: &[T], usage: BufferUsage) where Self: Sized {
+		let bytes = unsafe {
+			std::slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * std::mem::size_of::<T>())
+		};
+
+		self.upload_buffer(buffer, bytes, usage);
+	}
+
+	/// Uploads `data` laid out following std140 rules, for use as a uniform block.
+	fn upload_std140<T: GpuLayout>(&self, buffer: Buffer, data: &[T], usage: BufferUsage) where Self: Sized {
+		self.upload_buffer(buffer, &layout::to_bytes(data, Layout::Std140), usage);
+	}
+
+	/// Uploads `data` laid out following std430 rules, for use as a shader storage block.
+	fn upload_std430<T: GpuLayout>(&self, buffer: Buffer, data: &[T], usage: BufferUsage) where Self: Sized {
+		self.upload_buffer(buffer, &layout::to_bytes(data, Layout::Std430), usage);
+	}
+}
+
+
+
+impl Backend for Context {
+	fn capabilities(&self) -> &Capabilities { Context::capabilities(self) }
+
+	fn new_shader(&self, label: &str, shaders: &[(u32, &str)], defines: &Defines, resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+		Context::new_shader(self, label, shaders, defines, resources)
+	}
+
+	fn new_spirv_shader(&self, label: &str, shaders: &[(u32, &SpirvModule)], constants: &SpecializationConstants, resources: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+		Context::new_spirv_shader(self, label, shaders, constants, resources)
+	}
+
+	fn use_program(&self, program: Program) { Context::use_program(self, program) }
+
+	fn new_buffer(&self, label: &str) -> Buffer { Context::new_buffer(self, label) }
+	fn upload_buffer(&self, buffer: Buffer, data: &[u8], usage: BufferUsage) { buffer.upload(data, usage) }
+	fn bind_uniform_buffer(&self, binding: u32, buffer: Buffer) { Context::bind_uniform_buffer(self, binding, buffer) }
+	fn bind_shader_storage_buffer(&self, binding: u32, buffer: Buffer) { Context::bind_shader_storage_buffer(self, binding, buffer) }
+
+	fn new_vertex_array(&self, label: &str) -> VertexArray { Context::new_vertex_array(self, label) }
+	fn set_index_buffer(&self, vertex_array: VertexArray, buffer: Buffer) { vertex_array.set_index_buffer(buffer) }
+
+	fn new_texture(&self, label: &str, desc: &TextureDesc) -> Texture { Context::new_texture(self, label, desc) }
+	fn clear_texture(&self, texture: Texture, value: Vec4) { texture.clear(value) }
+	fn new_sampler(&self, desc: &SamplerDesc) -> Sampler { Context::new_sampler(self, desc) }
+	fn bind_texture(&self, binding: u32, texture: Texture) { Context::bind_texture(self, binding, texture) }
+	fn bind_sampler(&self, binding: u32, sampler: Option<Sampler>) { Context::bind_sampler(self, binding, sampler) }
+	fn bind_image_rw(&self, binding: u32, texture: Texture) { Context::bind_image_rw(self, binding, texture) }
+
+	fn draw_mesh_tasks(&self, state: &PipelineState, offset: u32, count: u32) {
+		Context::draw_mesh_tasks(self, state, offset, count)
+	}
+
+	fn draw_instanced(&self, state: &PipelineState, vertex_count: u32, instance_count: u32) {
+		Context::draw_instanced(self, state, vertex_count, instance_count)
+	}
+
+	fn draw_indexed(&self, state: &PipelineState, vertex_array: VertexArray, index_count: u32) {
+		Context::draw_indexed(self, state, vertex_array, index_count)
+	}
+
+	fn dispatch_compute(&self, x: u32, y: u32, z: u32) { Context::dispatch_compute(self, x, y, z) }
+	fn memory_barrier(&self, barriers: u32) { Context::memory_barrier(self, barriers) }
+}
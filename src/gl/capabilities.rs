@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::fmt;
+use super::raw;
+
+
+/// Optional features that subsystems may depend on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Feature {
+	MeshShaders,
+	Spirv,
+	PipelineStatistics,
+}
+
+/// How geometry is submitted. Subsystems pick between shader sets based on this.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderPath {
+	MeshShaders,
+
+	/// Vertex and index buffers or instanced draws, for devices without mesh shaders such as llvmpipe
+	VertexPipeline,
+}
+
+/// Extensions and implementation limits of the current device, queried once at startup.
+/// The default has no extensions and all limits zeroed.
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+	pub vendor: String,
+	pub renderer: String,
+	pub version: String,
+	pub extensions: HashSet<String>,
+
+	pub mesh_shaders: bool,
+
+	/// Whether programs can be created from SPIR-V modules
+	pub spirv: bool,
+
+	/// Whether shader invocations and clipping can be counted with queries
+	pub pipeline_statistics: bool,
+
+	/// Mesh and task limits are zero if mesh shaders are unsupported
+	pub max_mesh_output_vertices: u32,
+	pub max_mesh_output_primitives: u32,
+	pub max_mesh_work_group_size: u32,
+	pub max_mesh_shared_memory_size: u32,
+	pub max_task_output_count: u32,
+	pub max_task_work_group_size: u32,
+	pub max_task_shared_memory_size: u32,
+
+	/// Most tasks a single `DrawMeshTasksNV` can launch
+	pub max_draw_mesh_tasks_count: u32,
+
+	pub max_compute_work_group_size: [u32; 3],
+	pub max_compute_work_group_invocations: u32,
+	pub max_compute_shared_memory_size: u32,
+
+	pub max_texture_size: u32,
+	pub max_3d_texture_size: u32,
+	pub max_array_texture_layers: u32,
+	pub max_color_attachments: u32,
+
+	pub max_shader_storage_block_size: u64,
+
+	/// Zero if the driver can't save and restore linked programs
+	pub num_program_binary_formats: u32,
+}
+
+#[derive(Debug)]
+pub struct UnsupportedFeature {
+	pub feature: Feature,
+	pub required_by: &'static str,
+}
+
+
+impl Capabilities {
+	pub(super) fn query() -> Capabilities {
+		let extensions = query_extensions();
+		let mesh_shaders = extensions.contains("GL_NV_mesh_shader");
+		let spirv = extensions.contains("GL_ARB_gl_spirv");
+		let pipeline_statistics = extensions.contains("GL_ARB_pipeline_statistics_query");
+
+		let mesh_limit = |name| match mesh_shaders {
+			true => get_u32(name),
+			false => 0,
+		};
+
+		let mesh_indexed_limit = |name| match mesh_shaders {
+			true => get_indexed_u32(name, 0),
+			false => 0,
+		};
+
+		Capabilities {
+			mesh_shaders,
+			spirv,
+			pipeline_statistics,
+
+			max_mesh_output_vertices: mesh_limit(raw::MAX_MESH_OUTPUT_VERTICES_NV),
+			max_mesh_output_primitives: mesh_limit(raw::MAX_MESH_OUTPUT_PRIMITIVES_NV),
+			max_mesh_work_group_size: mesh_indexed_limit(raw::MAX_MESH_WORK_GROUP_SIZE_NV),
+			max_mesh_shared_memory_size: mesh_limit(raw::MAX_MESH_TOTAL_MEMORY_SIZE_NV),
+			max_task_output_count: mesh_limit(raw::MAX_TASK_OUTPUT_COUNT_NV),
+			max_task_work_group_size: mesh_indexed_limit(raw::MAX_TASK_WORK_GROUP_SIZE_NV),
+			max_task_shared_memory_size: mesh_limit(raw::MAX_TASK_TOTAL_MEMORY_SIZE_NV),
+			max_draw_mesh_tasks_count: mesh_limit(raw::MAX_DRAW_MESH_TASKS_COUNT_NV),
+
+			max_compute_work_group_size: [
+				get_indexed_u32(raw::MAX_COMPUTE_WORK_GROUP_SIZE, 0),
+				get_indexed_u32(raw::MAX_COMPUTE_WORK_GROUP_SIZE, 1),
+				get_indexed_u32(raw::MAX_COMPUTE_WORK_GROUP_SIZE, 2),
+			],
+			max_compute_work_group_invocations: get_u32(raw::MAX_COMPUTE_WORK_GROUP_INVOCATIONS),
+			max_compute_shared_memory_size: get_u32(raw::MAX_COMPUTE_SHARED_MEMORY_SIZE),
+
+			max_texture_size: get_u32(raw::MAX_TEXTURE_SIZE),
+			max_3d_texture_size: get_u32(raw::MAX_3D_TEXTURE_SIZE),
+			max_array_texture_layers: get_u32(raw::MAX_ARRAY_TEXTURE_LAYERS),
+			max_color_attachments: get_u32(raw::MAX_COLOR_ATTACHMENTS),
+
+			max_shader_storage_block_size: get_u64(raw::MAX_SHADER_STORAGE_BLOCK_SIZE),
+
+			num_program_binary_formats: get_u32(raw::NUM_PROGRAM_BINARY_FORMATS),
+
+			vendor: get_string(raw::VENDOR),
+			renderer: get_string(raw::RENDERER),
+			version: get_string(raw::VERSION),
+			extensions,
+		}
+	}
+
+	pub fn has_extension(&self, name: &str) -> bool {
+		self.extensions.contains(name)
+	}
+
+	pub fn supports(&self, feature: Feature) -> bool {
+		match feature {
+			Feature::MeshShaders => self.mesh_shaders,
+			Feature::Spirv => self.spirv,
+			Feature::PipelineStatistics => self.pipeline_statistics,
+		}
+	}
+
+	pub fn render_path(&self) -> RenderPath {
+		match self.mesh_shaders {
+			true => RenderPath::MeshShaders,
+			false => RenderPath::VertexPipeline,
+		}
+	}
+
+	/// Fails with a descriptive error if `feature` is unsupported, rather than leaving GL to fail later.
+	pub fn require(&self, feature: Feature, required_by: &'static str) -> Result<(), UnsupportedFeature> {
+		match self.supports(feature) {
+			true => Ok(()),
+			false => Err(UnsupportedFeature { feature, required_by }),
+		}
+	}
+
+	/// Features that this device doesn't support.
+	pub fn unsupported_features(&self) -> Vec<Feature> {
+		[Feature::MeshShaders, Feature::Spirv, Feature::PipelineStatistics].iter()
+			.copied()
+			.filter(|&feature| !self.supports(feature))
+			.collect()
+	}
+}
+
+
+impl Feature {
+	pub fn name(self) -> &'static str {
+		match self {
+			Feature::MeshShaders => "mesh shaders (GL_NV_mesh_shader)",
+			Feature::Spirv => "SPIR-V shaders (GL_ARB_gl_spirv)",
+			Feature::PipelineStatistics => "pipeline statistics queries (GL_ARB_pipeline_statistics_query)",
+		}
+	}
+}
+
+impl fmt::Display for UnsupportedFeature {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} requires {}, which this device doesn't support", self.required_by, self.feature.name())
+	}
+}
+
+impl std::error::Error for UnsupportedFeature {}
+
+
+
+fn query_extensions() -> HashSet<String> {
+	let count = get_u32(raw::NUM_EXTENSIONS);
+
+	(0..count)
+		.map(|index| unsafe {
+			let name = raw::GetStringi(raw::EXTENSIONS, index);
+			std::ffi::CStr::from_ptr(name as _).to_string_lossy().into_owned()
+		})
+		.collect()
+}
+
+fn get_string(name: u32) -> String {
+	unsafe {
+		let value = raw::GetString(name);
+		if value.is_null() {
+			return String::new()
+		}
+
+		std::ffi::CStr::from_ptr(value as _).to_string_lossy().into_owned()
+	}
+}
+
+fn get_u32(name: u32) -> u32 {
+	let mut value = 0;
+	unsafe {
+		raw::GetIntegerv(name, &mut value);
+	}
+	value as u32
+}
+
+fn get_indexed_u32(name: u32, index: u32) -> u32 {
+	let mut value = 0;
+	unsafe {
+		raw::GetIntegeri_v(name, index, &mut value);
+	}
+	value as u32
+}
+
+fn get_u64(name: u32) -> u64 {
+	let mut value = 0;
+	unsafe {
+		raw::GetInteger64v(name, &mut value);
+	}
+	value as u64
+}
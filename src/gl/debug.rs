@@ -0,0 +1,304 @@
+use std::cell::Cell;
+use std::ffi::{c_void, CStr};
+use super::{raw, Context};
+
+
+/// What to do when the driver reports a debug message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugAction {
+	Ignore,
+	Log,
+
+	/// Log, then trap into an attached debugger. Panics instead on platforms without SIGTRAP
+	Break,
+
+	/// Log, then panic
+	Panic,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugSeverity {
+	High,
+	Medium,
+	Low,
+	Notification,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugType {
+	Error,
+	DeprecatedBehavior,
+	UndefinedBehavior,
+	Portability,
+	Performance,
+	Marker,
+
+	/// Messages generated by pushing and popping debug groups
+	Group,
+	Other,
+}
+
+
+/// Decides how each debug message is handled, based on its type and severity.
+/// Type overrides take priority over severity actions.
+#[derive(Copy, Clone, Debug)]
+pub struct DebugPolicy {
+	severity_actions: [DebugAction; 4],
+	type_overrides: [Option<DebugAction>; 8],
+}
+
+
+impl DebugPolicy {
+	pub const DEFAULT: DebugPolicy = DebugPolicy {
+		severity_actions: [
+			DebugAction::Panic, // High
+			DebugAction::Log, // Medium
+			DebugAction::Log, // Low
+			DebugAction::Ignore, // Notification
+		],
+
+		type_overrides: [
+			Some(DebugAction::Panic), // Error
+			None, // DeprecatedBehavior
+			None, // UndefinedBehavior
+			None, // Portability
+			Some(DebugAction::Ignore), // Performance
+			None, // Marker
+			Some(DebugAction::Ignore), // Group
+			None, // Other
+		],
+	};
+
+	pub fn with_severity(mut self, severity: DebugSeverity, action: DebugAction) -> Self {
+		self.severity_actions[severity as usize] = action;
+		self
+	}
+
+	/// Handles all messages of type `ty` with `action`, regardless of severity.
+	pub fn with_type(mut self, ty: DebugType, action: DebugAction) -> Self {
+		self.type_overrides[ty as usize] = Some(action);
+		self
+	}
+
+	pub fn action(&self, severity: DebugSeverity, ty: DebugType) -> DebugAction {
+		self.type_overrides[ty as usize]
+			.unwrap_or(self.severity_actions[severity as usize])
+	}
+}
+
+impl Default for DebugPolicy {
+	fn default() -> Self {
+		DebugPolicy::DEFAULT
+	}
+}
+
+
+
+impl Context {
+	/// Replaces the current debug policy. Messages the policy ignores are also disabled in the driver.
+	pub fn set_debug_policy(&self, policy: DebugPolicy) {
+		self.debug_policy.set(policy);
+
+		const SEVERITIES: [DebugSeverity; 4] = [DebugSeverity::High, DebugSeverity::Medium, DebugSeverity::Low, DebugSeverity::Notification];
+		const TYPES: [DebugType; 8] = [
+			DebugType::Error, DebugType::DeprecatedBehavior, DebugType::UndefinedBehavior, DebugType::Portability,
+			DebugType::Performance, DebugType::Marker, DebugType::Group, DebugType::Other,
+		];
+
+		for &severity in SEVERITIES.iter() {
+			for &ty in TYPES.iter() {
+				let enabled = policy.action(severity, ty) != DebugAction::Ignore;
+
+				for &raw_type in ty.to_raw() {
+					unsafe {
+						raw::DebugMessageControl(
+							raw::DONT_CARE,
+							raw_type,
+							severity.to_raw(),
+							0, std::ptr::null(),
+							enabled as _
+						);
+					}
+				}
+			}
+		}
+	}
+}
+
+
+/// Opens a named group, shown as a region in captures from external GPU debuggers.
+pub fn push_group(name: &str) {
+	unsafe {
+		raw::PushDebugGroup(raw::DEBUG_SOURCE_APPLICATION, 0, name.len() as _, name.as_ptr() as _);
+	}
+}
+
+pub fn pop_group() {
+	unsafe {
+		raw::PopDebugGroup();
+	}
+}
+
+
+/// Attaches a readable name to a GL object, for debug messages and external GPU debuggers.
+/// `identifier` is the object namespace, e.g. `raw::BUFFER`.
+pub(super) fn set_label(identifier: u32, handle: u32, label: &str) {
+	unsafe {
+		raw::ObjectLabel(identifier, handle, label.len() as _, label.as_ptr() as _);
+	}
+}
+
+
+/// Registers the message callback. `policy` is read on each message, and must outlive the GL context.
+pub(super) unsafe fn install_callback(policy: &Cell<DebugPolicy>) {
+	raw::DebugMessageCallback(Some(message_callback), policy as *const _ as *const c_void);
+	raw::Enable(raw::DEBUG_OUTPUT);
+	raw::Enable(raw::DEBUG_OUTPUT_SYNCHRONOUS);
+}
+
+
+
+impl DebugSeverity {
+	fn from_raw(severity: u32) -> DebugSeverity {
+		match severity {
+			raw::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+			raw::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+			raw::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+			raw::DEBUG_SEVERITY_NOTIFICATION => DebugSeverity::Notification,
+
+			// Err on the side of noise for anything newer than we know about
+			_ => DebugSeverity::High,
+		}
+	}
+
+	fn to_raw(self) -> u32 {
+		match self {
+			DebugSeverity::High => raw::DEBUG_SEVERITY_HIGH,
+			DebugSeverity::Medium => raw::DEBUG_SEVERITY_MEDIUM,
+			DebugSeverity::Low => raw::DEBUG_SEVERITY_LOW,
+			DebugSeverity::Notification => raw::DEBUG_SEVERITY_NOTIFICATION,
+		}
+	}
+
+	fn log_level(self) -> log::Level {
+		match self {
+			DebugSeverity::High => log::Level::Error,
+			DebugSeverity::Medium => log::Level::Warn,
+			DebugSeverity::Low => log::Level::Info,
+			DebugSeverity::Notification => log::Level::Debug,
+		}
+	}
+}
+
+impl DebugType {
+	fn from_raw(ty: u32) -> DebugType {
+		match ty {
+			raw::DEBUG_TYPE_ERROR => DebugType::Error,
+			raw::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+			raw::DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::UndefinedBehavior,
+			raw::DEBUG_TYPE_PORTABILITY => DebugType::Portability,
+			raw::DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
+			raw::DEBUG_TYPE_MARKER => DebugType::Marker,
+			raw::DEBUG_TYPE_PUSH_GROUP | raw::DEBUG_TYPE_POP_GROUP => DebugType::Group,
+			_ => DebugType::Other,
+		}
+	}
+
+	fn to_raw(self) -> &'static [u32] {
+		match self {
+			DebugType::Error => &[raw::DEBUG_TYPE_ERROR],
+			DebugType::DeprecatedBehavior => &[raw::DEBUG_TYPE_DEPRECATED_BEHAVIOR],
+			DebugType::UndefinedBehavior => &[raw::DEBUG_TYPE_UNDEFINED_BEHAVIOR],
+			DebugType::Portability => &[raw::DEBUG_TYPE_PORTABILITY],
+			DebugType::Performance => &[raw::DEBUG_TYPE_PERFORMANCE],
+			DebugType::Marker => &[raw::DEBUG_TYPE_MARKER],
+			DebugType::Group => &[raw::DEBUG_TYPE_PUSH_GROUP, raw::DEBUG_TYPE_POP_GROUP],
+			DebugType::Other => &[raw::DEBUG_TYPE_OTHER],
+		}
+	}
+}
+
+
+fn source_name(source: u32) -> &'static str {
+	match source {
+		raw::DEBUG_SOURCE_API => "api",
+		raw::DEBUG_SOURCE_WINDOW_SYSTEM => "window system",
+		raw::DEBUG_SOURCE_SHADER_COMPILER => "shader compiler",
+		raw::DEBUG_SOURCE_THIRD_PARTY => "third party",
+		raw::DEBUG_SOURCE_APPLICATION => "application",
+		_ => "other",
+	}
+}
+
+
+extern "system" fn message_callback(source: u32, ty: u32, id: u32, severity: u32,
+	_length: i32, msg: *const i8, user_param: *mut c_void)
+{
+	let policy = unsafe { &*(user_param as *const Cell<DebugPolicy>) }.get();
+
+	let severity = DebugSeverity::from_raw(severity);
+	let ty = DebugType::from_raw(ty);
+
+	let action = policy.action(severity, ty);
+	if action == DebugAction::Ignore {
+		return
+	}
+
+	let msg = unsafe { CStr::from_ptr(msg as _) }.to_string_lossy();
+
+	log::log!(target: "gl", severity.log_level(), "[{} {:?} #{}] {}", source_name(source), ty, id, msg);
+
+	match action {
+		DebugAction::Ignore | DebugAction::Log => {}
+		DebugAction::Break => break_into_debugger(&msg),
+		DebugAction::Panic => panic!("GL {:?} ({:?} severity): {}", ty, severity, msg),
+	}
+}
+
+/// Raises SIGTRAP, which stops in an attached debugger and otherwise terminates the process.
+#[cfg(unix)]
+fn break_into_debugger(_msg: &str) {
+	unsafe {
+		libc::raise(libc::SIGTRAP);
+	}
+}
+
+#[cfg(not(unix))]
+fn break_into_debugger(msg: &str) {
+	panic!("GL debug break: {}", msg);
+}
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn default_policy() {
+		let policy = DebugPolicy::default();
+
+		assert_eq!(policy.action(DebugSeverity::High, DebugType::Error), DebugAction::Panic);
+		assert_eq!(policy.action(DebugSeverity::Medium, DebugType::UndefinedBehavior), DebugAction::Log);
+		assert_eq!(policy.action(DebugSeverity::Notification, DebugType::Other), DebugAction::Ignore);
+
+		// Type overrides win over severity
+		assert_eq!(policy.action(DebugSeverity::High, DebugType::Performance), DebugAction::Ignore);
+		assert_eq!(policy.action(DebugSeverity::Low, DebugType::Error), DebugAction::Panic);
+	}
+
+	#[test]
+	fn overrides_replace_defaults() {
+		let policy = DebugPolicy::default()
+			.with_type(DebugType::Error, DebugAction::Break)
+			.with_severity(DebugSeverity::Low, DebugAction::Ignore);
+
+		assert_eq!(policy.action(DebugSeverity::High, DebugType::Error), DebugAction::Break);
+		assert_eq!(policy.action(DebugSeverity::Low, DebugType::Error), DebugAction::Break);
+		assert_eq!(policy.action(DebugSeverity::Low, DebugType::Other), DebugAction::Ignore);
+		assert_eq!(policy.action(DebugSeverity::Notification, DebugType::Other), DebugAction::Ignore);
+
+		// Everything else is left as it was
+		assert_eq!(policy.action(DebugSeverity::High, DebugType::Other), DebugAction::Panic);
+		assert_eq!(policy.action(DebugSeverity::Medium, DebugType::Portability), DebugAction::Log);
+	}
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+use super::{raw, debug, Context, Texture, TextureDesc, TextureFormat};
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Framebuffer (u32);
+
+impl Framebuffer {
+	pub const DEFAULT: Framebuffer = Framebuffer(0);
+}
+
+
+#[derive(Clone, Debug)]
+pub struct RenderTargetDesc {
+	/// Formats of each colour attachment. May be empty for depth only targets.
+	pub color_formats: Vec<TextureFormat>,
+
+	/// Depth or depth-stencil format
+	pub depth_format: Option<TextureFormat>,
+
+	/// 1 for a regular target, or more for a multisampled target that must be resolved before sampling
+	pub samples: u32,
+}
+
+/// An offscreen framebuffer and the textures attached to it.
+pub struct RenderTarget {
+	label: String,
+	desc: RenderTargetDesc,
+	framebuffer: Framebuffer,
+	color_attachments: Vec<Texture>,
+	depth_attachment: Option<Texture>,
+	size: (u32, u32),
+}
+
+
+#[derive(Debug)]
+pub enum FramebufferError {
+	TooManyColorAttachments { requested: usize, max: usize },
+	InvalidSize(u32, u32),
+	Incomplete(u32),
+}
+
+
+
+impl RenderTarget {
+	/// `label` names the framebuffer and its attachments in debug messages and external GPU debuggers.
+	pub fn new(gl_ctx: &Context, label: impl Into<String>, desc: RenderTargetDesc, width: u32, height: u32) -> Result<RenderTarget, FramebufferError> {
+		let label = label.into();
+		let max_color_attachments = gl_ctx.capabilities().max_color_attachments;
+		let mut framebuffer = 0;
+
+		if desc.color_formats.len() > max_color_attachments as usize {
+			return Err(FramebufferError::TooManyColorAttachments {
+				requested: desc.color_formats.len(),
+				max: max_color_attachments as usize,
+			})
+		}
+
+		unsafe {
+			raw::CreateFramebuffers(1, &mut framebuffer);
+		}
+
+		debug::set_label(raw::FRAMEBUFFER, framebuffer, &label);
+
+		let mut target = RenderTarget {
+			label,
+			desc,
+			framebuffer: Framebuffer(framebuffer),
+			color_attachments: Vec::new(),
+			depth_attachment: None,
+			size: (0, 0),
+		};
+
+		target.resize(width, height)?;
+		Ok(target)
+	}
+
+	pub fn size(&self) -> (u32, u32) { self.size }
+	pub fn framebuffer(&self) -> Framebuffer { self.framebuffer }
+	pub fn color_attachment(&self, index: usize) -> Texture { self.color_attachments[index] }
+	pub fn depth_attachment(&self) -> Option<Texture> { self.depth_attachment }
+
+	/// Recreates all attachments at the new size. Does nothing if the size hasn't changed.
+	pub fn resize(&mut self, width: u32, height: u32) -> Result<(), FramebufferError> {
+		if width == 0 || height == 0 {
+			return Err(FramebufferError::InvalidSize(width, height))
+		}
+
+		if self.size == (width, height) {
+			return Ok(())
+		}
+
+		self.delete_attachments();
+
+		let fbo = self.framebuffer.0;
+		let samples = self.desc.samples;
+
+		unsafe {
+			for (index, &format) in self.desc.color_formats.iter().enumerate() {
+				let label = format!("{} colour {}", self.label, index);
+				let texture = new_attachment_texture(&label, format, samples, width, height);
+				raw::NamedFramebufferTexture(fbo, raw::COLOR_ATTACHMENT0 + index as u32, texture.handle(), 0);
+				self.color_attachments.push(texture);
+			}
+
+			if let Some(format) = self.desc.depth_format {
+				let attachment_point = match format.has_stencil() {
+					true => raw::DEPTH_STENCIL_ATTACHMENT,
+					false => raw::DEPTH_ATTACHMENT,
+				};
+
+				let label = format!("{} depth", self.label);
+				let texture = new_attachment_texture(&label, format, samples, width, height);
+				raw::NamedFramebufferTexture(fbo, attachment_point, texture.handle(), 0);
+				self.depth_attachment = Some(texture);
+			}
+
+			if self.color_attachments.is_empty() {
+				raw::NamedFramebufferDrawBuffer(fbo, raw::NONE);
+				raw::NamedFramebufferReadBuffer(fbo, raw::NONE);
+			} else {
+				let draw_buffers = (0..self.color_attachments.len() as u32)
+					.map(|index| raw::COLOR_ATTACHMENT0 + index)
+					.collect(): Vec<_>;
+
+				raw::NamedFramebufferDrawBuffers(fbo, draw_buffers.len() as _, draw_buffers.as_ptr());
+				raw::NamedFramebufferReadBuffer(fbo, raw::COLOR_ATTACHMENT0);
+			}
+
+			let status = raw::CheckNamedFramebufferStatus(fbo, raw::FRAMEBUFFER);
+			if status != raw::FRAMEBUFFER_COMPLETE {
+				return Err(FramebufferError::Incomplete(status))
+			}
+		}
+
+		self.size = (width, height);
+		Ok(())
+	}
+
+	/// Binds the target for drawing, and sets the viewport to cover it.
+	pub fn bind(&self, gl_ctx: &Context) {
+		gl_ctx.bind_framebuffer(self.framebuffer, self.size);
+	}
+
+	/// Copies the first colour attachment into `dst`, scaling if sizes differ.
+	pub fn blit_color(&self, dst: Framebuffer, dst_size: (u32, u32)) {
+		let filter = match self.size == dst_size {
+			true => raw::NEAREST,
+			false => raw::LINEAR,
+		};
+
+		blit(self.framebuffer, self.size, dst, dst_size, raw::COLOR_BUFFER_BIT, filter);
+	}
+
+	/// Resolves a multisampled target into a single sampled target of the same size.
+	pub fn resolve(&self, dst: &RenderTarget) {
+		assert_eq!(self.size, dst.size, "Resolve requires targets of the same size");
+
+		let mut mask = 0;
+		if !self.color_attachments.is_empty() && !dst.color_attachments.is_empty() {
+			mask |= raw::COLOR_BUFFER_BIT;
+		}
+
+		if self.depth_attachment.is_some() && dst.depth_attachment.is_some() {
+			mask |= raw::DEPTH_BUFFER_BIT;
+		}
+
+		blit(self.framebuffer, self.size, dst.framebuffer, dst.size, mask, raw::NEAREST);
+	}
+
+	fn delete_attachments(&mut self) {
+		for texture in self.color_attachments.drain(..).chain(self.depth_attachment.take()) {
+			texture.delete();
+		}
+	}
+}
+
+impl Drop for RenderTarget {
+	fn drop(&mut self) {
+		self.delete_attachments();
+
+		unsafe {
+			raw::DeleteFramebuffers(1, &self.framebuffer.0);
+		}
+	}
+}
+
+
+
+impl Context {
+	/// Binds `framebuffer` for drawing and sets the viewport to cover `size`.
+	pub fn bind_framebuffer(&self, framebuffer: Framebuffer, size: (u32, u32)) {
+		unsafe {
+			raw::BindFramebuffer(raw::FRAMEBUFFER, framebuffer.0);
+			raw::Viewport(0, 0, size.0 as _, size.1 as _);
+		}
+	}
+}
+
+
+pub fn blit(src: Framebuffer, src_size: (u32, u32), dst: Framebuffer, dst_size: (u32, u32), mask: u32, filter: u32) {
+	unsafe {
+		raw::BlitNamedFramebuffer(
+			src.0, dst.0,
+			0, 0, src_size.0 as _, src_size.1 as _,
+			0, 0, dst_size.0 as _, dst_size.1 as _,
+			mask, filter
+		);
+	}
+}
+
+
+fn new_attachment_texture(label: &str, format: TextureFormat, samples: u32, width: u32, height: u32) -> Texture {
+	let texture = Texture::new(label, &TextureDesc::new_2d(width, height, format).with_samples(samples));
+
+	if samples <= 1 {
+		texture.set_wrap(false);
+	}
+
+	texture
+}
+
+
+
+impl fmt::Display for FramebufferError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			FramebufferError::TooManyColorAttachments { requested, max } =>
+				write!(f, "Render target requested {} colour attachments, but only {} are supported", requested, max),
+
+			FramebufferError::InvalidSize(w, h) =>
+				write!(f, "Render target can't be created with size {}x{}", w, h),
+
+			FramebufferError::Incomplete(status) => {
+				let reason = match status {
+					raw::FRAMEBUFFER_UNDEFINED => "default framebuffer doesn't exist",
+					raw::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "an attachment is incomplete or has an unrenderable format",
+					raw::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "no attachments",
+					raw::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "a draw buffer refers to a missing attachment",
+					raw::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "the read buffer refers to a missing attachment",
+					raw::FRAMEBUFFER_UNSUPPORTED => "the combination of attachment formats is unsupported",
+					raw::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "attachments have mismatched sample counts",
+					raw::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "attachments have mismatched layer targets",
+					_ => "unknown status",
+				};
+
+				write!(f, "Framebuffer incomplete: {} (0x{:X})", reason, status)
+			}
+		}
+	}
+}
+
+impl std::error::Error for FramebufferError {}
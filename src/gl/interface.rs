@@ -0,0 +1,424 @@
+use std::fmt;
+use super::{raw, Program};
+use super::layout::FieldLayout;
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+	UniformBlock,
+	StorageBlock,
+	Sampler,
+	Image,
+}
+
+
+/// The active resources of a linked program, as reported by the driver.
+#[derive(Clone, Debug)]
+pub struct ProgramInterface {
+	pub uniform_blocks: Vec<BlockResource>,
+	pub storage_blocks: Vec<BlockResource>,
+	pub samplers: Vec<OpaqueResource>,
+	pub images: Vec<OpaqueResource>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockResource {
+	pub name: String,
+	pub binding: u32,
+
+	/// Minimum buffer size required by the block. Unsized arrays count as having one element.
+	pub size: usize,
+	pub members: Vec<BlockMember>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockMember {
+	pub name: String,
+	pub offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct OpaqueResource {
+	pub name: String,
+	pub unit: u32,
+}
+
+
+/// A resource a program is expected to declare, and how the rust side expects it to be laid out.
+#[derive(Copy, Clone, Debug)]
+pub struct ExpectedResource {
+	pub kind: ResourceKind,
+	pub name: &'static str,
+	pub binding: u32,
+
+	/// Size of the data bound from the rust side. For blocks ending in an unsized array
+	/// this should be the size of the fixed part plus one element.
+	pub size: Option<usize>,
+
+	/// Field offsets of the rust type, from `GpuLayout::FIELDS_STD140` or `FIELDS_STD430`.
+	/// Fields are matched to block members by name, ignoring any `u_`, `s_` or `g_` prefix in glsl.
+	/// For blocks holding a single unsized array, these are the fields of the element type.
+	pub members: &'static [FieldLayout],
+}
+
+
+#[derive(Debug)]
+pub enum Mismatch {
+	Missing { kind: ResourceKind, name: &'static str },
+	Binding { kind: ResourceKind, name: &'static str, expected: u32, actual: u32 },
+	Size { name: &'static str, expected: usize, actual: usize },
+	Offset { name: &'static str, member: String, expected: usize, actual: usize },
+}
+
+#[derive(Debug)]
+pub struct InterfaceError {
+	pub program: String,
+	pub mismatches: Vec<Mismatch>,
+}
+
+
+
+impl ExpectedResource {
+	pub const fn uniform_block(name: &'static str, binding: u32, size: usize) -> Self {
+		ExpectedResource { kind: ResourceKind::UniformBlock, name, binding, size: Some(size), members: &[] }
+	}
+
+	pub const fn storage_block(name: &'static str, binding: u32, size: usize) -> Self {
+		ExpectedResource { kind: ResourceKind::StorageBlock, name, binding, size: Some(size), members: &[] }
+	}
+
+	pub const fn sampler(name: &'static str, unit: u32) -> Self {
+		ExpectedResource { kind: ResourceKind::Sampler, name, binding: unit, size: None, members: &[] }
+	}
+
+	pub const fn image(name: &'static str, unit: u32) -> Self {
+		ExpectedResource { kind: ResourceKind::Image, name, binding: unit, size: None, members: &[] }
+	}
+
+	pub const fn with_members(self, members: &'static [FieldLayout]) -> Self {
+		ExpectedResource { members, ..self }
+	}
+}
+
+
+impl ProgramInterface {
+	pub fn block(&self, kind: ResourceKind, name: &str) -> Option<&BlockResource> {
+		let blocks = match kind {
+			ResourceKind::UniformBlock => &self.uniform_blocks,
+			ResourceKind::StorageBlock => &self.storage_blocks,
+			_ => return None,
+		};
+
+		blocks.iter().find(|b| b.name == name)
+	}
+
+	pub fn opaque(&self, kind: ResourceKind, name: &str) -> Option<&OpaqueResource> {
+		let resources = match kind {
+			ResourceKind::Sampler => &self.samplers,
+			ResourceKind::Image => &self.images,
+			_ => return None,
+		};
+
+		resources.iter().find(|r| r.name == name)
+	}
+
+	pub fn validate(&self, expected: &[ExpectedResource]) -> Vec<Mismatch> {
+		let mut mismatches = Vec::new();
+
+		for resource in expected {
+			let ExpectedResource { kind, name, .. } = *resource;
+
+			let (binding, size, members) = match kind {
+				ResourceKind::UniformBlock | ResourceKind::StorageBlock => match self.block(kind, name) {
+					Some(block) => (block.binding, Some(block.size), block.members.as_slice()),
+					None => {
+						mismatches.push(Mismatch::Missing { kind, name });
+						continue
+					}
+				}
+
+				ResourceKind::Sampler | ResourceKind::Image => match self.opaque(kind, name) {
+					Some(opaque) => (opaque.unit, None, &[][..]),
+					None => {
+						mismatches.push(Mismatch::Missing { kind, name });
+						continue
+					}
+				}
+			};
+
+			if binding != resource.binding {
+				mismatches.push(Mismatch::Binding { kind, name, expected: resource.binding, actual: binding });
+			}
+
+			// The rust side may pad its structs out further than glsl does, but never less
+			if let (Some(expected), Some(actual)) = (resource.size, size) {
+				if expected < actual {
+					mismatches.push(Mismatch::Size { name, expected, actual });
+				}
+			}
+
+			for member in members {
+				let field = member_field_name(name, &member.name)
+					.and_then(|field_name| resource.members.iter().find(|field| field.name == field_name));
+
+				if let Some(field) = field {
+					if field.offset != member.offset {
+						mismatches.push(Mismatch::Offset { name, member: member.name.clone(), expected: field.offset, actual: member.offset });
+					}
+				}
+			}
+		}
+
+		mismatches
+	}
+}
+
+
+impl Program {
+	pub fn interface(&self) -> ProgramInterface {
+		unsafe {
+			let (samplers, images) = query_opaque_uniforms(self.0);
+
+			ProgramInterface {
+				uniform_blocks: query_blocks(self.0, raw::UNIFORM_BLOCK, raw::UNIFORM),
+				storage_blocks: query_blocks(self.0, raw::SHADER_STORAGE_BLOCK, raw::BUFFER_VARIABLE),
+				samplers,
+				images,
+			}
+		}
+	}
+}
+
+
+
+/// The name of the rust field a block member corresponds to, if it is a direct field of the block
+/// or of the element type of an array the block holds. Members of nested structs aren't checked.
+fn member_field_name<'a>(block_name: &str, member_name: &'a str) -> Option<&'a str> {
+	// Members of blocks with an instance name are prefixed with the block name
+	let mut path = member_name.split('.').collect(): Vec<_>;
+	if path.len() > 1 && path[0] == block_name {
+		path.remove(0);
+	}
+
+	let field = match path[..] {
+		[field] => field,
+		[array, field] if array.ends_with("[0]") => field,
+		_ => return None,
+	};
+
+	let field = field.split('[').next().unwrap_or(field);
+
+	Some(["u_", "s_", "g_"].iter()
+		.find_map(|prefix| field.strip_prefix(prefix))
+		.unwrap_or(field))
+}
+
+unsafe fn active_resources(program: u32, interface: u32) -> u32 {
+	let mut count = 0;
+	raw::GetProgramInterfaceiv(program, interface, raw::ACTIVE_RESOURCES, &mut count);
+	count as u32
+}
+
+unsafe fn resource_props(program: u32, interface: u32, index: u32, props: &[u32]) -> Vec<i32> {
+	let mut values = vec![0; props.len()];
+	raw::GetProgramResourceiv(program, interface, index,
+		props.len() as _, props.as_ptr(),
+		values.len() as _, std::ptr::null_mut(), values.as_mut_ptr());
+	values
+}
+
+unsafe fn resource_name(program: u32, interface: u32, index: u32) -> String {
+	let length = resource_props(program, interface, index, &[raw::NAME_LENGTH])[0];
+
+	let mut buffer = vec![0u8; length as usize];
+	raw::GetProgramResourceName(program, interface, index, length, std::ptr::null_mut(), buffer.as_mut_ptr() as *mut _);
+
+	// Strip null terminator
+	buffer.pop();
+	String::from_utf8_lossy(&buffer).into_owned()
+}
+
+unsafe fn query_blocks(program: u32, block_interface: u32, variable_interface: u32) -> Vec<BlockResource> {
+	(0..active_resources(program, block_interface))
+		.map(|index| {
+			let props = resource_props(program, block_interface, index,
+				&[raw::BUFFER_BINDING, raw::BUFFER_DATA_SIZE, raw::NUM_ACTIVE_VARIABLES]);
+
+			let (binding, size, num_variables) = (props[0], props[1], props[2]);
+
+			let mut variable_indices = vec![0; num_variables as usize];
+			raw::GetProgramResourceiv(program, block_interface, index,
+				1, &raw::ACTIVE_VARIABLES,
+				num_variables, std::ptr::null_mut(), variable_indices.as_mut_ptr());
+
+			let mut members = variable_indices.into_iter()
+				.map(|variable| BlockMember {
+					name: resource_name(program, variable_interface, variable as u32),
+					offset: resource_props(program, variable_interface, variable as u32, &[raw::OFFSET])[0] as usize,
+				})
+				.collect(): Vec<_>;
+
+			members.sort_by_key(|m| m.offset);
+
+			BlockResource {
+				name: resource_name(program, block_interface, index),
+				binding: binding as u32,
+				size: size as usize,
+				members,
+			}
+		})
+		.collect()
+}
+
+unsafe fn query_opaque_uniforms(program: u32) -> (Vec<OpaqueResource>, Vec<OpaqueResource>) {
+	let mut samplers = Vec::new();
+	let mut images = Vec::new();
+
+	for index in 0..active_resources(program, raw::UNIFORM) {
+		let props = resource_props(program, raw::UNIFORM, index, &[raw::TYPE, raw::BLOCK_INDEX, raw::LOCATION]);
+		let (ty, block_index, location) = (props[0] as u32, props[1], props[2]);
+
+		// Members of uniform blocks are handled by query_blocks
+		if block_index != -1 { continue }
+
+		let list = match opaque_kind(ty) {
+			Some(ResourceKind::Sampler) => &mut samplers,
+			Some(ResourceKind::Image) => &mut images,
+			_ => continue,
+		};
+
+		let mut unit = 0;
+		raw::GetUniformiv(program, location, &mut unit);
+
+		list.push(OpaqueResource {
+			name: resource_name(program, raw::UNIFORM, index),
+			unit: unit as u32,
+		});
+	}
+
+	(samplers, images)
+}
+
+fn opaque_kind(ty: u32) -> Option<ResourceKind> {
+	match ty {
+		raw::SAMPLER_1D | raw::SAMPLER_2D | raw::SAMPLER_3D | raw::SAMPLER_CUBE
+		| raw::SAMPLER_1D_ARRAY | raw::SAMPLER_2D_ARRAY | raw::SAMPLER_CUBE_MAP_ARRAY
+		| raw::SAMPLER_2D_SHADOW | raw::SAMPLER_2D_ARRAY_SHADOW | raw::SAMPLER_CUBE_SHADOW
+		| raw::SAMPLER_2D_MULTISAMPLE | raw::SAMPLER_BUFFER
+		| raw::INT_SAMPLER_2D | raw::INT_SAMPLER_3D | raw::INT_SAMPLER_2D_ARRAY
+		| raw::UNSIGNED_INT_SAMPLER_2D | raw::UNSIGNED_INT_SAMPLER_3D | raw::UNSIGNED_INT_SAMPLER_2D_ARRAY
+			=> Some(ResourceKind::Sampler),
+
+		raw::IMAGE_1D | raw::IMAGE_2D | raw::IMAGE_3D | raw::IMAGE_CUBE
+		| raw::IMAGE_2D_ARRAY | raw::IMAGE_BUFFER
+		| raw::INT_IMAGE_2D | raw::INT_IMAGE_3D | raw::INT_IMAGE_2D_ARRAY
+		| raw::UNSIGNED_INT_IMAGE_2D | raw::UNSIGNED_INT_IMAGE_3D | raw::UNSIGNED_INT_IMAGE_2D_ARRAY
+			=> Some(ResourceKind::Image),
+
+		_ => None,
+	}
+}
+
+
+
+impl fmt::Display for ResourceKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			ResourceKind::UniformBlock => "uniform block",
+			ResourceKind::StorageBlock => "shader storage block",
+			ResourceKind::Sampler => "sampler",
+			ResourceKind::Image => "image",
+		};
+
+		f.write_str(name)
+	}
+}
+
+impl fmt::Display for Mismatch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Mismatch::Missing { kind, name } =>
+				write!(f, "{} '{}' is not declared or not active", kind, name),
+			Mismatch::Binding { kind, name, expected, actual } =>
+				write!(f, "{} '{}' is bound to {} in glsl, but rust expects {}", kind, name, actual, expected),
+			Mismatch::Size { name, expected, actual } =>
+				write!(f, "'{}' requires {} bytes in glsl, but the rust type is only {} bytes", name, actual, expected),
+			Mismatch::Offset { name, member, expected, actual } =>
+				write!(f, "'{}' member '{}' is at offset {} in glsl, but {} in the rust type", name, member, actual, expected),
+		}
+	}
+}
+
+impl fmt::Display for InterfaceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Program interface mismatch in {}:", self.program)?;
+
+		for mismatch in self.mismatches.iter() {
+			write!(f, "\n\t{}", mismatch)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl std::error::Error for InterfaceError {}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::gl::GpuLayout;
+	use common::math::*;
+
+	#[derive(GpuLayout)]
+	struct Item {
+		pos: Vec3,
+		size: f32,
+	}
+
+	fn block(name: &str, members: &[(&str, usize)]) -> BlockResource {
+		BlockResource {
+			name: name.into(),
+			binding: 0,
+			size: 16,
+			members: members.iter()
+				.map(|&(name, offset)| BlockMember { name: name.into(), offset })
+				.collect(),
+		}
+	}
+
+	fn validate(block: BlockResource) -> Vec<Mismatch> {
+		let interface = ProgramInterface {
+			uniform_blocks: Vec::new(),
+			storage_blocks: vec![block],
+			samplers: Vec::new(),
+			images: Vec::new(),
+		};
+
+		let expected = ExpectedResource::storage_block("ItemData", 0, Item::STD430.size).with_members(Item::FIELDS_STD430);
+		interface.validate(&[expected])
+	}
+
+	#[test]
+	fn matches_members_to_fields_by_name() {
+		assert!(validate(block("ItemData", &[("s_items[0].pos", 0), ("s_items[0].size", 12)])).is_empty());
+		assert!(validate(block("ItemData", &[("u_pos", 0), ("ItemData.u_size", 12)])).is_empty());
+
+		// Inactive members and members without a matching field are skipped
+		assert!(validate(block("ItemData", &[("s_items[0].size", 12), ("s_items[0].other", 0)])).is_empty());
+	}
+
+	#[test]
+	fn reports_misplaced_members() {
+		// Large enough, but with the fields swapped
+		let mismatches = validate(block("ItemData", &[("s_items[0].size", 0), ("s_items[0].pos", 4)]));
+
+		assert!(matches!(mismatches[..], [
+			Mismatch::Offset { expected: 12, actual: 0, .. },
+			Mismatch::Offset { expected: 0, actual: 4, .. },
+		]));
+
+		assert_eq!(mismatches[0].to_string(), "'ItemData' member 's_items[0].size' is at offset 0 in glsl, but 12 in the rust type");
+	}
+}
@@ -0,0 +1,237 @@
+use common::math::*;
+
+pub use layout_derive::GpuLayout;
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+	Std140,
+	Std430,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TypeLayout {
+	pub align: usize,
+	pub size: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FieldLayout {
+	pub name: &'static str,
+	pub offset: usize,
+}
+
+
+/// A type that can be written into a buffer following glsl's std140 or std430 layout rules.
+/// Usually implemented with `#[derive(GpuLayout)]`.
+pub trait GpuLayout: Sized {
+	const STD140: TypeLayout;
+	const STD430: TypeLayout;
+
+	const FIELDS_STD140: &'static [FieldLayout] = &[];
+	const FIELDS_STD430: &'static [FieldLayout] = &[];
+
+	/// Writes `self` to the start of `bytes`, leaving any padding untouched.
+	fn write_bytes(&self, layout: Layout, bytes: &mut [u8]);
+
+	fn layout(layout: Layout) -> TypeLayout {
+		match layout {
+			Layout::Std140 => Self::STD140,
+			Layout::Std430 => Self::STD430,
+		}
+	}
+
+	fn fields(layout: Layout) -> &'static [FieldLayout] {
+		match layout {
+			Layout::Std140 => Self::FIELDS_STD140,
+			Layout::Std430 => Self::FIELDS_STD430,
+		}
+	}
+}
+
+
+/// Serialises `data` as an array of `T`, as it would be laid out in a buffer block.
+pub fn to_bytes<T: GpuLayout>(data: &[T], layout: Layout) -> Vec<u8> {
+	let stride = array_stride(T::layout(layout), layout);
+	let mut bytes = vec![0u8; stride * data.len()];
+
+	for (item, item_bytes) in data.iter().zip(bytes.chunks_exact_mut(stride)) {
+		item.write_bytes(layout, item_bytes);
+	}
+
+	bytes
+}
+
+
+
+pub const fn round_up(value: usize, align: usize) -> usize {
+	(value + align - 1) / align * align
+}
+
+const fn max(a: usize, b: usize) -> usize {
+	if a > b { a } else { b }
+}
+
+pub const fn array_stride(element: TypeLayout, layout: Layout) -> usize {
+	match layout {
+		// std140 rounds array element alignment up to that of a vec4
+		Layout::Std140 => round_up(element.size, max(element.align, 16)),
+		Layout::Std430 => round_up(element.size, element.align),
+	}
+}
+
+pub const fn array_layout(element: TypeLayout, count: usize, layout: Layout) -> TypeLayout {
+	let align = match layout {
+		Layout::Std140 => max(element.align, 16),
+		Layout::Std430 => element.align,
+	};
+
+	TypeLayout {
+		align,
+		size: array_stride(element, layout) * count,
+	}
+}
+
+pub const fn struct_layout(fields: &[TypeLayout], layout: Layout) -> TypeLayout {
+	// std140 rounds struct alignment up to that of a vec4
+	let mut align = match layout {
+		Layout::Std140 => 16,
+		Layout::Std430 => 1,
+	};
+
+	let mut end = 0;
+	let mut index = 0;
+
+	while index < fields.len() {
+		end = round_up(end, fields[index].align) + fields[index].size;
+		align = max(align, fields[index].align);
+		index += 1;
+	}
+
+	TypeLayout {
+		align,
+		size: round_up(end, align),
+	}
+}
+
+pub const fn field_offset(fields: &[TypeLayout], field_index: usize) -> usize {
+	let mut end = 0;
+	let mut index = 0;
+
+	while index < field_index {
+		end = round_up(end, fields[index].align) + fields[index].size;
+		index += 1;
+	}
+
+	round_up(end, fields[field_index].align)
+}
+
+
+
+macro_rules! impl_gpu_layout {
+	($ty:ty, align: $align:expr, size: $size:expr) => {
+		impl GpuLayout for $ty {
+			const STD140: TypeLayout = TypeLayout { align: $align, size: $size };
+			const STD430: TypeLayout = TypeLayout { align: $align, size: $size };
+
+			fn write_bytes(&self, _: Layout, bytes: &mut [u8]) {
+				bytes[..$size].copy_from_slice(&as_bytes(self)[..$size]);
+			}
+		}
+	}
+}
+
+impl_gpu_layout!(f32, align: 4, size: 4);
+impl_gpu_layout!(u32, align: 4, size: 4);
+impl_gpu_layout!(i32, align: 4, size: 4);
+
+impl_gpu_layout!(Vec2, align: 8, size: 8);
+impl_gpu_layout!(Vec3, align: 16, size: 12);
+impl_gpu_layout!(Vec4, align: 16, size: 16);
+
+// Matrices are written in memory order, so must be declared row_major in glsl
+impl_gpu_layout!(Mat4, align: 16, size: 64);
+
+
+impl<T: GpuLayout, const N: usize> GpuLayout for [T; N] {
+	const STD140: TypeLayout = array_layout(T::STD140, N, Layout::Std140);
+	const STD430: TypeLayout = array_layout(T::STD430, N, Layout::Std430);
+
+	fn write_bytes(&self, layout: Layout, bytes: &mut [u8]) {
+		let stride = array_stride(T::layout(layout), layout);
+
+		for (index, item) in self.iter().enumerate() {
+			item.write_bytes(layout, &mut bytes[index * stride..]);
+		}
+	}
+}
+
+
+fn as_bytes<T>(value: &T) -> &[u8] {
+	unsafe {
+		std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())
+	}
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[derive(GpuLayout)]
+	struct Inner {
+		a: f32,
+		b: Vec3,
+	}
+
+	#[derive(GpuLayout)]
+	struct Outer {
+		a: Vec3,
+		b: f32,
+		c: [f32; 3],
+		d: Inner,
+		e: Vec2,
+	}
+
+	fn offsets<T: GpuLayout>(layout: Layout) -> Vec<usize> {
+		T::fields(layout).iter().map(|f| f.offset).collect()
+	}
+
+	#[test]
+	fn std140_offsets() {
+		assert_eq!(offsets::<Inner>(Layout::Std140), [0, 16]);
+		assert_eq!(Inner::STD140, TypeLayout { align: 16, size: 32 });
+
+		// vec3 and float share a vec4, float arrays have a vec4 stride
+		assert_eq!(offsets::<Outer>(Layout::Std140), [0, 12, 16, 64, 96]);
+		assert_eq!(Outer::STD140, TypeLayout { align: 16, size: 112 });
+	}
+
+	#[test]
+	fn std430_offsets() {
+		assert_eq!(offsets::<Inner>(Layout::Std430), [0, 16]);
+		assert_eq!(Inner::STD430, TypeLayout { align: 16, size: 32 });
+
+		assert_eq!(offsets::<Outer>(Layout::Std430), [0, 12, 16, 32, 64]);
+		assert_eq!(Outer::STD430, TypeLayout { align: 16, size: 80 });
+	}
+
+	#[test]
+	fn serialisation() {
+		let data = [
+			Inner { a: 1.0, b: Vec3::new(2.0, 3.0, 4.0) },
+			Inner { a: 5.0, b: Vec3::new(6.0, 7.0, 8.0) },
+		];
+
+		let bytes = to_bytes(&data, Layout::Std430);
+		let floats = bytes.chunks_exact(4)
+			.map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+			.collect(): Vec<_>;
+
+		assert_eq!(floats, [
+			1.0, 0.0, 0.0, 0.0,  2.0, 3.0, 4.0, 0.0,
+			5.0, 0.0, 0.0, 0.0,  6.0, 7.0, 8.0, 0.0,
+		]);
+	}
+}
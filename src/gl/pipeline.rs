@@ -0,0 +1,128 @@
+use super::raw;
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+	Opaque,
+	Alpha,
+	Additive,
+
+	/// src * dst
+	Multiply,
+
+	/// src * dst + dst
+	MultiplyAdd,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CullMode {
+	None,
+	Back,
+	Front,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PolygonMode {
+	Fill,
+	Line,
+}
+
+
+/// Fixed function state required by a draw call.
+/// Passed to draw calls on `gl::Context`, which only issues the GL calls needed to move from the previous state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PipelineState {
+	pub blend: BlendMode,
+	pub depth_test: bool,
+	pub depth_write: bool,
+	pub cull: CullMode,
+	pub polygon: PolygonMode,
+}
+
+
+impl PipelineState {
+	pub const DEFAULT: PipelineState = PipelineState {
+		blend: BlendMode::Opaque,
+		depth_test: true,
+		depth_write: true,
+		cull: CullMode::None,
+		polygon: PolygonMode::Fill,
+	};
+
+	/// Issues the GL calls required to transition from `previous` to `self`.
+	/// If `previous` is None then the current GL state is assumed unknown and everything is set.
+	pub(super) fn apply(&self, previous: Option<&PipelineState>) {
+		unsafe {
+			if previous.map_or(true, |p| p.blend != self.blend) {
+				apply_blend(self.blend, previous.map(|p| p.blend));
+			}
+
+			if previous.map_or(true, |p| p.depth_test != self.depth_test) {
+				set_capability(raw::DEPTH_TEST, self.depth_test);
+			}
+
+			if previous.map_or(true, |p| p.depth_write != self.depth_write) {
+				raw::DepthMask(self.depth_write as _);
+			}
+
+			if previous.map_or(true, |p| p.cull != self.cull) {
+				match self.cull {
+					CullMode::None => raw::Disable(raw::CULL_FACE),
+					CullMode::Back | CullMode::Front => {
+						raw::Enable(raw::CULL_FACE);
+						raw::CullFace(match self.cull {
+							CullMode::Front => raw::FRONT,
+							_ => raw::BACK,
+						});
+					}
+				}
+			}
+
+			if previous.map_or(true, |p| p.polygon != self.polygon) {
+				let mode = match self.polygon {
+					PolygonMode::Fill => raw::FILL,
+					PolygonMode::Line => raw::LINE,
+				};
+
+				raw::PolygonMode(raw::FRONT_AND_BACK, mode);
+			}
+		}
+	}
+}
+
+impl Default for PipelineState {
+	fn default() -> Self {
+		PipelineState::DEFAULT
+	}
+}
+
+
+unsafe fn set_capability(capability: u32, enabled: bool) {
+	if enabled {
+		raw::Enable(capability);
+	} else {
+		raw::Disable(capability);
+	}
+}
+
+unsafe fn apply_blend(mode: BlendMode, previous: Option<BlendMode>) {
+	let (src, dst) = match mode {
+		BlendMode::Opaque => {
+			raw::Disable(raw::BLEND);
+			return
+		}
+
+		BlendMode::Alpha => (raw::SRC_ALPHA, raw::ONE_MINUS_SRC_ALPHA),
+		BlendMode::Additive => (raw::ONE, raw::ONE),
+		BlendMode::Multiply => (raw::DST_COLOR, raw::ZERO),
+		BlendMode::MultiplyAdd => (raw::DST_COLOR, raw::ONE),
+	};
+
+	// Only need to touch the capability and equation if blending was previously off or unknown
+	if previous.map_or(true, |p| p == BlendMode::Opaque) {
+		raw::Enable(raw::BLEND);
+		raw::BlendEquation(raw::FUNC_ADD);
+	}
+
+	raw::BlendFunc(src, dst);
+}
@@ -0,0 +1,573 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::fmt;
+
+
+/// Resolves `#import <name>` directives in shader source.
+///
+/// Imports are resolved first against sources registered with `add_import`, then against
+/// files in any added search directories, trying `<name>`, `<name>.common.glsl` and `<name>.glsl`.
+/// Imports may be nested, each import is only ever emitted once per processed file,
+/// and directives inside comments are ignored.
+///
+/// Each inlined import is given its own GLSL source string number with `#line` directives,
+/// so that compile errors refer to lines in the file they came from. See `Processed::files`.
+#[derive(Debug, Default)]
+pub struct Preprocessor {
+	imports: HashMap<String, String>,
+	search_dirs: Vec<PathBuf>,
+}
+
+
+/// Preprocessed source, and the files that went into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Processed {
+	pub source: String,
+
+	/// Indexed by the source string numbers used in `#line` directives, starting with the root file
+	pub files: Vec<String>,
+}
+
+
+/// A set of `#define`s used to select a shader permutation.
+/// Ordered so that equal sets hash and compare equal regardless of insertion order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Defines (BTreeMap<String, String>);
+
+
+#[derive(Debug)]
+pub enum ErrorKind {
+	DuplicateImport(String),
+	UnknownImport(String),
+	MalformedImport(String),
+	ImportCycle(Vec<String>),
+	Io(PathBuf, std::io::Error),
+}
+
+#[derive(Debug)]
+pub struct Error {
+	pub kind: ErrorKind,
+
+	/// The file containing the offending directive
+	pub file: String,
+
+	/// 1-based line of the offending directive, or 0 if it doesn't refer to a line
+	pub line: usize,
+}
+
+
+struct State {
+	included: HashSet<String>,
+	stack: Vec<String>,
+	files: Vec<String>,
+}
+
+
+impl Preprocessor {
+	pub fn new() -> Self {
+		Preprocessor::default()
+	}
+
+	pub fn add_import(&mut self, name: impl Into<String>, src: impl Into<String>) -> Result<(), Error> {
+		let name = name.into();
+
+		if self.imports.contains_key(&name) {
+			return Err(Error {
+				kind: ErrorKind::DuplicateImport(name.clone()),
+				file: name,
+				line: 0,
+			})
+		}
+
+		self.imports.insert(name, src.into());
+		Ok(())
+	}
+
+	pub fn add_search_dir(&mut self, path: impl Into<PathBuf>) {
+		self.search_dirs.push(path.into());
+	}
+
+	pub fn process(&self, file_name: &str, src: &str) -> Result<Processed, Error> {
+		let mut state = State {
+			included: HashSet::new(),
+			stack: vec![file_name.to_owned()],
+			files: vec![file_name.to_owned()],
+		};
+
+		let mut result = String::with_capacity(src.len());
+		self.process_file(file_name, 0, src, &mut state, &mut result)?;
+
+		Ok(Processed {
+			source: result,
+			files: state.files,
+		})
+	}
+
+
+	fn process_file(&self, file_name: &str, file_index: usize, src: &str, state: &mut State, result: &mut String) -> Result<(), Error> {
+		let mut in_block_comment = false;
+		let lines = src.split_inclusive('\n').collect::<Vec<_>>();
+
+		for (line_idx, &line) in lines.iter().enumerate() {
+			let error = |kind| Error {
+				kind,
+				file: file_name.to_owned(),
+				line: line_idx + 1,
+			};
+
+			let code = strip_comments(line, &mut in_block_comment);
+			let directive = match code.trim_start().strip_prefix("#import") {
+				Some(directive) => directive,
+				None => {
+					result.push_str(line);
+					continue
+				}
+			};
+
+			let import_name = parse_import_name(directive)
+				.ok_or_else(|| error(ErrorKind::MalformedImport(code.trim().to_owned())))?;
+
+			let (key, import_src) = self.load(import_name)
+				.ok_or_else(|| error(ErrorKind::UnknownImport(import_name.to_owned())))?
+				.map_err(|(path, err)| error(ErrorKind::Io(path, err)))?;
+
+			if state.stack.contains(&key) {
+				let mut chain = state.stack.clone();
+				chain.push(key);
+				return Err(error(ErrorKind::ImportCycle(chain)))
+			}
+
+			if state.included.insert(key.clone()) {
+				let import_index = state.files.len();
+				state.files.push(import_name.to_owned());
+				result.push_str(&format!("#line 1 {}\n", import_index));
+
+				state.stack.push(key);
+				self.process_file(import_name, import_index, &import_src, state, result)?;
+				state.stack.pop();
+
+				if !result.ends_with('\n') {
+					result.push('\n');
+				}
+
+				// Resume numbering from the line after the directive
+				if line_idx + 1 < lines.len() {
+					result.push_str(&format!("#line {} {}\n", line_idx + 2, file_index));
+				}
+			} else {
+				// The directive becomes a blank line, so the lines that follow keep their numbers
+				result.push('\n');
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns a key identifying the import for include-once purposes, and the import source.
+	fn load(&self, name: &str) -> Option<Result<(String, String), (PathBuf, std::io::Error)>> {
+		if let Some(src) = self.imports.get(name) {
+			return Some(Ok((name.to_owned(), src.clone())))
+		}
+
+		let candidates = [
+			name.to_owned(),
+			format!("{}.common.glsl", name),
+			format!("{}.glsl", name),
+		];
+
+		for dir in self.search_dirs.iter() {
+			for candidate in candidates.iter() {
+				let path = dir.join(candidate);
+				if !path.is_file() { continue }
+
+				let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+				let result = std::fs::read_to_string(&path)
+					.map(|src| (key.display().to_string(), src))
+					.map_err(|err| (path, err));
+
+				return Some(result)
+			}
+		}
+
+		None
+	}
+}
+
+
+impl Processed {
+	/// Lists which file each source string number refers to, for making sense of compile errors.
+	pub fn describe_files(&self) -> String {
+		self.files.iter()
+			.enumerate()
+			.map(|(index, file)| format!("{}: {}", index, file))
+			.collect::<Vec<_>>()
+			.join(", ")
+	}
+}
+
+
+impl Defines {
+	pub fn new() -> Self {
+		Defines::default()
+	}
+
+	pub fn with(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+		self.set(name, value);
+		self
+	}
+
+	pub fn set(&mut self, name: impl Into<String>, value: impl ToString) {
+		self.0.insert(name.into(), value.to_string());
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+		self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+	}
+}
+
+
+/// Inserts `defines` directly after the `#version` directive in `src`, or at the start if there isn't one.
+/// Lines that follow keep their numbers, as `#version` must precede any imports in the root file.
+pub fn inject_defines(src: &str, defines: &Defines) -> String {
+	if defines.is_empty() {
+		return src.to_owned()
+	}
+
+	let mut in_block_comment = false;
+	let mut offset = 0;
+	let mut insert_point = None;
+	let mut lines_before = 0;
+
+	for line in src.split_inclusive('\n') {
+		offset += line.len();
+		lines_before += 1;
+
+		let code = strip_comments(line, &mut in_block_comment);
+		if code.trim_start().starts_with("#version") {
+			insert_point = Some(offset);
+			break
+		}
+	}
+
+	if insert_point.is_none() {
+		lines_before = 0;
+	}
+
+	let (prefix, suffix) = src.split_at(insert_point.unwrap_or(0));
+
+	let mut result = String::with_capacity(src.len() + defines.0.len() * 32);
+	result.push_str(prefix);
+
+	if !prefix.is_empty() && !prefix.ends_with('\n') {
+		result.push('\n');
+	}
+
+	for (name, value) in defines.iter() {
+		result.push_str(&format!("#define {} {}\n", name, value));
+	}
+
+	result.push_str(&format!("#line {} 0\n", lines_before + 1));
+	result.push_str(suffix);
+	result
+}
+
+
+/// Returns the parts of `line` that aren't within comments, tracking block comments across lines.
+fn strip_comments(line: &str, in_block_comment: &mut bool) -> String {
+	let mut code = String::with_capacity(line.len());
+	let mut rest = line;
+
+	while !rest.is_empty() {
+		if *in_block_comment {
+			match rest.find("*/") {
+				Some(end) => {
+					rest = &rest[end+2..];
+					*in_block_comment = false;
+					code.push(' ');
+				}
+
+				None => break,
+			}
+		} else {
+			let line_comment = rest.find("//");
+			let block_comment = rest.find("/*");
+
+			match (line_comment, block_comment) {
+				(Some(lc), Some(bc)) if lc < bc => {
+					code.push_str(&rest[..lc]);
+					break
+				}
+
+				(_, Some(bc)) => {
+					code.push_str(&rest[..bc]);
+					rest = &rest[bc+2..];
+					*in_block_comment = true;
+				}
+
+				(Some(lc), None) => {
+					code.push_str(&rest[..lc]);
+					break
+				}
+
+				(None, None) => {
+					code.push_str(rest);
+					break
+				}
+			}
+		}
+	}
+
+	code
+}
+
+/// Parses the remainder of an import directive: `name`, `"name"` or `<name>`.
+fn parse_import_name(directive: &str) -> Option<&str> {
+	// Require whitespace between '#import' and the name, so '#imports' isn't accepted
+	if !directive.starts_with(char::is_whitespace) {
+		return None
+	}
+
+	let name = directive.trim();
+	let name = name.strip_prefix('"').and_then(|n| n.strip_suffix('"'))
+		.or_else(|| name.strip_prefix('<').and_then(|n| n.strip_suffix('>')))
+		.unwrap_or(name);
+
+	if name.is_empty() || name.contains(char::is_whitespace) {
+		return None
+	}
+
+	Some(name)
+}
+
+
+impl fmt::Display for ErrorKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ErrorKind::DuplicateImport(name) => write!(f, "import '{}' registered more than once", name),
+			ErrorKind::UnknownImport(name) => write!(f, "unknown import '{}'", name),
+			ErrorKind::MalformedImport(directive) => write!(f, "malformed import directive '{}', expected '#import <name>'", directive),
+			ErrorKind::ImportCycle(chain) => write!(f, "import cycle: {}", chain.join(" -> ")),
+			ErrorKind::Io(path, err) => write!(f, "failed to read '{}': {}", path.display(), err),
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.line {
+			0 => write!(f, "{}: {}", self.file, self.kind),
+			line => write!(f, "{}:{}: {}", self.file, line, self.kind),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn preprocessor(imports: &[(&str, &str)]) -> Preprocessor {
+		let mut pp = Preprocessor::new();
+		for &(name, src) in imports {
+			pp.add_import(name, src).unwrap();
+		}
+		pp
+	}
+
+	#[test]
+	fn nested_imports() {
+		let pp = preprocessor(&[
+			("a", "a\n#import b\n"),
+			("b", "b\n"),
+		]);
+
+		let result = pp.process("root", "#version 450\n#import a\nmain\n").unwrap();
+		assert_eq!(result.source, "#version 450\n#line 1 1\na\n#line 1 2\nb\n#line 3 0\nmain\n");
+		assert_eq!(result.files, ["root", "a", "b"]);
+	}
+
+	#[test]
+	fn imports_included_once() {
+		let pp = preprocessor(&[
+			("common", "common\n"),
+			("a", "#import common\na\n"),
+		]);
+
+		let result = pp.process("root", "#import common\n#import a\n#import common\n").unwrap();
+		assert_eq!(result.source, "#line 1 1\ncommon\n#line 2 0\n#line 1 2\n\na\n#line 3 0\n\n");
+		assert_eq!(result.files, ["root", "common", "a"]);
+	}
+
+	#[test]
+	fn imports_in_comments_ignored() {
+		let pp = preprocessor(&[]);
+		let src = "// #import a\n/* \n#import b\n*/ x\n  /* */ // #import c\n";
+
+		assert_eq!(pp.process("root", src).unwrap().source, src);
+	}
+
+	#[test]
+	fn indented_and_quoted_imports() {
+		let pp = preprocessor(&[("a", "a")]);
+
+		let result = pp.process("root", "\t#import \"a\" // trailing\n").unwrap();
+		assert_eq!(result.source, "#line 1 1\na\n");
+	}
+
+	#[test]
+	fn unknown_import() {
+		let pp = preprocessor(&[("a", "\n\n#import missing\n")]);
+		let err = pp.process("root", "#import a\n").unwrap_err();
+
+		assert!(matches!(err.kind, ErrorKind::UnknownImport(ref name) if name == "missing"));
+		assert_eq!(err.file, "a");
+		assert_eq!(err.line, 3);
+	}
+
+	#[test]
+	fn malformed_import() {
+		let pp = preprocessor(&[]);
+
+		let err = pp.process("root", "x\n#import\n").unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::MalformedImport(_)));
+		assert_eq!((err.file.as_str(), err.line), ("root", 2));
+
+		let err = pp.process("root", "#importa\n").unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::MalformedImport(_)));
+
+		let err = pp.process("root", "#import a b\n").unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::MalformedImport(_)));
+	}
+
+	#[test]
+	fn import_cycle() {
+		let pp = preprocessor(&[
+			("a", "#import b\n"),
+			("b", "\n#import a\n"),
+		]);
+
+		let err = pp.process("root", "#import a\n").unwrap_err();
+
+		match err.kind {
+			ErrorKind::ImportCycle(chain) => assert_eq!(chain, ["root", "a", "b", "a"]),
+			kind => panic!("Unexpected error {}", kind),
+		}
+
+		assert_eq!((err.file.as_str(), err.line), ("b", 2));
+	}
+
+	#[test]
+	fn defines_injected_after_version() {
+		let defines = Defines::new()
+			.with("B", 2)
+			.with("A", "1");
+
+		let result = inject_defines("// header\n#version 450\nmain\n", &defines);
+		assert_eq!(result, "// header\n#version 450\n#define A 1\n#define B 2\n#line 3 0\nmain\n");
+
+		let result = inject_defines("main\n", &defines);
+		assert_eq!(result, "#define A 1\n#define B 2\n#line 1 0\nmain\n");
+
+		let result = inject_defines("#version 450", &defines);
+		assert_eq!(result, "#version 450\n#define A 1\n#define B 2\n#line 2 0\n");
+
+		assert_eq!(inject_defines("#version 450\n", &Defines::new()), "#version 450\n");
+	}
+
+	#[test]
+	fn defines_equal_regardless_of_order() {
+		let a = Defines::new().with("A", 1).with("B", 2);
+		let b = Defines::new().with("B", 2).with("A", 1);
+		assert_eq!(a, b);
+		assert_ne!(a, b.with("A", 3));
+	}
+
+	#[test]
+	fn duplicate_import() {
+		let mut pp = preprocessor(&[("a", "")]);
+		let err = pp.add_import("a", "").unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::DuplicateImport(_)));
+	}
+
+	#[test]
+	fn directory_imports() {
+		let dir = std::env::temp_dir().join(format!("preprocessor-test-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("shared.common.glsl"), "shared\n#import other.glsl\n").unwrap();
+		std::fs::write(dir.join("other.glsl"), "other\n").unwrap();
+
+		let mut pp = Preprocessor::new();
+		pp.add_search_dir(&dir);
+
+		let result = pp.process("root", "#import shared\n#import other\n");
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		let result = result.unwrap();
+		assert_eq!(result.source, "#line 1 1\nshared\n#line 1 2\nother\n#line 2 0\n\n");
+		assert_eq!(result.files, ["root", "shared", "other.glsl"]);
+	}
+
+	/// Follows `#line` directives the way a GLSL compiler would, returning the file and line of every other line.
+	fn map_lines(processed: &Processed) -> Vec<(&str, usize, &str)> {
+		let mut file = 0;
+		let mut line = 1;
+		let mut mapped = Vec::new();
+
+		for text in processed.source.lines() {
+			if let Some(directive) = text.strip_prefix("#line ") {
+				let mut parts = directive.split(' ').map(|part| part.parse().unwrap());
+				line = parts.next().unwrap();
+				file = parts.next().unwrap();
+				continue
+			}
+
+			mapped.push((processed.files[file].as_str(), line, text));
+			line += 1;
+		}
+
+		mapped
+	}
+
+	#[test]
+	fn lines_map_back_to_their_files() {
+		let sources = [
+			("root", "#version 450\n#import a\nroot 3\n#import b\nroot 5\n"),
+			("a", "a 1\n#import b\na 3\n"),
+			("b", "b 1\nb 2\n"),
+		];
+
+		let pp = preprocessor(&sources[1..]);
+		let processed = pp.process("root", sources[0].1).unwrap();
+
+		let source_line = |file: &str, line: usize| {
+			let (_, src) = sources.iter().find(|(name, _)| *name == file).unwrap();
+			src.lines().nth(line - 1).unwrap()
+		};
+
+		let mapped = map_lines(&processed);
+
+		for &(file, line, text) in mapped.iter() {
+			// Repeated imports are replaced by blank lines
+			if text.is_empty() {
+				assert!(source_line(file, line).starts_with("#import"));
+			} else {
+				assert_eq!(source_line(file, line), text, "{}:{}", file, line);
+			}
+		}
+
+		let texts = mapped.iter().map(|&(_, _, text)| text).collect::<Vec<_>>();
+		assert_eq!(texts, ["#version 450", "a 1", "b 1", "b 2", "a 3", "root 3", "", "root 5"]);
+
+		// Defines don't disturb numbering either
+		let with_defines = inject_defines(&processed.source, &Defines::new().with("A", 1));
+		let with_defines = Processed { source: with_defines, files: processed.files.clone() };
+		assert_eq!(map_lines(&with_defines)[2..], mapped[1..]);
+	}
+}
@@ -0,0 +1,240 @@
+use std::ffi::CString;
+use std::path::PathBuf;
+use super::{raw, Capabilities, Defines};
+
+
+/// Saves linked programs to disk, so later runs can skip compiling them.
+///
+/// Entries are keyed by the preprocessed source of every stage, the define set, and the driver
+/// vendor, renderer and version, so changing any of them simply misses the cache.
+/// Drivers may still reject a binary, in which case the program is recompiled and the entry replaced.
+///
+/// Keys are hashed with FNV-1a so they're the same across builds. Changes to how keys or entries
+/// are written must bump `FORMAT_VERSION`, which is part of every file name.
+#[derive(Clone, Debug)]
+pub struct ProgramCache {
+	dir: PathBuf,
+	driver: String,
+}
+
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) struct CacheKey (u64);
+
+/// 64 bit FNV-1a, which unlike `DefaultHasher` is fully specified.
+struct StableHasher (u64);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct ProgramBinary {
+	format: u32,
+	data: Vec<u8>,
+}
+
+
+impl ProgramCache {
+	pub fn new(dir: impl Into<PathBuf>, capabilities: &Capabilities) -> ProgramCache {
+		ProgramCache {
+			dir: dir.into(),
+			driver: format!("{}\n{}\n{}", capabilities.vendor, capabilities.renderer, capabilities.version),
+		}
+	}
+
+	pub fn dir(&self) -> &std::path::Path {
+		&self.dir
+	}
+
+	/// Removes entries written with a different `FORMAT_VERSION`, which would otherwise never be hit.
+	pub fn remove_stale_entries(&self) {
+		let current_prefix = format!("v{}-", FORMAT_VERSION);
+
+		let entries = match std::fs::read_dir(&self.dir) {
+			Ok(entries) => entries,
+			Err(_) => return,
+		};
+
+		for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+			let file_name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+
+			if file_name.ends_with(".bin") && !file_name.starts_with(&current_prefix) {
+				let _ = std::fs::remove_file(&path);
+			}
+		}
+	}
+
+	pub(super) fn key(&self, sources: &[(u32, CString)], defines: &Defines) -> CacheKey {
+		let mut hasher = StableHasher::new();
+		hasher.write_field(self.driver.as_bytes());
+
+		hasher.write(&(sources.len() as u64).to_le_bytes());
+		for (ty, source) in sources {
+			hasher.write(&ty.to_le_bytes());
+			hasher.write_field(source.as_bytes());
+		}
+
+		hasher.write(&(defines.iter().count() as u64).to_le_bytes());
+		for (name, value) in defines.iter() {
+			hasher.write_field(name.as_bytes());
+			hasher.write_field(value.as_bytes());
+		}
+
+		CacheKey(hasher.0)
+	}
+
+	pub(super) fn load(&self, key: CacheKey) -> Option<ProgramBinary> {
+		let bytes = std::fs::read(self.path(key)).ok()?;
+		if bytes.len() <= 4 {
+			return None
+		}
+
+		let (format, data) = bytes.split_at(4);
+
+		Some(ProgramBinary {
+			format: u32::from_le_bytes([format[0], format[1], format[2], format[3]]),
+			data: data.to_vec(),
+		})
+	}
+
+	pub(super) fn store(&self, key: CacheKey, binary: &ProgramBinary) {
+		let mut bytes = Vec::with_capacity(binary.data.len() + 4);
+		bytes.extend_from_slice(&binary.format.to_le_bytes());
+		bytes.extend_from_slice(&binary.data);
+
+		let result = std::fs::create_dir_all(&self.dir)
+			.and_then(|_| std::fs::write(self.path(key), bytes));
+
+		if let Err(error) = result {
+			log::warn!("Failed to write program cache entry to {}: {}", self.dir.display(), error);
+		}
+	}
+
+	pub(super) fn evict(&self, key: CacheKey) {
+		let _ = std::fs::remove_file(self.path(key));
+	}
+
+	fn path(&self, key: CacheKey) -> PathBuf {
+		self.dir.join(format!("v{}-{:016x}.bin", FORMAT_VERSION, key.0))
+	}
+}
+
+
+impl StableHasher {
+	fn new() -> StableHasher {
+		StableHasher(0xcbf2_9ce4_8422_2325)
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+		}
+	}
+
+	/// Prefixed with its length, so that adjacent fields can't run into each other.
+	fn write_field(&mut self, bytes: &[u8]) {
+		self.write(&(bytes.len() as u64).to_le_bytes());
+		self.write(bytes);
+	}
+}
+
+
+impl ProgramBinary {
+	/// Must only be called on a program linked with `PROGRAM_BINARY_RETRIEVABLE_HINT` set.
+	pub(super) unsafe fn retrieve(program_handle: u32) -> Option<ProgramBinary> {
+		let mut length = 0;
+		raw::GetProgramiv(program_handle, raw::PROGRAM_BINARY_LENGTH, &mut length);
+
+		if length <= 0 {
+			return None
+		}
+
+		let mut data = vec![0u8; length as usize];
+		let mut format = 0;
+		let mut written = 0;
+		raw::GetProgramBinary(program_handle, length, &mut written, &mut format, data.as_mut_ptr() as *mut _);
+
+		data.truncate(written as usize);
+		Some(ProgramBinary { format, data })
+	}
+
+	/// Replaces the program's executable with this binary. `LINK_STATUS` reports whether the driver accepted it.
+	pub(super) unsafe fn load_into(&self, program_handle: u32) {
+		raw::ProgramBinary(program_handle, self.format, self.data.as_ptr() as *const _, self.data.len() as _);
+	}
+}
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn test_cache(name: &str) -> ProgramCache {
+		let dir = std::env::temp_dir().join(format!("program_cache_test_{}_{}", name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		ProgramCache::new(dir, &Capabilities::default())
+	}
+
+	fn sources(src: &str) -> Vec<(u32, CString)> {
+		vec![(raw::FRAGMENT_SHADER, CString::new(src).unwrap())]
+	}
+
+	#[test]
+	fn keys_differ_by_source_defines_and_driver() {
+		let cache = test_cache("keys");
+		let key = cache.key(&sources("a"), &Defines::new());
+
+		assert_eq!(key, cache.key(&sources("a"), &Defines::new()));
+		assert_ne!(key, cache.key(&sources("b"), &Defines::new()));
+		assert_ne!(key, cache.key(&sources("a"), &Defines::new().with("A", 1)));
+
+		let other_driver = ProgramCache::new(cache.dir(), &Capabilities {
+			renderer: "other".into(),
+			..Capabilities::default()
+		});
+
+		assert_ne!(key, other_driver.key(&sources("a"), &Defines::new()));
+	}
+
+	#[test]
+	fn keys_are_stable() {
+		let mut hasher = StableHasher::new();
+		hasher.write(b"a");
+		assert_eq!(hasher.0, 0xaf63_dc4c_8601_ec8c);
+
+		// Changing this breaks every existing cache entry, so should come with a new FORMAT_VERSION
+		let cache = test_cache("stable");
+		assert_eq!(cache.key(&sources("a"), &Defines::new().with("A", 1)), CacheKey(0x3c71_55cd_a4e5_be08));
+	}
+
+	#[test]
+	fn removes_entries_from_other_versions() {
+		let cache = test_cache("stale");
+		let key = cache.key(&sources("a"), &Defines::new());
+		cache.store(key, &ProgramBinary { format: 0x1234, data: vec![1, 2, 3] });
+
+		let stale_path = cache.dir().join("0123456789abcdef.bin");
+		std::fs::write(&stale_path, [0; 8]).unwrap();
+
+		cache.remove_stale_entries();
+		assert!(!stale_path.exists());
+		assert!(cache.load(key).is_some());
+
+		let _ = std::fs::remove_dir_all(cache.dir());
+	}
+
+	#[test]
+	fn stores_and_evicts_entries() {
+		let cache = test_cache("entries");
+		let key = cache.key(&sources("a"), &Defines::new());
+		let binary = ProgramBinary { format: 0x1234, data: vec![1, 2, 3] };
+
+		assert_eq!(cache.load(key), None);
+
+		cache.store(key, &binary);
+		assert_eq!(cache.load(key), Some(binary));
+
+		cache.evict(key);
+		assert_eq!(cache.load(key), None);
+
+		let _ = std::fs::remove_dir_all(cache.dir());
+	}
+}
@@ -0,0 +1,180 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::error::Error;
+use common::math::Vec4;
+use super::*;
+
+
+/// A single call made through `Backend`, as captured by `RecordingBackend`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+	NewShader { program: Program, label: String, defines: Defines },
+	NewSpirvShader { program: Program, label: String, constants: SpecializationConstants },
+	UseProgram(Program),
+
+	NewBuffer { buffer: Buffer, label: String },
+	UploadBuffer { buffer: Buffer, data: Vec<u8>, usage: BufferUsage },
+	BindUniformBuffer { binding: u32, buffer: Buffer },
+	BindShaderStorageBuffer { binding: u32, buffer: Buffer },
+
+	NewVertexArray { vertex_array: VertexArray, label: String },
+	SetIndexBuffer { vertex_array: VertexArray, buffer: Buffer },
+
+	NewTexture { texture: Texture, label: String },
+	ClearTexture { texture: Texture, value: Vec4 },
+	NewSampler { sampler: Sampler, desc: SamplerDesc },
+	BindTexture { binding: u32, texture: Texture },
+	BindSampler { binding: u32, sampler: Option<Sampler> },
+	BindImage { binding: u32, texture: Texture },
+
+	DrawMeshTasks { state: PipelineState, offset: u32, count: u32 },
+	DrawInstanced { state: PipelineState, vertex_count: u32, instance_count: u32 },
+	DrawIndexed { state: PipelineState, vertex_array: VertexArray, index_count: u32 },
+	DispatchCompute { x: u32, y: u32, z: u32 },
+	MemoryBarrier(u32),
+}
+
+
+/// A `Backend` that makes no GL calls, and instead records every command for inspection.
+/// Object handles are allocated sequentially, starting at 1.
+pub struct RecordingBackend {
+	capabilities: Capabilities,
+	commands: RefCell<Vec<Command>>,
+	next_handle: Cell<u32>,
+}
+
+
+impl RecordingBackend {
+	pub fn new(capabilities: Capabilities) -> RecordingBackend {
+		RecordingBackend {
+			capabilities,
+			commands: RefCell::new(Vec::new()),
+			next_handle: Cell::new(1),
+		}
+	}
+
+	/// A backend reporting mesh shader support, with limits typical of NV hardware.
+	pub fn with_mesh_shaders() -> RecordingBackend {
+		RecordingBackend::new(Capabilities {
+			mesh_shaders: true,
+			max_mesh_output_vertices: 256,
+			max_mesh_output_primitives: 512,
+			max_mesh_work_group_size: 32,
+			max_task_output_count: 65535,
+			max_task_work_group_size: 32,
+			max_draw_mesh_tasks_count: 65535,
+			max_color_attachments: 8,
+			..Capabilities::default()
+		})
+	}
+
+	/// A backend without mesh shader support, so subsystems choose the vertex pipeline.
+	pub fn without_mesh_shaders() -> RecordingBackend {
+		RecordingBackend::new(Capabilities {
+			max_color_attachments: 8,
+			..Capabilities::default()
+		})
+	}
+
+	pub fn commands(&self) -> Ref<'_, [Command]> {
+		Ref::map(self.commands.borrow(), Vec::as_slice)
+	}
+
+	/// Returns all commands recorded so far, and starts recording afresh.
+	pub fn take_commands(&self) -> Vec<Command> {
+		self.commands.take()
+	}
+
+	fn record(&self, command: Command) {
+		self.commands.borrow_mut().push(command);
+	}
+
+	fn new_handle(&self) -> u32 {
+		let handle = self.next_handle.get();
+		self.next_handle.set(handle + 1);
+		handle
+	}
+}
+
+
+impl Backend for RecordingBackend {
+	fn capabilities(&self) -> &Capabilities { &self.capabilities }
+
+	fn new_shader(&self, label: &str, _: &[(u32, &str)], defines: &Defines, _: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+		let program = Program(self.new_handle());
+		self.record(Command::NewShader { program, label: label.into(), defines: defines.clone() });
+		Ok(program)
+	}
+
+	fn new_spirv_shader(&self, label: &str, _: &[(u32, &SpirvModule)], constants: &SpecializationConstants, _: &[ExpectedResource]) -> Result<Program, Box<dyn Error>> {
+		self.capabilities.require(Feature::Spirv, "new_spirv_shader")?;
+
+		let program = Program(self.new_handle());
+		self.record(Command::NewSpirvShader { program, label: label.into(), constants: constants.clone() });
+		Ok(program)
+	}
+
+	fn use_program(&self, program: Program) { self.record(Command::UseProgram(program)) }
+
+	fn new_buffer(&self, label: &str) -> Buffer {
+		let buffer = Buffer(self.new_handle());
+		self.record(Command::NewBuffer { buffer, label: label.into() });
+		buffer
+	}
+
+	fn upload_buffer(&self, buffer: Buffer, data: &[u8], usage: BufferUsage) {
+		self.record(Command::UploadBuffer { buffer, data: data.to_vec(), usage });
+	}
+
+	fn bind_uniform_buffer(&self, binding: u32, buffer: Buffer) {
+		self.record(Command::BindUniformBuffer { binding, buffer });
+	}
+
+	fn bind_shader_storage_buffer(&self, binding: u32, buffer: Buffer) {
+		self.record(Command::BindShaderStorageBuffer { binding, buffer });
+	}
+
+	fn new_vertex_array(&self, label: &str) -> VertexArray {
+		let vertex_array = VertexArray(self.new_handle());
+		self.record(Command::NewVertexArray { vertex_array, label: label.into() });
+		vertex_array
+	}
+
+	fn set_index_buffer(&self, vertex_array: VertexArray, buffer: Buffer) {
+		self.record(Command::SetIndexBuffer { vertex_array, buffer });
+	}
+
+	fn new_texture(&self, label: &str, desc: &TextureDesc) -> Texture {
+		let texture = Texture::from_raw(self.new_handle(), *desc);
+		self.record(Command::NewTexture { texture, label: label.into() });
+		texture
+	}
+
+	fn clear_texture(&self, texture: Texture, value: Vec4) {
+		self.record(Command::ClearTexture { texture, value });
+	}
+
+	fn new_sampler(&self, desc: &SamplerDesc) -> Sampler {
+		let sampler = Sampler(self.new_handle());
+		self.record(Command::NewSampler { sampler, desc: *desc });
+		sampler
+	}
+
+	fn bind_texture(&self, binding: u32, texture: Texture) { self.record(Command::BindTexture { binding, texture }) }
+	fn bind_sampler(&self, binding: u32, sampler: Option<Sampler>) { self.record(Command::BindSampler { binding, sampler }) }
+	fn bind_image_rw(&self, binding: u32, texture: Texture) { self.record(Command::BindImage { binding, texture }) }
+
+	fn draw_mesh_tasks(&self, state: &PipelineState, offset: u32, count: u32) {
+		self.record(Command::DrawMeshTasks { state: *state, offset, count });
+	}
+
+	fn draw_instanced(&self, state: &PipelineState, vertex_count: u32, instance_count: u32) {
+		self.record(Command::DrawInstanced { state: *state, vertex_count, instance_count });
+	}
+
+	fn draw_indexed(&self, state: &PipelineState, vertex_array: VertexArray, index_count: u32) {
+		self.record(Command::DrawIndexed { state: *state, vertex_array, index_count });
+	}
+
+	fn dispatch_compute(&self, x: u32, y: u32, z: u32) { self.record(Command::DispatchCompute { x, y, z }) }
+	fn memory_barrier(&self, barriers: u32) { self.record(Command::MemoryBarrier(barriers)) }
+}
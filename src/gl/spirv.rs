@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+
+/// Modules compiled from `src/shaders` by build.rs, paired with the GLSL source they were compiled from.
+/// Empty if glslang wasn't available at build time.
+mod precompiled {
+	include!(concat!(env!("OUT_DIR"), "/spirv_shaders.rs"));
+}
+
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_DECORATE: u32 = 71;
+const DECORATION_SPEC_ID: u32 = 1;
+
+
+/// A validated SPIR-V module, ready to be passed to `Context::new_spirv_shader`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpirvModule {
+	words: Vec<u32>,
+}
+
+/// Values for `layout(constant_id = N)` constants, applied when a module is specialized.
+/// Values are stored as their raw 32 bit representation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SpecializationConstants (BTreeMap<u32, u32>);
+
+
+#[derive(Debug)]
+pub enum SpirvError {
+	/// The module isn't a whole number of words, or is shorter than a header
+	BadLength(usize),
+	BadMagic(u32),
+	MalformedInstruction(usize),
+}
+
+
+impl SpirvModule {
+	/// Accepts modules in either byte order, as produced by glslang or loaded from disk.
+	pub fn from_bytes(bytes: &[u8]) -> Result<SpirvModule, SpirvError> {
+		if bytes.len() % 4 != 0 {
+			return Err(SpirvError::BadLength(bytes.len()))
+		}
+
+		let words = bytes.chunks_exact(4)
+			.map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+			.collect();
+
+		SpirvModule::from_words(words)
+	}
+
+	pub fn from_words(mut words: Vec<u32>) -> Result<SpirvModule, SpirvError> {
+		if words.len() < HEADER_WORDS {
+			return Err(SpirvError::BadLength(words.len() * 4))
+		}
+
+		if words[0] == MAGIC.swap_bytes() {
+			for word in words.iter_mut() {
+				*word = word.swap_bytes();
+			}
+		}
+
+		if words[0] != MAGIC {
+			return Err(SpirvError::BadMagic(words[0]))
+		}
+
+		let module = SpirvModule { words };
+
+		// Walk the instruction stream once up front, so later walks can't run off the end
+		let mut offset = HEADER_WORDS;
+		while offset < module.words.len() {
+			let word_count = (module.words[offset] >> 16) as usize;
+			if word_count == 0 || offset + word_count > module.words.len() {
+				return Err(SpirvError::MalformedInstruction(offset))
+			}
+
+			offset += word_count;
+		}
+
+		Ok(module)
+	}
+
+	/// The module that build.rs compiled from `glsl`, if any.
+	/// `glsl` must be the unprocessed contents of a file in `src/shaders`.
+	pub fn precompiled(glsl: &str) -> Option<SpirvModule> {
+		precompiled::MODULES.iter()
+			.find(|&&(source, _)| source == glsl)
+			.and_then(|&(_, bytes)| SpirvModule::from_bytes(bytes).ok())
+	}
+
+	pub fn words(&self) -> &[u32] {
+		&self.words
+	}
+
+	pub fn size_in_bytes(&self) -> usize {
+		self.words.len() * 4
+	}
+
+	/// The ids of all specialization constants declared in the module.
+	pub fn specialization_ids(&self) -> Vec<u32> {
+		self.instructions()
+			.filter(|inst| inst[0] & 0xffff == OP_DECORATE)
+			.filter(|inst| inst.len() >= 4 && inst[2] == DECORATION_SPEC_ID)
+			.map(|inst| inst[3])
+			.collect()
+	}
+
+	fn instructions(&self) -> impl Iterator<Item=&[u32]> + '_ {
+		let mut remaining = &self.words[HEADER_WORDS..];
+
+		std::iter::from_fn(move || {
+			let word_count = (*remaining.first()? >> 16) as usize;
+			let (inst, rest) = remaining.split_at(word_count);
+			remaining = rest;
+			Some(inst)
+		})
+	}
+}
+
+
+impl SpecializationConstants {
+	pub fn new() -> Self {
+		SpecializationConstants::default()
+	}
+
+	pub fn with_u32(mut self, id: u32, value: u32) -> Self {
+		self.0.insert(id, value);
+		self
+	}
+
+	pub fn with_i32(self, id: u32, value: i32) -> Self {
+		self.with_u32(id, value as u32)
+	}
+
+	pub fn with_f32(self, id: u32, value: f32) -> Self {
+		self.with_u32(id, value.to_bits())
+	}
+
+	pub fn with_bool(self, id: u32, value: bool) -> Self {
+		self.with_u32(id, value as u32)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item=(u32, u32)> + '_ {
+		self.0.iter().map(|(&id, &value)| (id, value))
+	}
+
+	/// The constants declared by `module`, as separate id and value lists.
+	/// GL rejects specialization of constants a module doesn't declare.
+	pub(super) fn for_module(&self, module: &SpirvModule) -> (Vec<u32>, Vec<u32>) {
+		let declared = module.specialization_ids();
+
+		self.iter()
+			.filter(|(id, _)| declared.contains(id))
+			.unzip()
+	}
+}
+
+
+impl fmt::Display for SpirvError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SpirvError::BadLength(length) => write!(f, "invalid SPIR-V module length of {} bytes", length),
+			SpirvError::BadMagic(magic) => write!(f, "invalid SPIR-V magic number {:#010x}", magic),
+			SpirvError::MalformedInstruction(offset) => write!(f, "malformed SPIR-V instruction at word {}", offset),
+		}
+	}
+}
+
+impl std::error::Error for SpirvError {}
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn module_with_spec_ids(ids: &[u32]) -> Vec<u32> {
+		let mut words = vec![MAGIC, 0x0001_0000, 0, 100, 0];
+
+		for (target, &id) in ids.iter().enumerate() {
+			words.extend_from_slice(&[4 << 16 | OP_DECORATE, target as u32 + 1, DECORATION_SPEC_ID, id]);
+		}
+
+		// OpNop
+		words.push(1 << 16);
+		words
+	}
+
+	#[test]
+	fn parses_either_byte_order() {
+		let words = module_with_spec_ids(&[]);
+
+		let le_bytes = words.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect(): Vec<_>;
+		let be_bytes = words.iter().flat_map(|w| w.to_be_bytes().to_vec()).collect(): Vec<_>;
+
+		assert_eq!(SpirvModule::from_bytes(&le_bytes).unwrap().words(), &words[..]);
+		assert_eq!(SpirvModule::from_bytes(&be_bytes).unwrap().words(), &words[..]);
+	}
+
+	#[test]
+	fn rejects_invalid_modules() {
+		assert!(matches!(SpirvModule::from_bytes(&[0; 6]), Err(SpirvError::BadLength(6))));
+		assert!(matches!(SpirvModule::from_words(vec![0; 5]), Err(SpirvError::BadMagic(0))));
+
+		let mut truncated = module_with_spec_ids(&[3]);
+		truncated.truncate(truncated.len() - 2);
+		assert!(matches!(SpirvModule::from_words(truncated), Err(SpirvError::MalformedInstruction(5))));
+	}
+
+	#[test]
+	fn filters_constants_by_module() {
+		let module = SpirvModule::from_words(module_with_spec_ids(&[0, 7])).unwrap();
+		assert_eq!(module.specialization_ids(), [0, 7]);
+
+		let constants = SpecializationConstants::new()
+			.with_u32(0, 16)
+			.with_bool(3, true)
+			.with_f32(7, 1.0);
+
+		let (ids, values) = constants.for_module(&module);
+		assert_eq!(ids, [0, 7]);
+		assert_eq!(values, [16, 1.0f32.to_bits()]);
+	}
+}
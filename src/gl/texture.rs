@@ -0,0 +1,541 @@
+use common::math::Vec4;
+use super::{raw, debug, Context};
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+	Texture2D,
+	Texture2DArray,
+	Texture3D,
+	Cubemap,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+	R8,
+	Rg8,
+	Rgba8,
+	Srgba8,
+
+	R16F,
+	Rg16F,
+	Rgba16F,
+
+	R32F,
+	Rg32F,
+	Rgba32F,
+
+	R32U,
+
+	Depth32F,
+	Depth24Stencil8,
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureDesc {
+	pub kind: TextureKind,
+	pub format: TextureFormat,
+
+	pub width: u32,
+	pub height: u32,
+
+	/// Number of layers for array textures, or depth for 3D textures. Ignored for 2D textures and cubemaps.
+	pub depth: u32,
+
+	pub mip_levels: u32,
+
+	/// Only 2D textures may be multisampled
+	pub samples: u32,
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Texture {
+	handle: u32,
+	desc: TextureDesc,
+}
+
+
+/// A box within a single mip level. For arrays and cubemaps `z` selects layers/faces.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureRegion {
+	pub offset: (u32, u32, u32),
+	pub size: (u32, u32, u32),
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+	Nearest,
+	Linear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+	Repeat,
+	MirroredRepeat,
+	ClampToEdge,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SamplerDesc {
+	pub min_filter: Filter,
+	pub mag_filter: Filter,
+
+	/// Filter used between mip levels, or None to only sample the base level
+	pub mip_filter: Option<Filter>,
+	pub wrap: WrapMode,
+}
+
+/// Filtering and wrap state that can be bound alongside any texture, overriding the texture's own state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sampler (u32);
+
+
+
+impl TextureFormat {
+	pub fn internal_format(self) -> u32 {
+		match self {
+			TextureFormat::R8 => raw::R8,
+			TextureFormat::Rg8 => raw::RG8,
+			TextureFormat::Rgba8 => raw::RGBA8,
+			TextureFormat::Srgba8 => raw::SRGB8_ALPHA8,
+
+			TextureFormat::R16F => raw::R16F,
+			TextureFormat::Rg16F => raw::RG16F,
+			TextureFormat::Rgba16F => raw::RGBA16F,
+
+			TextureFormat::R32F => raw::R32F,
+			TextureFormat::Rg32F => raw::RG32F,
+			TextureFormat::Rgba32F => raw::RGBA32F,
+
+			TextureFormat::R32U => raw::R32UI,
+
+			TextureFormat::Depth32F => raw::DEPTH_COMPONENT32F,
+			TextureFormat::Depth24Stencil8 => raw::DEPTH24_STENCIL8,
+		}
+	}
+
+	/// The pixel format and type expected for CPU uploads of this format.
+	pub fn pixel_format(self) -> (u32, u32) {
+		match self {
+			TextureFormat::R8 => (raw::RED, raw::UNSIGNED_BYTE),
+			TextureFormat::Rg8 => (raw::RG, raw::UNSIGNED_BYTE),
+			TextureFormat::Rgba8 | TextureFormat::Srgba8 => (raw::RGBA, raw::UNSIGNED_BYTE),
+
+			TextureFormat::R16F => (raw::RED, raw::HALF_FLOAT),
+			TextureFormat::Rg16F => (raw::RG, raw::HALF_FLOAT),
+			TextureFormat::Rgba16F => (raw::RGBA, raw::HALF_FLOAT),
+
+			TextureFormat::R32F => (raw::RED, raw::FLOAT),
+			TextureFormat::Rg32F => (raw::RG, raw::FLOAT),
+			TextureFormat::Rgba32F => (raw::RGBA, raw::FLOAT),
+
+			TextureFormat::R32U => (raw::RED_INTEGER, raw::UNSIGNED_INT),
+
+			TextureFormat::Depth32F => (raw::DEPTH_COMPONENT, raw::FLOAT),
+			TextureFormat::Depth24Stencil8 => (raw::DEPTH_STENCIL, raw::UNSIGNED_INT_24_8),
+		}
+	}
+
+	pub fn bytes_per_pixel(self) -> usize {
+		match self {
+			TextureFormat::R8 => 1,
+			TextureFormat::Rg8 | TextureFormat::R16F => 2,
+			TextureFormat::Rgba8 | TextureFormat::Srgba8 | TextureFormat::Rg16F
+				| TextureFormat::R32F | TextureFormat::R32U
+				| TextureFormat::Depth32F | TextureFormat::Depth24Stencil8 => 4,
+			TextureFormat::Rgba16F | TextureFormat::Rg32F => 8,
+			TextureFormat::Rgba32F => 16,
+		}
+	}
+
+	pub fn is_depth(self) -> bool {
+		matches!(self, TextureFormat::Depth32F | TextureFormat::Depth24Stencil8)
+	}
+
+	pub fn has_stencil(self) -> bool {
+		matches!(self, TextureFormat::Depth24Stencil8)
+	}
+}
+
+
+impl TextureDesc {
+	pub fn new_2d(width: u32, height: u32, format: TextureFormat) -> Self {
+		TextureDesc {
+			kind: TextureKind::Texture2D,
+			format,
+			width, height,
+			depth: 1,
+			mip_levels: 1,
+			samples: 1,
+		}
+	}
+
+	pub fn new_2d_array(width: u32, height: u32, layers: u32, format: TextureFormat) -> Self {
+		TextureDesc { kind: TextureKind::Texture2DArray, depth: layers, ..TextureDesc::new_2d(width, height, format) }
+	}
+
+	pub fn new_3d(width: u32, height: u32, depth: u32, format: TextureFormat) -> Self {
+		TextureDesc { kind: TextureKind::Texture3D, depth, ..TextureDesc::new_2d(width, height, format) }
+	}
+
+	pub fn new_cubemap(size: u32, format: TextureFormat) -> Self {
+		TextureDesc { kind: TextureKind::Cubemap, depth: 6, ..TextureDesc::new_2d(size, size, format) }
+	}
+
+	pub fn with_mip_levels(self, mip_levels: u32) -> Self {
+		TextureDesc { mip_levels, ..self }
+	}
+
+	/// Allocates mips all the way down to 1x1.
+	pub fn with_full_mip_chain(self) -> Self {
+		let largest_dimension = match self.kind {
+			TextureKind::Texture3D => self.width.max(self.height).max(self.depth),
+			_ => self.width.max(self.height),
+		};
+
+		let mip_levels = 32 - largest_dimension.leading_zeros();
+		self.with_mip_levels(mip_levels)
+	}
+
+	pub fn with_samples(self, samples: u32) -> Self {
+		TextureDesc { samples, ..self }
+	}
+
+	/// The size of a mip level. Array layers and cubemap faces aren't reduced.
+	pub fn mip_size(&self, level: u32) -> (u32, u32, u32) {
+		let reduce = |v: u32| (v >> level).max(1);
+
+		match self.kind {
+			TextureKind::Texture2D => (reduce(self.width), reduce(self.height), 1),
+			TextureKind::Texture3D => (reduce(self.width), reduce(self.height), reduce(self.depth)),
+			TextureKind::Texture2DArray | TextureKind::Cubemap => (reduce(self.width), reduce(self.height), self.depth),
+		}
+	}
+
+	fn target(&self) -> u32 {
+		match self.kind {
+			TextureKind::Texture2D if self.samples > 1 => raw::TEXTURE_2D_MULTISAMPLE,
+			TextureKind::Texture2D => raw::TEXTURE_2D,
+			TextureKind::Texture2DArray => raw::TEXTURE_2D_ARRAY,
+			TextureKind::Texture3D => raw::TEXTURE_3D,
+			TextureKind::Cubemap => raw::TEXTURE_CUBE_MAP,
+		}
+	}
+}
+
+
+impl TextureRegion {
+	/// The whole of the given mip level.
+	pub fn mip(desc: &TextureDesc, level: u32) -> Self {
+		TextureRegion {
+			offset: (0, 0, 0),
+			size: desc.mip_size(level),
+		}
+	}
+
+	fn texel_count(&self) -> usize {
+		let (w, h, d) = self.size;
+		w as usize * h as usize * d as usize
+	}
+}
+
+
+impl SamplerDesc {
+	pub const LINEAR: SamplerDesc = SamplerDesc {
+		min_filter: Filter::Linear,
+		mag_filter: Filter::Linear,
+		mip_filter: Some(Filter::Linear),
+		wrap: WrapMode::Repeat,
+	};
+
+	pub const NEAREST: SamplerDesc = SamplerDesc {
+		min_filter: Filter::Nearest,
+		mag_filter: Filter::Nearest,
+		mip_filter: None,
+		wrap: WrapMode::Repeat,
+	};
+
+	pub fn with_wrap(self, wrap: WrapMode) -> Self {
+		SamplerDesc { wrap, ..self }
+	}
+}
+
+
+
+impl Context {
+	pub fn new_texture(&self, label: &str, desc: &TextureDesc) -> Texture {
+		Texture::new(label, desc)
+	}
+
+	pub fn new_sampler(&self, desc: &SamplerDesc) -> Sampler {
+		let (min_filter, mag_filter) = filter_modes(desc);
+		let wrap = wrap_mode(desc.wrap);
+
+		unsafe {
+			let mut sampler = 0;
+			raw::CreateSamplers(1, &mut sampler);
+			raw::SamplerParameteri(sampler, raw::TEXTURE_MIN_FILTER, min_filter as _);
+			raw::SamplerParameteri(sampler, raw::TEXTURE_MAG_FILTER, mag_filter as _);
+			raw::SamplerParameteri(sampler, raw::TEXTURE_WRAP_S, wrap as _);
+			raw::SamplerParameteri(sampler, raw::TEXTURE_WRAP_T, wrap as _);
+			raw::SamplerParameteri(sampler, raw::TEXTURE_WRAP_R, wrap as _);
+			Sampler(sampler)
+		}
+	}
+
+	pub fn bind_texture(&self, binding: u32, texture: Texture) {
+		unsafe {
+			raw::BindTextureUnit(binding, texture.handle);
+		}
+	}
+
+	/// Binds `sampler` to a texture unit, or restores the bound texture's own sampling state if None.
+	pub fn bind_sampler(&self, binding: u32, sampler: Option<Sampler>) {
+		unsafe {
+			raw::BindSampler(binding, sampler.map_or(0, |s| s.0));
+		}
+	}
+
+	/// Binds mip 0 of `texture` for image load/store. All layers are bound for layered textures.
+	pub fn bind_image_rw(&self, binding: u32, texture: Texture) {
+		let layered = match texture.desc.kind {
+			TextureKind::Texture2D => raw::FALSE,
+			_ => raw::TRUE,
+		};
+
+		unsafe {
+			let (level, layer) = (0, 0);
+			raw::BindImageTexture(binding, texture.handle, level, layered, layer,
+				raw::READ_WRITE, texture.desc.format.internal_format());
+		}
+	}
+}
+
+
+impl Texture {
+	pub(super) fn new(label: &str, desc: &TextureDesc) -> Texture {
+		assert!(desc.mip_levels >= 1, "Textures require at least one mip level");
+		assert!(desc.samples <= 1 || (desc.kind == TextureKind::Texture2D && desc.mip_levels == 1),
+			"Only single mip 2D textures can be multisampled");
+
+		let format = desc.format.internal_format();
+		let (width, height, depth) = (desc.width as i32, desc.height as i32, desc.depth as i32);
+		let levels = desc.mip_levels as i32;
+
+		unsafe {
+			let mut handle = 0;
+			raw::CreateTextures(desc.target(), 1, &mut handle);
+			debug::set_label(raw::TEXTURE, handle, label);
+
+			match desc.kind {
+				TextureKind::Texture2D if desc.samples > 1 =>
+					raw::TextureStorage2DMultisample(handle, desc.samples as _, format, width, height, raw::TRUE),
+
+				TextureKind::Texture2D | TextureKind::Cubemap =>
+					raw::TextureStorage2D(handle, levels, format, width, height),
+
+				TextureKind::Texture2DArray | TextureKind::Texture3D =>
+					raw::TextureStorage3D(handle, levels, format, width, height, depth),
+			}
+
+			let texture = Texture { handle, desc: *desc };
+
+			// Multisample textures have no sampler state
+			if desc.samples <= 1 {
+				let mip_filter = match desc.mip_levels {
+					1 => None,
+					_ => Some(Filter::Linear),
+				};
+
+				texture.set_sampler_state(&SamplerDesc {
+					mip_filter,
+					..SamplerDesc::LINEAR
+				});
+			}
+
+			texture
+		}
+	}
+
+	/// Wraps a handle without touching GL, for backends that don't create real textures.
+	pub(super) fn from_raw(handle: u32, desc: TextureDesc) -> Texture {
+		Texture { handle, desc }
+	}
+
+	pub fn desc(&self) -> &TextureDesc {
+		&self.desc
+	}
+
+	pub(super) fn handle(&self) -> u32 {
+		self.handle
+	}
+
+	pub(super) fn delete(self) {
+		unsafe {
+			raw::DeleteTextures(1, &self.handle);
+		}
+	}
+
+	/// Uploads a tightly packed region of texels. `data` must match the layout given by `TextureFormat::pixel_format`.
+	pub fn upload_region<T: Copy>(&self, level: u32, region: TextureRegion, data: &[T]) {
+		assert!(level < self.desc.mip_levels, "Mip level {} out of range", level);
+		assert!(self.desc.samples <= 1, "Can't upload to multisampled textures");
+
+		let expected_size = region.texel_count() * self.desc.format.bytes_per_pixel();
+		let data_size = data.len() * std::mem::size_of::<T>();
+		assert_eq!(data_size, expected_size, "Texture upload size mismatch");
+
+		let (format, ty) = self.desc.format.pixel_format();
+		let (x, y, z) = (region.offset.0 as i32, region.offset.1 as i32, region.offset.2 as i32);
+		let (w, h, d) = (region.size.0 as i32, region.size.1 as i32, region.size.2 as i32);
+
+		unsafe {
+			raw::PixelStorei(raw::UNPACK_ALIGNMENT, 1);
+
+			match self.desc.kind {
+				TextureKind::Texture2D =>
+					raw::TextureSubImage2D(self.handle, level as _, x, y, w, h, format, ty, data.as_ptr() as *const _),
+
+				TextureKind::Texture2DArray | TextureKind::Texture3D | TextureKind::Cubemap =>
+					raw::TextureSubImage3D(self.handle, level as _, x, y, z, w, h, d, format, ty, data.as_ptr() as *const _),
+			}
+		}
+	}
+
+	/// Uploads an entire mip level.
+	pub fn upload<T: Copy>(&self, level: u32, data: &[T]) {
+		self.upload_region(level, TextureRegion::mip(&self.desc, level), data);
+	}
+
+	/// Reads back an entire mip level, in the layout given by `TextureFormat::pixel_format`.
+	/// Stalls until all rendering to the texture has finished.
+	pub fn download(&self, level: u32) -> Vec<u8> {
+		assert!(level < self.desc.mip_levels, "Mip level {} out of range", level);
+		assert!(self.desc.samples <= 1, "Can't download multisampled textures");
+
+		let size = TextureRegion::mip(&self.desc, level).texel_count() * self.desc.format.bytes_per_pixel();
+		let mut data = vec![0u8; size];
+
+		let (format, ty) = self.desc.format.pixel_format();
+
+		unsafe {
+			raw::PixelStorei(raw::PACK_ALIGNMENT, 1);
+			raw::GetTextureImage(self.handle, level as _, format, ty, size as _, data.as_mut_ptr() as *mut _);
+		}
+
+		data
+	}
+
+	/// Regenerates all mip levels from the base level.
+	pub fn generate_mips(&self) {
+		unsafe {
+			raw::GenerateTextureMipmap(self.handle);
+		}
+	}
+
+	/// Clears every mip level to `value`. Depth formats use `value.x` as depth, and `value.y` as stencil if present.
+	pub fn clear(&self, value: Vec4) {
+		let format = self.desc.format;
+
+		for level in 0..self.desc.mip_levels {
+			unsafe {
+				match format {
+					TextureFormat::Depth32F => {
+						raw::ClearTexImage(self.handle, level as _, raw::DEPTH_COMPONENT, raw::FLOAT, &value.x as *const f32 as _);
+					}
+
+					TextureFormat::Depth24Stencil8 => {
+						// FLOAT_32_UNSIGNED_INT_24_8_REV is laid out as a float followed by a stencil byte in the low bits
+						let depth_stencil = [value.x.to_bits(), value.y as u32 & 0xFF];
+						raw::ClearTexImage(self.handle, level as _, raw::DEPTH_STENCIL, raw::FLOAT_32_UNSIGNED_INT_24_8_REV, depth_stencil.as_ptr() as _);
+					}
+
+					TextureFormat::R32U => {
+						let values = [value.x as u32, value.y as u32, value.z as u32, value.w as u32];
+						raw::ClearTexImage(self.handle, level as _, raw::RGBA_INTEGER, raw::UNSIGNED_INT, values.as_ptr() as _);
+					}
+
+					_ => {
+						let values = [value.x, value.y, value.z, value.w];
+						raw::ClearTexImage(self.handle, level as _, raw::RGBA, raw::FLOAT, values.as_ptr() as _);
+					}
+				}
+			}
+		}
+	}
+
+	pub fn set_filter(&self, min_linear: bool, mag_linear: bool) {
+		let min = match min_linear {
+			true => raw::LINEAR,
+			false => raw::NEAREST,
+		};
+
+		let mag = match mag_linear {
+			true => raw::LINEAR,
+			false => raw::NEAREST,
+		};
+
+		unsafe {
+			raw::TextureParameteri(self.handle, raw::TEXTURE_MIN_FILTER, min as _);
+			raw::TextureParameteri(self.handle, raw::TEXTURE_MAG_FILTER, mag as _);
+		}
+	}
+
+	pub fn set_wrap(&self, wrap: bool) {
+		let mode = match wrap {
+			true => raw::REPEAT,
+			false => raw::CLAMP_TO_EDGE,
+		};
+
+		unsafe {
+			raw::TextureParameteri(self.handle, raw::TEXTURE_WRAP_S, mode as _);
+			raw::TextureParameteri(self.handle, raw::TEXTURE_WRAP_T, mode as _);
+			raw::TextureParameteri(self.handle, raw::TEXTURE_WRAP_R, mode as _);
+		}
+	}
+
+	/// Sets the sampling state used when no sampler object is bound.
+	pub fn set_sampler_state(&self, desc: &SamplerDesc) {
+		let (min_filter, mag_filter) = filter_modes(desc);
+		let wrap = wrap_mode(desc.wrap);
+
+		unsafe {
+			raw::TextureParameteri(self.handle, raw::TEXTURE_MIN_FILTER, min_filter as _);
+			raw::TextureParameteri(self.handle, raw::TEXTURE_MAG_FILTER, mag_filter as _);
+			raw::TextureParameteri(self.handle, raw::TEXTURE_WRAP_S, wrap as _);
+			raw::TextureParameteri(self.handle, raw::TEXTURE_WRAP_T, wrap as _);
+			raw::TextureParameteri(self.handle, raw::TEXTURE_WRAP_R, wrap as _);
+		}
+	}
+}
+
+
+
+fn filter_modes(desc: &SamplerDesc) -> (u32, u32) {
+	let min_filter = match (desc.min_filter, desc.mip_filter) {
+		(Filter::Nearest, None) => raw::NEAREST,
+		(Filter::Linear, None) => raw::LINEAR,
+		(Filter::Nearest, Some(Filter::Nearest)) => raw::NEAREST_MIPMAP_NEAREST,
+		(Filter::Nearest, Some(Filter::Linear)) => raw::NEAREST_MIPMAP_LINEAR,
+		(Filter::Linear, Some(Filter::Nearest)) => raw::LINEAR_MIPMAP_NEAREST,
+		(Filter::Linear, Some(Filter::Linear)) => raw::LINEAR_MIPMAP_LINEAR,
+	};
+
+	let mag_filter = match desc.mag_filter {
+		Filter::Nearest => raw::NEAREST,
+		Filter::Linear => raw::LINEAR,
+	};
+
+	(min_filter, mag_filter)
+}
+
+fn wrap_mode(wrap: WrapMode) -> u32 {
+	match wrap {
+		WrapMode::Repeat => raw::REPEAT,
+		WrapMode::MirroredRepeat => raw::MIRRORED_REPEAT,
+		WrapMode::ClampToEdge => raw::CLAMP_TO_EDGE,
+	}
+}
@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use common::math::*;
+use crate::{gl, perf, Camera, Scene};
+
+
+/// Renders a fixed number of frames from a scripted camera into an offscreen target, and writes each to disk.
+/// Enabled with `--headless [--frames N] [--size WxH] [--output DIR]`.
+#[derive(Clone, Debug)]
+pub struct HeadlessOptions {
+	pub frames: u32,
+	pub width: u32,
+	pub height: u32,
+	pub output_dir: PathBuf,
+}
+
+
+impl HeadlessOptions {
+	/// Removes all headless arguments from `args`, leaving any others in place.
+	/// Returns None if `--headless` isn't present.
+	pub fn take_from_args(args: &mut Vec<String>) -> Result<Option<HeadlessOptions>, String> {
+		let mut options = HeadlessOptions {
+			frames: 60,
+			width: 700,
+			height: 700,
+			output_dir: PathBuf::from("headless"),
+		};
+
+		let mut headless = false;
+		let mut first_option = None;
+		let mut index = 0;
+
+		while index < args.len() {
+			let takes_value = match args[index].as_str() {
+				"--headless" => {
+					args.remove(index);
+					headless = true;
+					continue
+				}
+
+				"--frames" | "--size" | "--output" => true,
+				_ => false,
+			};
+
+			if !takes_value {
+				index += 1;
+				continue
+			}
+
+			let arg = args.remove(index);
+			if index >= args.len() {
+				return Err(format!("{} expects a value", arg))
+			}
+
+			let value = args.remove(index);
+
+			if first_option.is_none() {
+				first_option = Some(arg.clone());
+			}
+
+			match arg.as_str() {
+				"--frames" => {
+					options.frames = value.parse()
+						.map_err(|_| format!("Invalid frame count '{}'", value))?;
+				}
+
+				"--size" => {
+					let (width, height) = parse_size(&value)
+						.ok_or_else(|| format!("Invalid size '{}', expected WxH", value))?;
+
+					options.width = width;
+					options.height = height;
+				}
+
+				"--output" => { options.output_dir = value.into() }
+				_ => unreachable!(),
+			}
+		}
+
+		match (headless, first_option) {
+			(true, _) => Ok(Some(options)),
+			(false, Some(option)) => Err(format!("{} requires --headless", option)),
+			(false, None) => Ok(None),
+		}
+	}
+}
+
+
+/// Falls back to SDLs offscreen video driver when there's no display to create a hidden window on.
+/// Must be called before SDL is initialised.
+pub fn select_video_driver() {
+	let has_display = std::env::var_os("DISPLAY").is_some()
+		|| std::env::var_os("WAYLAND_DISPLAY").is_some();
+
+	if !has_display && std::env::var_os("SDL_VIDEODRIVER").is_none() {
+		std::env::set_var("SDL_VIDEODRIVER", "offscreen");
+	}
+}
+
+
+pub fn run(gl_ctx: &gl::Context, scene: &mut Scene, inst: &mut perf::Instrumenter, options: &HeadlessOptions) -> Result<(), Box<dyn Error>> {
+	std::fs::create_dir_all(&options.output_dir)?;
+
+	for frame in 0..options.frames {
+		let t = frame as f32 / options.frames as f32;
+
+		scene.paint_system.paint(scripted_brush_pos(t));
+		scene.render(gl_ctx, inst, &scripted_camera(t), true);
+
+		inst.end_frame();
+
+		let (width, height) = scene.target.size();
+		let pixels = scene.target.color_attachment(0).download(0);
+
+		let path = options.output_dir.join(format!("frame_{:04}.ppm", frame));
+		write_ppm(&path, width, height, &pixels)?;
+	}
+
+	inst.finish_trace();
+	inst.report();
+	log::info!("Wrote {} frames to {}", options.frames, options.output_dir.display());
+
+	Ok(())
+}
+
+
+/// Orbits the origin once over the course of the run.
+fn scripted_camera(t: f32) -> Camera {
+	Camera {
+		yaw: t * 2.0 * PI,
+		..Camera::new()
+	}
+}
+
+/// Paints a circle around the origin, so paint and terrain have something to show.
+pub fn scripted_brush_pos(t: f32) -> Vec2 {
+	let angle = t * 2.0 * PI;
+	Vec2::new(angle.cos(), angle.sin()) * 30.0
+}
+
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+	let mut parts = size.split('x');
+	let width = parts.next()?.parse().ok()?;
+	let height = parts.next()?.parse().ok()?;
+
+	match (parts.next(), width, height) {
+		(None, w, h) if w > 0 && h > 0 => Some((w, h)),
+		_ => None,
+	}
+}
+
+/// Writes tightly packed RGBA8 pixels as a binary PPM, flipping from GL's bottom-up row order.
+fn write_ppm(path: &Path, width: u32, height: u32, rgba: &[u8]) -> std::io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	write!(file, "P6\n{} {}\n255\n", width, height)?;
+
+	let row_size = width as usize * 4;
+
+	for row in rgba.chunks_exact(row_size).rev() {
+		for pixel in row.chunks_exact(4) {
+			file.write_all(&pixel[..3])?;
+		}
+	}
+
+	file.flush()
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::args::owned as args;
+
+	fn parse(list: &[&str]) -> Result<Option<HeadlessOptions>, String> {
+		HeadlessOptions::take_from_args(&mut args(list))
+	}
+
+	#[test]
+	fn takes_headless_args() {
+		let options = parse(&["--headless"]).unwrap().unwrap();
+		assert_eq!((options.frames, options.width, options.height), (60, 700, 700));
+		assert_eq!(options.output_dir, PathBuf::from("headless"));
+
+		let mut list = args(&["--size", "320x200", "--trace", "--headless", "--frames", "5", "--output", "out"]);
+		let options = HeadlessOptions::take_from_args(&mut list).unwrap().unwrap();
+
+		assert_eq!(list, args(&["--trace"]));
+		assert_eq!((options.frames, options.width, options.height), (5, 320, 200));
+		assert_eq!(options.output_dir, PathBuf::from("out"));
+
+		assert!(parse(&[]).unwrap().is_none());
+		assert!(parse(&["--benchmark"]).unwrap().is_none());
+	}
+
+	#[test]
+	fn rejects_invalid_args() {
+		assert_eq!(parse(&["--headless", "--size"]).unwrap_err(), "--size expects a value");
+		assert_eq!(parse(&["--headless", "--frames", "many"]).unwrap_err(), "Invalid frame count 'many'");
+		assert_eq!(parse(&["--frames", "5"]).unwrap_err(), "--frames requires --headless");
+
+		for size in ["0x10", "10x0", "10x10x10", "10", "10x", "x10", "-10x10", "axb"].iter() {
+			assert!(parse(&["--headless", "--size", size]).is_err(), "{}", size);
+		}
+
+		assert_eq!(parse_size("1x1"), Some((1, 1)));
+	}
+}
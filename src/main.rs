@@ -9,87 +9,299 @@ pub mod particles;
 pub mod terrain;
 pub mod paint;
 
+pub mod text;
+pub mod overlay;
+
+pub mod args;
+pub mod headless;
+pub mod benchmark;
+
 use std::error::Error;
 use common::math::*;
 
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, gl::GpuLayout)]
 struct Uniforms {
 	projection_view: Mat4,
 	camera_up: Vec4,
 	camera_right: Vec4,
-	world_size: Vec2, _0: Vec2,
-	// NOTE: align to Vec4s
+	world_size: Vec2,
 }
 
+pub const GLOBAL_UNIFORMS: gl::ExpectedResource = gl::ExpectedResource::uniform_block("UniformData", 0, <Uniforms as gl::GpuLayout>::STD140.size)
+	.with_members(<Uniforms as gl::GpuLayout>::FIELDS_STD140);
 
 
-fn init_window(sdl_video: &sdl2::VideoSubsystem) -> Result<(sdl2::video::Window, gl::Context), Box<dyn Error>> {
+
+fn init_window(sdl_video: &sdl2::VideoSubsystem, width: u32, height: u32, hidden: bool) -> Result<(sdl2::video::Window, gl::Context), Box<dyn Error>> {
 	let gl_attr = sdl_video.gl_attr();
 	gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
 	gl_attr.set_context_version(4, 5);
 	gl_attr.set_context_flags().debug().set();
 
-	let window = sdl_video.window("mesh", 700, 700)
-		.position_centered()
-		.resizable()
-		.opengl()
-		.build()?;
+	let mut window_builder = sdl_video.window("mesh", width, height);
+	window_builder.opengl();
+
+	if hidden {
+		window_builder.hidden();
+	} else {
+		window_builder.position_centered().resizable();
+	}
+
+	let window = window_builder.build()?;
 
 	let gl_ctx = window.gl_create_context()?;
 	window.gl_make_current(&gl_ctx)?;
 
 	gl::raw::load_with(|s| sdl_video.gl_get_proc_address(s) as *const _);
 
-	assert!(sdl_video.gl_extension_supported("GL_NV_mesh_shader"));
+	let mut gl_ctx = gl::Context::new(gl_ctx);
+
+	for feature in gl_ctx.capabilities().unsupported_features() {
+		log::warn!("Unsupported feature: {}", feature.name());
+	}
+
+	log::info!("Using render path: {:?}", gl_ctx.capabilities().render_path());
+
+	let program_cache = gl::ProgramCache::new(std::env::temp_dir().join("mesh-shaders-program-cache"), gl_ctx.capabilities());
+	program_cache.remove_stale_entries();
+	gl_ctx.set_program_cache(Some(program_cache));
+
+	Ok((window, gl_ctx))
+}
+
+
+/// Prints log records to stderr, prefixed with their level and target.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+	fn enabled(&self, _: &log::Metadata<'_>) -> bool { true }
+
+	fn log(&self, record: &log::Record<'_>) {
+		eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+	}
+
+	fn flush(&self) {}
+}
+
+
+pub struct Camera {
+	pub pos: Vec3,
+	pub yaw: f32,
+	pub pitch: f32,
+	pub zoom: f32,
+}
+
+impl Camera {
+	pub fn new() -> Camera {
+		Camera {
+			pos: Vec3::from_y(2.0),
+			yaw: 0.0,
+			pitch: -PI / 5.0,
+			zoom: 12.0,
+		}
+	}
+
+	pub fn yaw_matrix(&self) -> Mat4 {
+		Mat4::yrot(self.yaw)
+	}
+
+	pub fn orientation(&self) -> Mat4 {
+		self.yaw_matrix() * Mat4::xrot(self.pitch)
+	}
+}
+
 
+/// Which subsystems are drawn. Toggled with the number keys in interactive mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SceneToggles {
+	pub scene_view: bool,
+	pub particles: bool,
+	pub paint: bool,
+	pub terrain: bool,
+}
+
+impl Default for SceneToggles {
+	fn default() -> Self {
+		SceneToggles {
+			scene_view: false,
+			particles: false,
+			paint: true,
+			terrain: true,
+		}
+	}
+}
+
+
+/// All render subsystems, and the offscreen target they draw into.
+pub struct Scene {
+	uniforms: Uniforms,
+	uniform_buffer: gl::Buffer,
+
+	pub scene_view: scene_view::SceneView,
+	pub particles: particles::ParticleSystem,
+	pub paint_system: paint::PaintSystem,
+	pub terrain: terrain::Terrain,
 
-	Ok((window, gl::Context::new(gl_ctx)))
+	pub target: gl::RenderTarget,
+	pub toggles: SceneToggles,
 }
 
+impl Scene {
+	pub fn new(gl_ctx: &mut gl::Context, width: u32, height: u32) -> Result<Scene, Box<dyn Error>> {
+		gl_ctx.add_shader_import("global", include_str!("shaders/global.common.glsl"))?;
+		gl_ctx.add_shader_import("particle", include_str!("shaders/particle.common.glsl"))?;
+
+		let uniforms = Uniforms {
+			projection_view: Mat4::ident(),
+			camera_up: Vec4::from_y(1.0),
+			camera_right: Vec4::from_x(1.0),
+			world_size: Vec2::splat(200.0),
+		};
+
+		let gl_ctx = &*gl_ctx;
+
+		let uniform_buffer = gl_ctx.new_buffer("global uniforms");
+		uniform_buffer.upload_std140(&[uniforms], gl::BufferUsage::Stream);
+		gl_ctx.bind_uniform_buffer(GLOBAL_UNIFORMS.binding, uniform_buffer);
+
+		let target = gl::RenderTarget::new(gl_ctx, "scene", gl::RenderTargetDesc {
+			color_formats: vec![gl::TextureFormat::Rgba8],
+			depth_format: Some(gl::TextureFormat::Depth32F),
+			samples: 1,
+		}, width, height)?;
+
+		Ok(Scene {
+			uniforms,
+			uniform_buffer,
+
+			scene_view: scene_view::SceneView::new(gl_ctx)?,
+			particles: particles::ParticleSystem::new(gl_ctx)?,
+			paint_system: paint::PaintSystem::new(gl_ctx)?,
+			terrain: terrain::Terrain::new(gl_ctx)?,
+
+			target,
+			toggles: SceneToggles::default(),
+		})
+	}
+
+	/// The projection view matrix used for the most recent frame.
+	pub fn projection_view(&self) -> Mat4 {
+		self.uniforms.projection_view
+	}
+
+	/// Runs simulations if `update_enabled`, and draws all enabled subsystems into `self.target`.
+	pub fn render(&mut self, gl_ctx: &gl::Context, inst: &mut perf::Instrumenter, camera: &Camera, update_enabled: bool) {
+		let (width, height) = self.target.size();
+		let aspect = width as f32 / height as f32;
+
+		let camera_orientation = camera.orientation();
+
+		self.uniforms.camera_up = camera_orientation * Vec4::from_y(1.0);
+		self.uniforms.camera_right = camera_orientation * Vec4::from_x(1.0);
+
+		self.uniforms.projection_view = Mat4::perspective(PI/3.0, aspect, 0.1, 1000.0)
+			* Mat4::translate(Vec3::from_z(-camera.zoom))
+			* camera_orientation.inverse()
+			* Mat4::translate(-camera.pos);
+
+		self.uniform_buffer.upload_std140(&[self.uniforms], gl::BufferUsage::Stream);
+
+		let toggles = self.toggles;
+
+		if update_enabled {
+			perf_scope!(inst, "update");
+
+			if toggles.particles {
+				self.particles.update(gl_ctx, inst, self.paint_system.resources());
+			}
+
+			self.paint_system.update(gl_ctx, inst);
+		}
+
+		perf_scope!(inst, "draw");
+
+		self.target.bind(gl_ctx);
+		gl_ctx.clear(Vec4::splat(1.0));
+
+		if toggles.scene_view {
+			self.scene_view.draw(gl_ctx, inst);
+		}
+
+		if toggles.terrain {
+			self.terrain.draw(gl_ctx, inst, self.paint_system.resources());
+		}
+
+		if toggles.paint {
+			self.paint_system.draw(gl_ctx, inst);
+		}
+
+		if toggles.particles {
+			self.particles.draw(gl_ctx, inst);
+		}
+	}
+}
 
 
 
 fn main() -> Result<(), Box<dyn Error>> {
 	std::env::set_var("RUST_BACKTRACE", "1");
 
+	log::set_logger(&StderrLogger).map_err(|e| e.to_string())?;
+	log::set_max_level(log::LevelFilter::Info);
+
+	let mut args = std::env::args().skip(1).collect(): Vec<_>;
+	let trace_request = perf::TraceRequest::take_from_args(&mut args)?;
+	let benchmark_options = benchmark::BenchmarkOptions::take_from_args(&mut args)?;
+	let headless_options = headless::HeadlessOptions::take_from_args(&mut args)?;
+	args::reject_remaining(&args)?;
+
+	if benchmark_options.is_some() && headless_options.is_some() {
+		return Err("--benchmark can't be combined with --headless".into())
+	}
+
+	let offscreen = headless_options.is_some() || benchmark_options.is_some();
+
+	if offscreen {
+		headless::select_video_driver();
+	}
+
 	let sdl = sdl2::init()?;
 	let sdl_video = sdl.video()?;
 
-	let (window, mut gl_ctx) = init_window(&sdl_video)?;
+	let (width, height) = match (&headless_options, &benchmark_options) {
+		(Some(options), _) => (options.width, options.height),
+		(_, Some(options)) => (options.width, options.height),
+		_ => (700, 700),
+	};
+
+	let (window, mut gl_ctx) = init_window(&sdl_video, width, height, offscreen)?;
 
 	let mut instrumenter = perf::Instrumenter::new(&gl_ctx);
 
-	gl_ctx.add_shader_import("global", include_str!("shaders/global.common.glsl"));
-	gl_ctx.add_shader_import("particle", include_str!("shaders/particle.common.glsl"));
+	if let Some(request) = &trace_request {
+		instrumenter.start_trace(request);
+	}
 
-	let mut uniforms = Uniforms {
-		projection_view: Mat4::ident(),
-		camera_up: Vec4::from_y(1.0),
-		camera_right: Vec4::from_x(1.0),
-		world_size: Vec2::splat(200.0),
+	if let Some(options) = benchmark_options {
+		let mut scene = Scene::new(&mut gl_ctx, options.width, options.height)?;
+		return benchmark::run(&gl_ctx, &mut scene, &mut instrumenter, &options)
+	}
 
-		_0: Vec2::zero(),
-	};
+	if let Some(options) = headless_options {
+		let mut scene = Scene::new(&mut gl_ctx, options.width, options.height)?;
+		return headless::run(&gl_ctx, &mut scene, &mut instrumenter, &options)
+	}
 
-	let uniform_buffer = gl_ctx.new_buffer();
-	uniform_buffer.upload(&[uniforms], gl::BufferUsage::Stream);
-	gl_ctx.bind_uniform_buffer(0, uniform_buffer);
+	let (drawable_width, drawable_height) = window.drawable_size();
+	let mut scene = Scene::new(&mut gl_ctx, drawable_width, drawable_height)?;
 
-	let scene_view = scene_view::SceneView::new(&gl_ctx)?;
-	let particles = particles::ParticleSystem::new(&gl_ctx);
-	let mut paint_system = paint::PaintSystem::new(&gl_ctx);
-	let terrain = terrain::Terrain::new(&gl_ctx);
+	let mut overlay = overlay::ProfilerOverlay::new(&gl_ctx)?;
+	instrumenter.add_sink(overlay.sink());
 
 	let mut event_pump = sdl.event_pump()?;
-	let mut aspect = 1.0f32;
-	let mut zoom = 12.0f32;
+	let mut camera = Camera::new();
 
-	let mut yaw = 0.0f32;
-	let mut pitch = -PI / 5.0;
-
-	let mut camera_pos = Vec3::from_y(2.0);
 	let mut forward_pressed = false;
 	let mut back_pressed = false;
 	let mut left_pressed = false;
@@ -102,11 +314,6 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 	let mut wireframe_enabled = false;
 
-	let mut scene_view_enabled = false;
-	let mut particles_enabled = false;
-	let mut paint_enabled = true;
-	let mut terrain_enabled = true;
-
 	let mut mouse_world_pos = Vec2::zero();
 
 	'main: loop {
@@ -117,26 +324,30 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 			match event {
 				Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'main,
-				Event::Window{ win_event: WindowEvent::Resized(w, h), .. } => unsafe {
-					gl::raw::Viewport(0, 0, w as _, h as _);
-					aspect = w as f32 / h as f32;
+				Event::Window{ win_event: WindowEvent::Resized(..), .. } => {
+					let (w, h) = window.drawable_size();
+
+					// Minimised windows can report zero sized drawables
+					if w > 0 && h > 0 {
+						scene.target.resize(w, h)?;
+					}
 				}
 
 				Event::MouseWheel { y, .. } => {
-					zoom = (zoom.log2() - y as f32 / 5.0).exp2();
+					camera.zoom = (camera.zoom.log2() - y as f32 / 5.0).exp2();
 				}
 
 				Event::MouseMotion { xrel, yrel, x, y, .. } => {
 					if left_down {
-						yaw += xrel as f32 * 0.005;
-						pitch = (pitch - yrel as f32 * 0.005).clamp(-PI, PI);
+						camera.yaw += xrel as f32 * 0.005;
+						camera.pitch = (camera.pitch - yrel as f32 * 0.005).clamp(-PI, PI);
 					}
 
 					let (w, h) = window.drawable_size();
 					let mouse_x =  x as f32 / w as f32 * 2.0 - 1.0;
 					let mouse_y = -(y as f32 / h as f32 * 2.0 - 1.0);
 
-					let proj_view_inv = uniforms.projection_view.inverse();
+					let proj_view_inv = scene.projection_view().inverse();
 
 					let near_point = proj_view_inv * Vec4::new(mouse_x, mouse_y, -1.0, 1.0);
 					let near_point = near_point.to_vec3() / near_point.w;
@@ -170,10 +381,18 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 				Event::KeyDown { keycode: Some(Keycode::Space), .. } => { update_enabled = !update_enabled }
 				Event::KeyDown { keycode: Some(keycode), .. } => match keycode {
-					Keycode::Num1 => { scene_view_enabled = !scene_view_enabled }
-					Keycode::Num2 => { particles_enabled = !particles_enabled }
-					Keycode::Num3 => { paint_enabled = !paint_enabled }
-					Keycode::Num4 => { terrain_enabled = !terrain_enabled }
+					Keycode::Num1 => { scene.toggles.scene_view = !scene.toggles.scene_view }
+					Keycode::Num2 => { scene.toggles.particles = !scene.toggles.particles }
+					Keycode::Num3 => { scene.toggles.paint = !scene.toggles.paint }
+					Keycode::Num4 => { scene.toggles.terrain = !scene.toggles.terrain }
+
+					Keycode::LeftBracket => scene.terrain.set_subdivisions(&gl_ctx, scene.terrain.subdivisions() - 1)?,
+					Keycode::RightBracket => scene.terrain.set_subdivisions(&gl_ctx, scene.terrain.subdivisions() + 1)?,
+
+					Keycode::P => instrumenter.report(),
+					Keycode::R => instrumenter.reset_stats(),
+					Keycode::T => instrumenter.start_trace(&perf::TraceRequest::default()),
+					Keycode::O => overlay.toggle(),
 
 					Keycode::Z => {
 						wireframe_enabled = !wireframe_enabled;
@@ -200,77 +419,32 @@ fn main() -> Result<(), Box<dyn Error>> {
 		}
 
 		if right_down {
-			paint_system.paint(mouse_world_pos);
+			scene.paint_system.paint(mouse_world_pos);
 		}
 
-		let camera_yaw_mat = Mat4::yrot(yaw);
-		let camera_orientation = camera_yaw_mat * Mat4::xrot(pitch);
-
 		let move_speed = match shift_pressed {
 			true => 15.0,
 			false => 5.0,
 		};
 
+		let camera_yaw_mat = camera.yaw_matrix();
 		let cam_move_fwd = camera_yaw_mat * Vec3::from_z(-move_speed / 60.0);
 		let cam_move_right = camera_yaw_mat * Vec3::from_x(move_speed / 60.0);
 
-		if forward_pressed { camera_pos += cam_move_fwd }
-		if back_pressed { camera_pos -= cam_move_fwd }
-		if left_pressed { camera_pos -= cam_move_right }
-		if right_pressed { camera_pos += cam_move_right }
-
-
-		uniforms.camera_up = camera_orientation * Vec4::from_y(1.0);
-		uniforms.camera_right = camera_orientation * Vec4::from_x(1.0);
-
-		uniforms.projection_view = Mat4::perspective(PI/3.0, aspect, 0.1, 1000.0)
-			* Mat4::translate(Vec3::from_z(-zoom))
-			* camera_orientation.inverse()
-			* Mat4::translate(-camera_pos);
-
-		uniform_buffer.upload(&[uniforms], gl::BufferUsage::Stream);
-
-		if update_enabled {
-			if particles_enabled {
-				particles.update(&gl_ctx, &mut instrumenter, paint_system.resources());
-			}
-
-			paint_system.update(&gl_ctx, &mut instrumenter);
-		}
-
-		unsafe {
-			gl::raw::ClearColor(1.0, 1.0, 1.0, 1.0);
-			gl::raw::Clear(gl::raw::COLOR_BUFFER_BIT | gl::raw::DEPTH_BUFFER_BIT);
-		}
-
-		if scene_view_enabled {
-			scene_view.draw(&gl_ctx, &mut instrumenter);
-		}
-
-		if terrain_enabled {
-			terrain.draw(&gl_ctx, &mut instrumenter, paint_system.resources());
-		}
+		if forward_pressed { camera.pos += cam_move_fwd }
+		if back_pressed { camera.pos -= cam_move_fwd }
+		if left_pressed { camera.pos -= cam_move_right }
+		if right_pressed { camera.pos += cam_move_right }
 
-		if paint_enabled {
-			paint_system.draw(&gl_ctx, &mut instrumenter);
-		}
+		scene.render(&gl_ctx, &mut instrumenter, &camera, update_enabled);
+		scene.target.blit_color(gl::Framebuffer::DEFAULT, window.drawable_size());
 
-		if particles_enabled {
-			particles.draw(&gl_ctx, &mut instrumenter);
-		}
+		gl_ctx.bind_framebuffer(gl::Framebuffer::DEFAULT, window.drawable_size());
+		overlay.draw(&gl_ctx, &mut instrumenter, window.drawable_size());
 
+		instrumenter.measure_swap(|| window.gl_swap_window());
 		instrumenter.end_frame();
-
-		window.gl_swap_window();
 	}
 
 	Ok(())
 }
-
-
-
-
-
-
-
-
@@ -5,13 +5,16 @@ pub struct MeshData<V> {
 	pub vertex_data: Vec<V>,
 	pub meshlet_data: Vec<u8>,
 	pub num_meshlets: usize,
+
+	/// Meshlets expanded into a plain triangle list, for drawing without mesh shaders
+	pub indices: Vec<u32>,
 }
 
 
 
 
-const MAX_MESHLET_TRIANGLES: usize = 126;
-const MAX_MESHLET_VERTICES: usize = 64;
+pub const MAX_MESHLET_TRIANGLES: usize = 126;
+pub const MAX_MESHLET_VERTICES: usize = 64;
 
 
 pub struct MeshletBuilder<V> {
@@ -114,6 +117,8 @@ impl<V: Clone> MeshletBuilder<V> {
 			});
 		}
 
+		let indices = self.expand_indices();
+
 		// pad to 32b
 		// if self.vertex_indices.len() % 2 == 1 {
 		// 	self.vertex_indices.push(0);
@@ -165,8 +170,25 @@ impl<V: Clone> MeshletBuilder<V> {
 			vertex_data: self.vertices,
 			meshlet_data: buffer,
 			num_meshlets: self.meshlet_descriptors.len(),
+			indices,
 		}
 	}
+
+	fn expand_indices(&self) -> Vec<u32> {
+		let mut indices = Vec::with_capacity(self.primitive_indices.len());
+
+		for meshlet in self.meshlet_descriptors.iter() {
+			let vertices = &self.vertex_indices[meshlet.vertex_begin as usize..];
+			let primitive_begin = meshlet.primitive_begin as usize * 3;
+			let primitive_end = primitive_begin + meshlet.primitive_count as usize * 3;
+
+			for &local_index in &self.primitive_indices[primitive_begin..primitive_end] {
+				indices.push(vertices[local_index as usize]);
+			}
+		}
+
+		indices
+	}
 }
 
 
@@ -201,3 +223,51 @@ fn as_bytes<T>(buf: &[T]) -> &[u8] {
 		)
 	}
 }
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// A `span` by `span` grid of quads, two triangles each.
+	fn grid(span: u16) -> (Vec<u32>, Vec<u16>) {
+		let vertex_span = span + 1;
+		let vertices = (0..vertex_span as u32 * vertex_span as u32).collect();
+
+		let mut indices = Vec::new();
+		for y in 0..span {
+			for x in 0..span {
+				let corner = x + y * vertex_span;
+				indices.extend_from_slice(&[corner, corner + 1, corner + vertex_span]);
+				indices.extend_from_slice(&[corner + 1, corner + vertex_span + 1, corner + vertex_span]);
+			}
+		}
+
+		(vertices, indices)
+	}
+
+	#[test]
+	fn expanded_indices_match_appended_triangles() {
+		let (vertices, triangles) = grid(20);
+		let (small_vertices, small_triangles) = grid(3);
+
+		let mut builder = MeshletBuilder::new();
+		builder.append(&vertices, &triangles);
+		builder.append(&small_vertices, &small_triangles);
+		builder.append(&vertices, &triangles);
+
+		let mesh = builder.build();
+
+		let expected = [(0, &triangles), (vertices.len(), &small_triangles), (vertices.len() + small_vertices.len(), &triangles)].iter()
+			.flat_map(|&(vertex_start, triangles)| triangles.iter().map(move |&index| vertex_start as u32 + index as u32))
+			.collect(): Vec<_>;
+
+		assert_eq!(mesh.indices, expected);
+		assert_eq!(mesh.vertex_data.len(), vertices.len() * 2 + small_vertices.len());
+
+		// Each large grid exceeds both limits on its own, so must span several meshlets
+		assert!(vertices.len() > MAX_MESHLET_VERTICES && triangles.len() / 3 > MAX_MESHLET_TRIANGLES);
+		assert!(mesh.num_meshlets > 2);
+	}
+}
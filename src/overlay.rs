@@ -0,0 +1,201 @@
+use std::error::Error;
+use common::math::*;
+use crate::{gl, perf, perf_scope};
+use crate::text::TextRenderer;
+
+
+/// Number of frames shown in the frame time graph.
+const HISTORY_LENGTH: usize = 120;
+
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+
+/// In characters, including indentation
+const NAME_COLUMN_WIDTH: usize = 12;
+const SECTION_BAR_WIDTH: f32 = 128.0;
+
+const GRAPH_COLUMN_WIDTH: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 64.0;
+
+/// The graph is scaled to fit at least two frames at this budget, and marks it with a line.
+const TARGET_FRAME_MS: f64 = 1000.0 / 60.0;
+
+
+/// Draws per-section GPU times, a frame time graph and triangle counts over the window,
+/// from the statistics gathered by an `Instrumenter`.
+/// The graph is drawn from frames collected by `sink`, which must be added to the instrumenter.
+pub struct ProfilerOverlay {
+	text: TextRenderer,
+	visible: bool,
+	history: perf::MemorySink,
+}
+
+impl ProfilerOverlay {
+	pub fn new(gl_ctx: &impl gl::Backend) -> Result<ProfilerOverlay, Box<dyn Error>> {
+		Ok(ProfilerOverlay {
+			text: TextRenderer::new(gl_ctx)?,
+			visible: false,
+			history: perf::MemorySink::new(HISTORY_LENGTH),
+		})
+	}
+
+	/// Collects frames for the graph, whether or not the overlay is visible.
+	pub fn sink(&self) -> perf::MemorySink {
+		self.history.clone()
+	}
+
+	pub fn is_visible(&self) -> bool {
+		self.visible
+	}
+
+	pub fn toggle(&mut self) {
+		self.visible = !self.visible;
+	}
+
+	/// Draws over whatever framebuffer is bound, which should be `screen_size` pixels.
+	pub fn draw(&mut self, gl_ctx: &impl gl::Backend, inst: &mut perf::Instrumenter, screen_size: (u32, u32)) {
+		if !self.visible { return }
+
+		self.layout(inst.stats());
+
+		perf_scope!(inst, "overlay");
+		self.text.draw(gl_ctx, Vec2::new(screen_size.0 as f32, screen_size.1 as f32));
+	}
+
+	/// Queues the whole overlay in `self.text`, back to front.
+	fn layout(&mut self, stats: &perf::Statistics) {
+		let text = &mut self.text;
+		let line_height = text.line_height();
+		let char_width = text.text_width(" ");
+
+		let frame = stats.frame();
+		let mean = |window: &perf::stats::RollingWindow| window.summary().map_or(0.0, |summary| summary.mean);
+
+		let bound = stats.bound().map_or("", perf::Bound::name);
+		let summary = format!("gpu {:.2}ms cpu {:.2}ms {}", mean(&frame.gpu_time_ms), mean(&frame.cpu_busy_ms), bound);
+		let triangles = format!("{} triangles", format_count(mean(&frame.triangles)));
+
+		// Name, bar and a value of up to 13 characters
+		let graph_width = HISTORY_LENGTH as f32 * GRAPH_COLUMN_WIDTH;
+		let section_width = text.text_width(&" ".repeat(NAME_COLUMN_WIDTH + 15)) + SECTION_BAR_WIDTH;
+		let content_width = graph_width.max(section_width).max(text.text_width(&summary));
+		let panel_width = content_width + PADDING * 2.0;
+
+		let num_lines = 2 + stats.scopes().len();
+		let panel_height = num_lines as f32 * line_height + GRAPH_HEIGHT + PADDING * 4.0;
+
+		text.rect(Vec2::splat(MARGIN), Vec2::new(panel_width, panel_height), Vec4::new(0.0, 0.0, 0.0, 0.7));
+
+		let left = MARGIN + PADDING;
+		let mut y = MARGIN + PADDING;
+
+		// Summary
+		text.text(Vec2::new(left, y), &summary, Vec4::splat(1.0));
+		y += line_height;
+
+		text.text(Vec2::new(left, y), &triangles, Vec4::splat(1.0));
+		y += line_height + PADDING;
+
+		// Frame time graph, with CPU busy time marked over GPU time
+		let history = self.history.frames();
+		let frame_times = history.iter()
+			.map(|frame| (frame.gpu_time_ns() as f64 / 1.0e6, frame.cpu_busy_ns() as f64 / 1.0e6))
+			.collect(): Vec<_>;
+
+		let graph_max_ms = frame_times.iter()
+			.map(|&(gpu_ms, cpu_busy_ms)| gpu_ms.max(cpu_busy_ms))
+			.fold(TARGET_FRAME_MS * 2.0, f64::max);
+
+		let graph_bottom = y + GRAPH_HEIGHT;
+		let graph_y = |ms: f64| graph_bottom - (ms / graph_max_ms) as f32 * GRAPH_HEIGHT;
+
+		text.rect(Vec2::new(left, y), Vec2::new(graph_width, GRAPH_HEIGHT), Vec4::new(1.0, 1.0, 1.0, 0.1));
+
+		for (index, &(gpu_ms, cpu_busy_ms)) in frame_times.iter().enumerate() {
+			let x = left + index as f32 * GRAPH_COLUMN_WIDTH;
+			let gpu_top = graph_y(gpu_ms);
+
+			text.rect(Vec2::new(x, gpu_top), Vec2::new(GRAPH_COLUMN_WIDTH, graph_bottom - gpu_top), gpu_color());
+			text.rect(Vec2::new(x, graph_y(cpu_busy_ms) - 1.0), Vec2::new(GRAPH_COLUMN_WIDTH, 2.0), cpu_color());
+		}
+
+		text.rect(Vec2::new(left, graph_y(TARGET_FRAME_MS)), Vec2::new(graph_width, 1.0), Vec4::new(1.0, 1.0, 1.0, 0.5));
+		y = graph_bottom + PADDING;
+
+		// Sections, with bars showing their share of GPU frame time
+		let frame_gpu_ms = mean(&frame.gpu_time_ms).max(f64::EPSILON);
+		let bar_left = left + NAME_COLUMN_WIDTH as f32 * char_width + char_width;
+		let value_left = bar_left + SECTION_BAR_WIDTH + char_width;
+
+		for scope in stats.scopes() {
+			let indent = (scope.depth * 2).min(NAME_COLUMN_WIDTH - 1);
+			let name = scope.name.chars().take(NAME_COLUMN_WIDTH - indent).collect(): String;
+			text.text(Vec2::new(left + indent as f32 * char_width, y), &name, Vec4::splat(1.0));
+
+			let gpu_ms = mean(&scope.gpu_time_ms);
+			let bar_width = (gpu_ms / frame_gpu_ms).min(1.0) as f32 * SECTION_BAR_WIDTH;
+			let bar_inset = line_height / 8.0;
+
+			text.rect(Vec2::new(bar_left, y + bar_inset), Vec2::new(SECTION_BAR_WIDTH, line_height - bar_inset * 2.0), Vec4::new(1.0, 1.0, 1.0, 0.1));
+			text.rect(Vec2::new(bar_left, y + bar_inset), Vec2::new(bar_width, line_height - bar_inset * 2.0), depth_color(scope.depth));
+
+			let value = format!("{:5.2}ms {:>5}", gpu_ms, format_count(mean(&scope.triangles)));
+			text.text(Vec2::new(value_left, y), &value, Vec4::splat(1.0));
+
+			y += line_height;
+		}
+	}
+}
+
+
+fn gpu_color() -> Vec4 { Vec4::new(1.0, 0.6, 0.2, 0.9) }
+fn cpu_color() -> Vec4 { Vec4::new(0.3, 0.8, 1.0, 1.0) }
+
+fn depth_color(depth: usize) -> Vec4 {
+	match depth % 3 {
+		0 => Vec4::new(1.0, 0.6, 0.2, 0.9),
+		1 => Vec4::new(0.5, 0.9, 0.4, 0.9),
+		_ => Vec4::new(0.8, 0.5, 1.0, 0.9),
+	}
+}
+
+/// Short form of a count, at most five characters.
+fn format_count(count: f64) -> String {
+	if count < 1.0e3 {
+		format!("{:.0}", count)
+	} else if count < 1.0e6 {
+		format!("{:.1}k", count / 1.0e3)
+	} else {
+		format!("{:.1}M", count / 1.0e6)
+	}
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::gl::{Command, RecordingBackend};
+
+	#[test]
+	fn only_draws_when_visible() {
+		let gl_ctx = RecordingBackend::with_mesh_shaders();
+		let mut inst = perf::Instrumenter::disabled();
+		let mut overlay = ProfilerOverlay::new(&gl_ctx).unwrap();
+
+		gl_ctx.take_commands();
+		overlay.draw(&gl_ctx, &mut inst, (700, 700));
+		assert!(gl_ctx.commands().is_empty());
+
+		overlay.toggle();
+		overlay.draw(&gl_ctx, &mut inst, (700, 700));
+		assert!(gl_ctx.take_commands().iter().any(|command| matches!(command, Command::DrawMeshTasks{..})));
+	}
+
+	#[test]
+	fn formats_counts_compactly() {
+		assert_eq!(format_count(999.0), "999");
+		assert_eq!(format_count(12_345.0), "12.3k");
+		assert_eq!(format_count(4_500_000.0), "4.5M");
+	}
+}
@@ -1,21 +1,38 @@
+use std::error::Error;
 use common::math::*;
-use crate::{gl, perf};
+use crate::{gl, perf, perf_scope, GLOBAL_UNIFORMS};
+use crate::gl::GpuLayout;
 
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+const TEXTURE_SIZE: u32 = 4096;
+const BRUSH_WORKGROUP_SIZE: u32 = 8;
+
+
+#[derive(Copy, Clone, Debug, GpuLayout)]
 struct PaintUniforms {
 	world_size: Vec2,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, GpuLayout)]
 struct BrushUniforms {
-	brush_center: Vec2,
-	brush_size: Vec2,
+	center: Vec2,
+	size: Vec2,
 }
 
 
+const PAINT_SAMPLER: gl::ExpectedResource = gl::ExpectedResource::sampler("u_paint_sampler", 0);
+const BRUSH_DATA: gl::ExpectedResource = gl::ExpectedResource::uniform_block("BrushData", 1, BrushUniforms::STD140.size).with_members(BrushUniforms::FIELDS_STD140);
+const PAINT_IMAGE: gl::ExpectedResource = gl::ExpectedResource::image("u_image", 0);
+
+
+const PIPELINE_STATE: gl::PipelineState = gl::PipelineState {
+	blend: gl::BlendMode::MultiplyAdd,
+	depth_test: false,
+	depth_write: false,
+	..gl::PipelineState::DEFAULT
+};
+
+
 struct PaintOperation {
 	pos: Vec2,
 	size: Vec2,
@@ -23,49 +40,68 @@ struct PaintOperation {
 
 pub struct Resources {
 	texture: gl::Texture,
+	linear_sampler: gl::Sampler,
+	nearest_sampler: gl::Sampler,
 }
 
 pub struct PaintSystem {
+	render_path: gl::RenderPath,
 	rendering_program: gl::Program,
 	brush_program: gl::Program,
 
 	brush_uniforms: gl::Buffer,
 	texture: gl::Texture,
+	linear_sampler: gl::Sampler,
+	nearest_sampler: gl::Sampler,
 
 	paint_queue: Vec<PaintOperation>,
 }
 
 impl PaintSystem {
-	pub fn new(gl_ctx: &gl::Context) -> PaintSystem {
-		let rendering_program = gl_ctx.new_shader(&[
-			(gl::raw::MESH_SHADER_NV, include_str!("shaders/paint.mesh.glsl")),
+	pub fn new(gl_ctx: &impl gl::Backend) -> Result<PaintSystem, Box<dyn Error>> {
+		let render_path = gl_ctx.capabilities().render_path();
+
+		let geometry_shader = match render_path {
+			gl::RenderPath::MeshShaders => (gl::raw::MESH_SHADER_NV, include_str!("shaders/paint.mesh.glsl")),
+			gl::RenderPath::VertexPipeline => (gl::raw::VERTEX_SHADER, include_str!("shaders/paint.vert.glsl")),
+		};
+
+		let rendering_program = gl_ctx.new_shader("paint", &[
+			geometry_shader,
 			(gl::raw::FRAGMENT_SHADER, include_str!("shaders/paint.frag.glsl")),
-		]);
+		], &gl::Defines::new(), &[GLOBAL_UNIFORMS, PAINT_SAMPLER])?;
 
-		let brush_program = gl_ctx.new_shader(&[
+		let brush_program = gl_ctx.new_shader("paint brush", &[
 			(gl::raw::COMPUTE_SHADER, include_str!("shaders/paint_brush.compute.glsl")),
-		]);
+		], &gl::Defines::new().with("WORKGROUP_SIZE", BRUSH_WORKGROUP_SIZE),
+		&[GLOBAL_UNIFORMS, BRUSH_DATA, PAINT_IMAGE])?;
+
+		let brush_uniforms = gl_ctx.new_buffer("brush uniforms");
+		let texture = gl_ctx.new_texture("paint", &gl::TextureDesc::new_2d(TEXTURE_SIZE, TEXTURE_SIZE, gl::TextureFormat::R32F));
+		gl_ctx.clear_texture(texture, Vec4::zero());
 
-		let brush_uniforms = gl_ctx.new_buffer();
-		let texture = gl_ctx.new_texture(4096, 4096, gl::raw::R32F);
-		texture.clear();
-		texture.set_wrap(false);
-		texture.set_filter(true, true);
+		let linear_sampler = gl_ctx.new_sampler(&gl::SamplerDesc::LINEAR.with_wrap(gl::WrapMode::ClampToEdge));
+		let nearest_sampler = gl_ctx.new_sampler(&gl::SamplerDesc::NEAREST.with_wrap(gl::WrapMode::ClampToEdge));
 
-		PaintSystem {
+		Ok(PaintSystem {
+			render_path,
 			rendering_program,
 			brush_program,
 
 			brush_uniforms,
 			texture,
+			linear_sampler,
+			nearest_sampler,
 
 			paint_queue: Vec::new(),
-		}
+		})
 	}
 
 	pub fn resources(&self) -> Resources {
 		Resources {
 			texture: self.texture,
+			linear_sampler: self.linear_sampler,
+			nearest_sampler: self.nearest_sampler,
 		}
 	}
 
@@ -76,63 +112,118 @@ impl PaintSystem {
 		});
 	}
 
-	pub fn update(&mut self, gl_ctx: &gl::Context, inst: &mut perf::Instrumenter) {
+	pub fn update(&mut self, gl_ctx: &impl gl::Backend, inst: &mut perf::Instrumenter) {
 		if self.paint_queue.is_empty() { return }
 
-		inst.start_section("brush sim");
+		{
+			perf_scope!(inst, "brush sim");
 
-		gl_ctx.bind_image_rw(0, self.texture, gl::raw::R32F);
+			gl_ctx.bind_image_rw(PAINT_IMAGE.binding, self.texture);
 
-		gl_ctx.bind_uniform_buffer(1, self.brush_uniforms);
-		gl_ctx.use_program(self.brush_program);
+			gl_ctx.bind_uniform_buffer(BRUSH_DATA.binding, self.brush_uniforms);
+			gl_ctx.use_program(self.brush_program);
 
-		for PaintOperation{pos, size} in self.paint_queue.drain(..) {
-			let brush_uniforms = BrushUniforms {
-				brush_center: pos,
-				brush_size: size, 
-			};
+			for PaintOperation{pos, size} in self.paint_queue.drain(..) {
+				let brush_uniforms = BrushUniforms {
+					center: pos,
+					size,
+				};
 
-			self.brush_uniforms.upload(&[brush_uniforms], gl::BufferUsage::Dynamic);
+				gl_ctx.upload_std140(self.brush_uniforms, &[brush_uniforms], gl::BufferUsage::Dynamic);
 
-			// TODO: actually figure out numbers
-			gl_ctx.dispatch_compute(512, 512, 1);
+				// TODO: actually figure out numbers
+				let num_workgroups = TEXTURE_SIZE / BRUSH_WORKGROUP_SIZE;
+				gl_ctx.dispatch_compute(num_workgroups, num_workgroups, 1);
+			}
 		}
 
-		inst.end_section();
-
-		unsafe {
-			gl::raw::MemoryBarrier(gl::raw::SHADER_IMAGE_ACCESS_BARRIER_BIT);
-		}
+		gl_ctx.memory_barrier(gl::raw::SHADER_IMAGE_ACCESS_BARRIER_BIT);
 	}
 
-	pub fn draw(&self, gl_ctx: &gl::Context, inst: &mut perf::Instrumenter) {
-		unsafe {
-			gl::raw::Disable(gl::raw::DEPTH_TEST);
-
-			gl::raw::Enable(gl::raw::BLEND);
-			gl::raw::BlendFunc(gl::raw::DST_COLOR, gl::raw::ONE);
-			gl::raw::BlendEquation(gl::raw::FUNC_ADD);
-			gl::raw::DepthMask(0);
-		}
-		
-		gl_ctx.bind_texture(0, self.texture);
+	pub fn draw(&self, gl_ctx: &impl gl::Backend, inst: &mut perf::Instrumenter) {
+		self.resources().bind(gl_ctx, PAINT_SAMPLER.binding, gl::Filter::Linear);
 		gl_ctx.use_program(self.rendering_program);
 
-		inst.start_section("paint");
-		gl_ctx.draw_mesh_tasks(0, 1);
-		inst.end_section();
+		perf_scope!(inst, "paint");
 
-		unsafe {
-			gl::raw::Enable(gl::raw::DEPTH_TEST);
-			gl::raw::Disable(gl::raw::BLEND);
-			gl::raw::DepthMask(1);
+		match self.render_path {
+			gl::RenderPath::MeshShaders => gl_ctx.draw_mesh_tasks(&PIPELINE_STATE, 0, 1),
+			gl::RenderPath::VertexPipeline => gl_ctx.draw_instanced(&PIPELINE_STATE, 6, 1),
 		}
 	}
 }
 
 
 impl Resources {
-	pub fn bind(&self, gl_ctx: &gl::Context, texture_slot: u32) {
+	pub fn bind(&self, gl_ctx: &impl gl::Backend, texture_slot: u32, filter: gl::Filter) {
+		let sampler = match filter {
+			gl::Filter::Linear => self.linear_sampler,
+			gl::Filter::Nearest => self.nearest_sampler,
+		};
+
 		gl_ctx.bind_texture(texture_slot, self.texture);
+		gl_ctx.bind_sampler(texture_slot, Some(sampler));
 	}
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::gl::{Command, RecordingBackend};
+
+	#[test]
+	fn update_dispatches_once_per_brush_op() {
+		let gl_ctx = RecordingBackend::with_mesh_shaders();
+		let mut inst = perf::Instrumenter::disabled();
+
+		let mut paint_system = PaintSystem::new(&gl_ctx).unwrap();
+		paint_system.paint(Vec2::new(1.0, 2.0));
+		paint_system.paint(Vec2::new(3.0, 4.0));
+
+		gl_ctx.take_commands();
+		paint_system.update(&gl_ctx, &mut inst);
+
+		// Each dispatch should be preceded by an upload of its own brush uniforms
+		let expected_uniforms = |brush_center| gl::layout::to_bytes(&[BrushUniforms {
+			center: brush_center,
+			size: Vec2::splat(20.0),
+		}], gl::Layout::Std140);
+
+		let brush_commands = gl_ctx.take_commands().into_iter()
+			.filter(|command| matches!(command, Command::UploadBuffer{..} | Command::DispatchCompute{..}))
+			.collect(): Vec<_>;
+
+		let num_workgroups = TEXTURE_SIZE / BRUSH_WORKGROUP_SIZE;
+		let dispatch = Command::DispatchCompute { x: num_workgroups, y: num_workgroups, z: 1 };
+		let upload = |brush_center| Command::UploadBuffer {
+			buffer: paint_system.brush_uniforms,
+			data: expected_uniforms(brush_center),
+			usage: gl::BufferUsage::Dynamic,
+		};
+
+		assert_eq!(brush_commands, [
+			upload(Vec2::new(1.0, 2.0)), dispatch.clone(),
+			upload(Vec2::new(3.0, 4.0)), dispatch,
+		]);
+
+		// The queue should be drained
+		paint_system.update(&gl_ctx, &mut inst);
+		assert!(gl_ctx.commands().is_empty());
+	}
+
+	#[test]
+	fn draw_uses_vertex_pipeline_without_mesh_shaders() {
+		let gl_ctx = RecordingBackend::without_mesh_shaders();
+		let mut inst = perf::Instrumenter::disabled();
+
+		let paint_system = PaintSystem::new(&gl_ctx).unwrap();
+
+		gl_ctx.take_commands();
+		paint_system.draw(&gl_ctx, &mut inst);
+
+		let commands = gl_ctx.take_commands();
+		assert!(commands.contains(&Command::DrawInstanced { state: PIPELINE_STATE, vertex_count: 6, instance_count: 1 }));
+		assert!(!commands.iter().any(|command| matches!(command, Command::DrawMeshTasks{..})));
+	}
+}
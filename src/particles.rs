@@ -1,27 +1,43 @@
+use std::error::Error;
 use common::math::*;
-use crate::{gl, perf, paint};
+use crate::{gl, perf, perf_scope, paint, GLOBAL_UNIFORMS};
+use crate::gl::GpuLayout;
 
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+const SIMULATION_WORKGROUP_SIZE: u32 = 16;
+const RENDER_WORKGROUP_SIZE: u32 = 32;
+
+/// Number of particles emitted by each mesh workgroup
+const PARTICLES_PER_WORKGROUP: u32 = 16;
+
+
+#[derive(Copy, Clone, Debug, GpuLayout)]
 struct Particle {
-	pos: Vec3,
-	_0: f32,
+	position: Vec3,
 	velocity: Vec3,
-	_1: f32,
 	tail: Vec3,
-	_2: f32,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, GpuLayout)]
 struct StatsBuffer {
 	particle_buffer_size: u32,
 	max_task_output_count: u32,
 }
 
 
+const PARTICLE_DATA: gl::ExpectedResource = gl::ExpectedResource::storage_block("ParticleData", 0, Particle::STD430.size).with_members(Particle::FIELDS_STD430);
+const STATS: gl::ExpectedResource = gl::ExpectedResource::storage_block("Stats", 1, StatsBuffer::STD430.size).with_members(StatsBuffer::FIELDS_STD430);
+const PIPELINE_STATE: gl::PipelineState = gl::PipelineState {
+	blend: gl::BlendMode::Multiply,
+	depth_write: false,
+	..gl::PipelineState::DEFAULT
+};
+
+const PAINT_SAMPLER: gl::ExpectedResource = gl::ExpectedResource::sampler("u_paint_sampler", 0);
+
+
 pub struct ParticleSystem {
+	render_path: gl::RenderPath,
 	rendering_program: gl::Program,
 	simulation_program: gl::Program,
 
@@ -33,16 +49,32 @@ pub struct ParticleSystem {
 }
 
 impl ParticleSystem {
-	pub fn new(gl_ctx: &gl::Context) -> ParticleSystem {
-		let rendering_program = gl_ctx.new_shader(&[
-			(gl::raw::TASK_SHADER_NV, include_str!("shaders/particle.task.glsl")),
-			(gl::raw::MESH_SHADER_NV, include_str!("shaders/particle.mesh.glsl")),
-			(gl::raw::FRAGMENT_SHADER, include_str!("shaders/particle.frag.glsl")),
-		]);
-
-		let simulation_program = gl_ctx.new_shader(&[
+	pub fn new(gl_ctx: &impl gl::Backend) -> Result<ParticleSystem, Box<dyn Error>> {
+		let render_path = gl_ctx.capabilities().render_path();
+
+		let rendering_program = match render_path {
+			gl::RenderPath::MeshShaders => gl_ctx.new_shader("particles", &[
+				(gl::raw::TASK_SHADER_NV, include_str!("shaders/particle.task.glsl")),
+				(gl::raw::MESH_SHADER_NV, include_str!("shaders/particle.mesh.glsl")),
+				(gl::raw::FRAGMENT_SHADER, include_str!("shaders/particle.frag.glsl")),
+			], &gl::Defines::new()
+				.with("WORKGROUP_SIZE", RENDER_WORKGROUP_SIZE)
+				.with("PARTICLES_PER_WORKGROUP", PARTICLES_PER_WORKGROUP),
+			&[GLOBAL_UNIFORMS, PARTICLE_DATA, STATS])?,
+
+			gl::RenderPath::VertexPipeline => gl_ctx.new_shader("particles", &[
+				(gl::raw::VERTEX_SHADER, include_str!("shaders/particle.vert.glsl")),
+				(gl::raw::FRAGMENT_SHADER, include_str!("shaders/particle.frag.glsl")),
+			], &gl::Defines::new()
+				.with("VERTEX_PIPELINE", 1)
+				.with("PARTICLES_PER_WORKGROUP", PARTICLES_PER_WORKGROUP),
+			&[GLOBAL_UNIFORMS, PARTICLE_DATA])?,
+		};
+
+		let simulation_program = gl_ctx.new_shader("particle simulation", &[
 			(gl::raw::COMPUTE_SHADER, include_str!("shaders/particle_simulation.compute.glsl")),
-		]);
+		], &gl::Defines::new().with("WORKGROUP_SIZE", SIMULATION_WORKGROUP_SIZE),
+		&[GLOBAL_UNIFORMS, PARTICLE_DATA, PAINT_SAMPLER])?;
 
 		
 		let mut particles = Vec::new();
@@ -64,36 +96,33 @@ impl ParticleSystem {
 
 					let tail = pos;
 
-					particles.push(Particle {
-						pos, velocity, tail,
-						_0: 0.0, _1: 0.0, _2: 0.0,
-					});
+					particles.push(Particle {position: pos, velocity, tail});
 
 					offset += 0.5;
 				}
 			}
 		}
 
-		let mut max_task_output_count = 0;
-		unsafe {
-			gl::raw::GetIntegerv(gl::raw::MAX_TASK_OUTPUT_COUNT_NV, &mut max_task_output_count);
-		}
+		let max_task_output_count = gl_ctx.capabilities().max_task_output_count;
 
 		println!("particles: {:?}", particles.len());
-		println!("required tasks: {:?}", particles.len() / max_task_output_count as usize);
+
+		if render_path == gl::RenderPath::MeshShaders {
+			println!("required tasks: {:?}", particles.len() / max_task_output_count as usize);
+		}
 
 		let particle_buffer_size = particles.len() as u32;
-		let max_task_output_count = max_task_output_count as u32;
 
 		let stats = StatsBuffer {particle_buffer_size, max_task_output_count};
 
-		let particle_ssbo = gl_ctx.new_buffer();
-		let stats_ssbo = gl_ctx.new_buffer();
+		let particle_ssbo = gl_ctx.new_buffer("particles");
+		let stats_ssbo = gl_ctx.new_buffer("particle stats");
 
-		particle_ssbo.upload(&particles, gl::BufferUsage::Static);
-		stats_ssbo.upload(&[stats], gl::BufferUsage::Static);
+		gl_ctx.upload_std430(particle_ssbo, &particles, gl::BufferUsage::Static);
+		gl_ctx.upload_std430(stats_ssbo, &[stats], gl::BufferUsage::Static);
 
-		ParticleSystem {
+		Ok(ParticleSystem {
+			render_path,
 			rendering_program,
 			simulation_program,
 			particle_ssbo,
@@ -101,48 +130,41 @@ impl ParticleSystem {
 
 			particle_buffer_size,
 			max_task_output_count,
-		}
+		})
 	}
 
-	pub fn update(&self, gl_ctx: &gl::Context, inst: &mut perf::Instrumenter, paint_resources: paint::Resources) {
-		paint_resources.bind(gl_ctx, 0);
+	pub fn update(&self, gl_ctx: &impl gl::Backend, inst: &mut perf::Instrumenter, paint_resources: paint::Resources) {
+		paint_resources.bind(gl_ctx, PAINT_SAMPLER.binding, gl::Filter::Linear);
 
-		gl_ctx.bind_shader_storage_buffer(0, self.particle_ssbo);
+		gl_ctx.bind_shader_storage_buffer(PARTICLE_DATA.binding, self.particle_ssbo);
 		gl_ctx.use_program(self.simulation_program);
 
-		let particles_per_invocation = 16;
-
-		inst.start_section("particles sim");
-		gl_ctx.dispatch_compute((self.particle_buffer_size + particles_per_invocation - 1) / particles_per_invocation, 1, 1);
-		inst.end_section();
-
-		unsafe {
-			gl::raw::MemoryBarrier(gl::raw::SHADER_STORAGE_BARRIER_BIT);
+		{
+			perf_scope!(inst, "particles sim", perf::CounterSet::NONE.with(perf::Counter::ComputeShaderInvocations));
+			gl_ctx.dispatch_compute((self.particle_buffer_size + SIMULATION_WORKGROUP_SIZE - 1) / SIMULATION_WORKGROUP_SIZE, 1, 1);
 		}
-	}
 
-	pub fn draw(&self, gl_ctx: &gl::Context, inst: &mut perf::Instrumenter) {
-		unsafe {
-			gl::raw::Enable(gl::raw::BLEND);
-			gl::raw::BlendFunc(gl::raw::DST_COLOR, gl::raw::ZERO);
-			gl::raw::BlendEquation(gl::raw::FUNC_ADD);
+		gl_ctx.memory_barrier(gl::raw::SHADER_STORAGE_BARRIER_BIT);
+	}
 
-			gl::raw::DepthMask(0);
-		}
-		
-		gl_ctx.bind_shader_storage_buffer(0, self.particle_ssbo);
-		gl_ctx.bind_shader_storage_buffer(1, self.stats_ssbo);
+	pub fn draw(&self, gl_ctx: &impl gl::Backend, inst: &mut perf::Instrumenter) {
+		gl_ctx.bind_shader_storage_buffer(PARTICLE_DATA.binding, self.particle_ssbo);
+		gl_ctx.bind_shader_storage_buffer(STATS.binding, self.stats_ssbo);
 		gl_ctx.use_program(self.rendering_program);
 
-		let num_task_invocations = (self.particle_buffer_size + self.max_task_output_count - 1) / self.max_task_output_count;
+		perf_scope!(inst, "particles", perf::CounterSet::PIPELINE_STATISTICS.with(perf::Counter::TaskWorkgroups));
 
-		inst.start_section("particles");
-		gl_ctx.draw_mesh_tasks(0, num_task_invocations);
-		inst.end_section();
+		match self.render_path {
+			gl::RenderPath::MeshShaders => {
+				let num_task_invocations = (self.particle_buffer_size + self.max_task_output_count - 1) / self.max_task_output_count;
+				gl_ctx.draw_mesh_tasks(&PIPELINE_STATE, 0, num_task_invocations);
+				inst.count_task_workgroups(num_task_invocations);
+			}
 
-		unsafe {
-			gl::raw::Disable(gl::raw::BLEND);
-			gl::raw::DepthMask(1);
+			gl::RenderPath::VertexPipeline => {
+				// 3 triangles per particle, matching particle.mesh.glsl
+				gl_ctx.draw_instanced(&PIPELINE_STATE, 9, self.particle_buffer_size);
+			}
 		}
 	}
 }
\ No newline at end of file
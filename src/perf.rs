@@ -1,158 +1,756 @@
+use std::collections::VecDeque;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
 use crate::gl;
 
+pub mod stats;
+pub mod frame;
+pub mod trace;
+pub mod counters;
+pub mod sink;
+
+pub use stats::{Statistics, Summary};
+pub use frame::{FrameResults, ScopeResult, Bound};
+pub use trace::{TraceRecorder, TraceRequest};
+pub use counters::{Counter, CounterSet, CounterValues};
+pub use sink::{ProfileSink, StdoutSink, FileSink, FileFormat, MemorySink, CallbackSink};
+
+
+const DEFAULT_STATS_WINDOW: usize = 300;
+const DEFAULT_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many frames can be waiting on results before another query set has to be created
+const QUERY_RING_DEPTH: usize = 3;
+
+/// Scopes a query set has room for before it grows.
+/// Each scope uses two timestamp queries, plus a primitive query per stretch not covered by a child.
+/// Pipeline statistics pools start empty, as most scopes don't use them
+const QUERY_SET_SIZE: usize = 32;
+
+/// Records nested scopes timed on both the CPU and GPU, and collects them into a tree per frame.
+///
+/// Scopes are timed with timestamp queries, as only one `TIME_ELAPSED` query may be active at a time.
+/// Primitive counts and pipeline statistics have the same restriction, so each scope only counts
+/// what happens outside of its children, and inclusive counts are summed up once results are available.
+///
+/// Every frame gets its own set of queries from a ring, so recording never waits on earlier frames.
+/// Frames are reported in order as soon as their results are available, and the ring grows
+/// whenever the GPU falls further behind than it has sets for.
+pub struct Instrumenter {
+	/// Queries used by the frame being recorded
+	recording_queries: QuerySet,
 
-#[derive(Copy, Clone, Debug)]
-enum State {
-	Recording,
-	Waiting,
-}
+	/// Scopes started this frame, in the order they were started
+	recording_scopes: Vec<Scope>,
 
-pub struct Instrumenter {
-	section_cache: Vec<Section>,
-	recording_section: Option<Section>,
-	waiting_sections: Vec<Section>,
-	state: State,
+	/// Indices into `recording_scopes` of all scopes that haven't yet ended, innermost last
+	open_scopes: Vec<usize>,
+
+	/// Frames still waiting on query results, oldest first
+	pending_frames: VecDeque<PendingFrame>,
+
+	/// Query sets of frames that have been reported, ready to be reused
+	free_query_sets: Vec<QuerySet>,
+
+	/// When the instrumenter was created, which all frame start times are relative to
+	created: Instant,
+
+	/// When the current frame started, which is when the previous one ended
+	frame_start: Instant,
+	frame_index: u64,
+	swap_wait: Duration,
+
+	last_frame: Option<FrameResults>,
+	stats: Statistics,
+
+	/// Collects frames for a trace, until it has enough to be saved
+	trace: Option<TraceRecorder>,
+
+	/// Receive every frame and report
+	sinks: Vec<Box<dyn ProfileSink>>,
+
+	/// How often `stats` are reported to sinks, or None to only report on request
+	report_interval: Option<Duration>,
+	last_report: Instant,
+
+	/// Counters this device can provide
+	available_counters: CounterSet,
+
+	/// Disabled instrumenters make no GL calls
+	enabled: bool,
 }
 
 
 impl Instrumenter {
 	pub fn new(gl_ctx: &gl::Context) -> Instrumenter {
-		let mut section_cache = Vec::new();
+		let available_counters = match gl_ctx.capabilities().pipeline_statistics {
+			true => CounterSet::ALL,
+			false => CounterSet::WORKGROUPS,
+		};
 
-		for _ in 0..20 {
-			section_cache.push(Section::new(gl_ctx));
+		Instrumenter {
+			available_counters,
+			recording_queries: QuerySet::new(),
+			free_query_sets: (1..QUERY_RING_DEPTH).map(|_| QuerySet::new()).collect(),
+			enabled: true,
+			report_interval: Some(DEFAULT_REPORT_INTERVAL),
+			sinks: vec![Box::new(StdoutSink)],
+			..Instrumenter::disabled()
 		}
+	}
 
+	/// An instrumenter that records nothing, for use without a GL context.
+	pub fn disabled() -> Instrumenter {
 		Instrumenter {
-			section_cache,
-			recording_section: None,
-			waiting_sections: Vec::new(),
-			state: State::Recording,
+			recording_queries: QuerySet::default(),
+			recording_scopes: Vec::new(),
+			open_scopes: Vec::new(),
+			pending_frames: VecDeque::new(),
+			free_query_sets: Vec::new(),
+			created: Instant::now(),
+			frame_start: Instant::now(),
+			frame_index: 0,
+			swap_wait: Duration::from_secs(0),
+			last_frame: None,
+			stats: Statistics::new(DEFAULT_STATS_WINDOW),
+			trace: None,
+			sinks: Vec::new(),
+			report_interval: None,
+			last_report: Instant::now(),
+			available_counters: CounterSet::NONE,
+			enabled: false,
 		}
 	}
 
 
+	pub fn stats(&self) -> &Statistics {
+		&self.stats
+	}
+
+	/// The most recent frame whose results are available.
+	pub fn last_frame(&self) -> Option<&FrameResults> {
+		self.last_frame.as_ref()
+	}
+
+	/// How many ended frames are still waiting on GPU results.
+	pub fn pending_frames(&self) -> usize {
+		self.pending_frames.len()
+	}
+
+	/// Sets how many frames statistics are gathered over.
+	pub fn set_stats_window(&mut self, frames: usize) {
+		self.stats.set_window_size(frames);
+	}
+
+	/// Sets how often statistics are reported, or None to only report them from `report`.
+	pub fn set_report_interval(&mut self, interval: Option<Duration>) {
+		self.report_interval = interval;
+	}
+
+	/// Sends the current statistics to every sink. By default this prints them to stdout.
+	pub fn report(&mut self) {
+		self.send_to_sinks(|sink, stats| sink.report(stats));
+		self.last_report = Instant::now();
+	}
+
+	/// Adds a sink to receive every frame from now on, and every report.
+	pub fn add_sink(&mut self, sink: impl ProfileSink + 'static) {
+		self.sinks.push(Box::new(sink));
+	}
+
+	/// Removes all sinks, including the default stdout sink.
+	pub fn clear_sinks(&mut self) {
+		self.sinks.clear();
+	}
+
+	/// Removes any sink that fails, so that one broken output doesn't spam the log every frame.
+	fn send_to_sinks(&mut self, mut send: impl FnMut(&mut dyn ProfileSink, &Statistics) -> io::Result<()>) {
+		let stats = &self.stats;
+
+		self.sinks.retain_mut(|sink| match send(sink.as_mut(), stats) {
+			Ok(()) => true,
+			Err(error) => {
+				log::error!("Removing profile sink after error: {}", error);
+				false
+			}
+		});
+	}
+
+	pub fn reset_stats(&mut self) {
+		self.stats.reset();
+	}
+
+	/// Starts collecting the next `request.frames` frames with results, and saves them as a trace once done.
+	/// Replaces any trace already in progress.
+	pub fn start_trace(&mut self, request: &TraceRequest) {
+		if !self.enabled { return }
+
+		log::info!("Tracing {} frames to {}", request.frames, request.path.display());
+		self.trace = Some(TraceRecorder::new(&request.path, request.frames));
+	}
+
+	/// Saves any trace in progress, even if it hasn't yet collected all its frames.
+	pub fn finish_trace(&mut self) {
+		if let Some(trace) = self.trace.take() {
+			match trace.save() {
+				Ok(()) => log::info!("Saved trace to {}", trace.path().display()),
+				Err(error) => log::error!("Failed to save trace to {}: {}", trace.path().display(), error),
+			}
+		}
+	}
+
+
+	/// Starts a scope nested inside the innermost open scope, if any.
+	/// Every call must be matched by a call to `end_section`.
 	pub fn start_section(&mut self, name: &str) {
-		match self.state {
-			State::Recording => {},
-			State::Waiting => return,
-		};
+		self.start_section_with_counters(name, CounterSet::NONE);
+	}
 
-		if self.recording_section.is_some() {
-			self.end_section();
+	/// Starts a scope that also counts `counters`, on top of anything its parent counts.
+	/// Pipeline statistics are silently left out on devices without `ARB_pipeline_statistics_query`.
+	pub fn start_section_with_counters(&mut self, name: &str, counters: CounterSet) {
+		// Disabled instrumenters still track nesting, so guards and mismatched sections behave the same without GL.
+		// Nothing is recorded, so the index is never looked up.
+		if !self.enabled {
+			self.open_scopes.push(self.recording_scopes.len());
+			return
 		}
 
-		let mut section = self.section_cache.pop()
-			.expect("Query cache empty!");
+		gl::debug::push_group(name);
 
-		section.start(name.into());
+		let parent = self.open_scopes.last().copied();
 
-		self.recording_section = Some(section);
-	}
+		// Children count everything their parent does, so that inclusive counts add up
+		let inherited = parent.map_or(CounterSet::NONE, |parent| self.recording_scopes[parent].counters);
+		let counters = counters.union(inherited).intersection(self.available_counters);
 
-	pub fn end_section(&mut self) {
-		match self.state {
-			State::Recording => {},
-			State::Waiting => return,
+		// The parents counts pause until this scope ends
+		if let Some(parent) = parent {
+			self.recording_scopes[parent].current_segment().end();
+		}
+
+		let now = Instant::now();
+
+		let mut scope = Scope {
+			name: name.into(),
+			parent,
+			depth: self.open_scopes.len(),
+			cpu_start: now,
+			cpu_end: now,
+			start_query: self.recording_queries.acquire_timestamp(),
+			end_query: self.recording_queries.acquire_timestamp(),
+			counters,
+			tallied: CounterValues::zeroed(counters),
+			segments: Vec::new(),
 		};
 
-		let section = self.recording_section.take()
+		unsafe {
+			gl::raw::QueryCounter(scope.start_query, gl::raw::TIMESTAMP);
+		}
+
+		scope.segments.push(Segment::begin(&mut self.recording_queries, counters));
+
+		self.open_scopes.push(self.recording_scopes.len());
+		self.recording_scopes.push(scope);
+	}
+
+	pub fn end_section(&mut self) {
+		let scope_index = self.open_scopes.pop()
 			.expect("Mismatched start/end query section!");
 
-		Section::end();
+		if !self.enabled { return }
 
-		self.waiting_sections.push(section);
+		gl::debug::pop_group();
+
+		self.recording_scopes[scope_index].current_segment().end();
+
+		unsafe {
+			gl::raw::QueryCounter(self.recording_scopes[scope_index].end_query, gl::raw::TIMESTAMP);
+		}
+
+		self.recording_scopes[scope_index].cpu_end = Instant::now();
+
+		// Resume counting for the parent
+		if let Some(parent) = self.recording_scopes[scope_index].parent {
+			let parent = &mut self.recording_scopes[parent];
+			parent.segments.push(Segment::begin(&mut self.recording_queries, parent.counters));
+		}
 	}
 
+	/// Starts a section that ends when the returned guard is dropped.
+	/// The guard derefs to the instrumenter, so nested sections and counts can be recorded through it.
+	pub fn scope(&mut self, name: &str) -> SectionGuard<'_> {
+		self.scope_with_counters(name, CounterSet::NONE)
+	}
+
+	pub fn scope_with_counters(&mut self, name: &str, counters: CounterSet) -> SectionGuard<'_> {
+		let outer_depth = self.open_scopes.len();
+		self.start_section_with_counters(name, counters);
+
+		SectionGuard {
+			inst: self,
+			outer_depth,
+		}
+	}
+
+	/// Runs `f` inside a section, which ends even if `f` panics.
+	pub fn with_section<R>(&mut self, name: &str, f: impl FnOnce(&mut Instrumenter) -> R) -> R {
+		let mut guard = self.scope(name);
+		f(&mut guard)
+	}
+
+	/// Counts task shader workgroups launched by a draw, for the innermost scope if it counts them.
+	pub fn count_task_workgroups(&mut self, count: u32) {
+		self.tally(Counter::TaskWorkgroups, count as u64);
+	}
+
+	/// Counts mesh shader workgroups launched by a draw, for the innermost scope if it counts them.
+	/// Only draws without a task shader know this up front.
+	pub fn count_mesh_workgroups(&mut self, count: u32) {
+		self.tally(Counter::MeshWorkgroups, count as u64);
+	}
+
+	fn tally(&mut self, counter: Counter, value: u64) {
+		if !self.enabled { return }
+
+		if let Some(&scope_index) = self.open_scopes.last() {
+			self.recording_scopes[scope_index].tallied.add(counter, value);
+		}
+	}
+
+
+	fn report_frame(&mut self, pending: PendingFrame) {
+		let raw_scopes = pending.scopes.iter()
+			.map(|scope| scope.result(pending.cpu_start))
+			.collect();
+
+		let mut queries = pending.queries;
+		queries.reset();
+		self.free_query_sets.push(queries);
+
+		let frame = FrameResults::from_raw(raw_scopes, pending.timing);
+		self.stats.record_frame(&frame);
+
+		if let Some(trace) = &mut self.trace {
+			trace.record(&frame);
+		}
+
+		self.send_to_sinks(|sink, stats| sink.frame(&frame, stats));
+
+		self.last_frame = Some(frame);
+	}
+
+	/// Runs `swap`, which is expected to swap the window's buffers, and counts the time it takes as swap wait.
+	pub fn measure_swap<R>(&mut self, swap: impl FnOnce() -> R) -> R {
+		let start = Instant::now();
+		let result = swap();
+		self.swap_wait += start.elapsed();
+		result
+	}
+
+	/// Blocks until every ended frame has been reported, so that stats and sinks cover everything recorded so far.
+	pub fn flush(&mut self) {
+		while let Some(pending) = self.pending_frames.pop_front() {
+			self.report_frame(pending);
+		}
+
+		self.send_to_sinks(|sink, _| sink.flush());
+	}
 
 	pub fn end_frame(&mut self) {
-		if self.recording_section.is_some() {
+		while !self.open_scopes.is_empty() {
 			self.end_section();
 		}
 
-		self.state = State::Waiting;
+		if !self.enabled { return }
 
-		let queries_ready = self.waiting_sections.iter()
-			.all(&Section::ready);
+		let frame_end = Instant::now();
+		let frame_start = std::mem::replace(&mut self.frame_start, frame_end);
+		let swap_wait = std::mem::replace(&mut self.swap_wait, Duration::from_secs(0));
 
-		if queries_ready {
-			let mut total_time = 0.0f64;
-			let mut total_tris = 0usize;
+		let timing = frame::FrameTiming {
+			index: self.frame_index,
+			cpu_start_ns: (frame_start - self.created).as_nanos() as u64,
+			cpu_time_ns: (frame_end - frame_start).as_nanos() as u64,
+			swap_wait_ns: swap_wait.as_nanos() as u64,
+		};
+
+		self.frame_index += 1;
 
-			for section in self.waiting_sections.drain(..) {
-				let (time_nanos, triangles) = section.result();
-				let time_ms = time_nanos as f64 / 1000_000.0;
-				print!("[{}: {}tris {:.3}ms] ", section.name, triangles, time_ms);
+		self.pending_frames.push_back(PendingFrame {
+			scopes: self.recording_scopes.drain(..).collect(),
+			queries: std::mem::take(&mut self.recording_queries),
+			cpu_start: frame_start,
+			timing,
+		});
 
-				total_time += time_ms;
-				total_tris += triangles;
+		// Queries complete in order, so stop at the first frame that isn't ready
+		while self.pending_frames.front().map_or(false, PendingFrame::ready) {
+			let pending = self.pending_frames.pop_front().unwrap();
+			self.report_frame(pending);
+		}
 
-				self.section_cache.push(section);
+		self.recording_queries = self.free_query_sets.pop().unwrap_or_else(|| {
+			log::debug!("Growing query ring to {} frames", self.pending_frames.len() + 1);
+			QuerySet::new()
+		});
+
+		if self.trace.as_ref().map_or(false, TraceRecorder::is_complete) {
+			self.finish_trace();
+		}
+
+		if let Some(interval) = self.report_interval {
+			if self.last_report.elapsed() >= interval {
+				self.report();
 			}
+		}
+	}
+}
 
-			println!("[[total: {}tris {:.3}ms]]", total_tris, total_time);
 
-			self.state = State::Recording;
+
+
+/// Ends a section when dropped, see `Instrumenter::scope`.
+#[must_use = "the section ends as soon as the guard is dropped"]
+pub struct SectionGuard<'a> {
+	inst: &'a mut Instrumenter,
+
+	/// Open scopes outside of this one. Anything left open inside is ended along with it,
+	/// and nothing is ended if the frame already ended it.
+	outer_depth: usize,
+}
+
+impl Deref for SectionGuard<'_> {
+	type Target = Instrumenter;
+
+	fn deref(&self) -> &Instrumenter {
+		self.inst
+	}
+}
+
+impl DerefMut for SectionGuard<'_> {
+	fn deref_mut(&mut self) -> &mut Instrumenter {
+		self.inst
+	}
+}
+
+impl Drop for SectionGuard<'_> {
+	fn drop(&mut self) {
+		while self.inst.open_scopes.len() > self.outer_depth {
+			self.inst.end_section();
 		}
 	}
 }
 
 
+/// Times the rest of the enclosing block as a section of `$inst`, an `Instrumenter` or a mutable reference to one,
+/// optionally counting a `CounterSet` as well.
+/// `$inst` is shadowed by a reference through the section's guard, so it can still be used as normal within the block.
+///
+/// Compiles to nothing without the `profiling` feature.
+#[cfg(feature = "profiling")]
+#[macro_export]
+macro_rules! perf_scope {
+	($inst:ident, $name:expr) => {
+		$crate::perf_scope!($inst, $name, $crate::perf::CounterSet::NONE)
+	};
+
+	($inst:ident, $name:expr, $counters:expr) => {
+		let mut perf_scope_guard = $inst.scope_with_counters($name, $counters);
+
+		#[allow(unused_variables)]
+		let $inst: &mut $crate::perf::Instrumenter = &mut perf_scope_guard;
+	};
+}
 
+#[cfg(not(feature = "profiling"))]
+#[macro_export]
+macro_rules! perf_scope {
+	($inst:ident, $name:expr $(, $counters:expr)?) => {
+		let _ = &$inst;
+	};
+}
+
+
+
+/// A frame's scopes and CPU timings, waiting on GPU query results.
+struct PendingFrame {
+	scopes: Vec<Scope>,
+	queries: QuerySet,
+	cpu_start: Instant,
+	timing: frame::FrameTiming,
+}
+
+impl PendingFrame {
+	fn ready(&self) -> bool {
+		self.scopes.iter().all(Scope::ready)
+	}
+}
+
+/// All queries used by a single frame.
+/// Kept in separate pools by target, as a query object can't change type once used.
+#[derive(Default)]
+struct QuerySet {
+	timestamps: QueryPool,
+	primitives: QueryPool,
+
+	/// One pool per `Counter`, of which only pipeline statistics are used
+	statistics: [QueryPool; Counter::ALL.len()],
+}
+
+/// Queries of a single target. Grows whenever more are used than it has.
+#[derive(Default)]
+struct QueryPool {
+	queries: Vec<u32>,
+	used: usize,
+}
 
-struct Section {
+impl QuerySet {
+	fn new() -> QuerySet {
+		QuerySet {
+			timestamps: QueryPool::new(2 * QUERY_SET_SIZE),
+			primitives: QueryPool::new(QUERY_SET_SIZE),
+			..QuerySet::default()
+		}
+	}
+
+	fn acquire_timestamp(&mut self) -> u32 {
+		self.timestamps.acquire()
+	}
+
+	/// Makes every query available again. Must only be called once all results have been read.
+	fn reset(&mut self) {
+		self.timestamps.used = 0;
+		self.primitives.used = 0;
+
+		for pool in self.statistics.iter_mut() {
+			pool.used = 0;
+		}
+	}
+}
+
+impl QueryPool {
+	fn new(count: usize) -> QueryPool {
+		QueryPool {
+			queries: gen_queries(count),
+			used: 0,
+		}
+	}
+
+	/// Doubles the pool if all queries are in use.
+	fn acquire(&mut self) -> u32 {
+		if self.used == self.queries.len() {
+			let count = self.queries.len().max(1);
+			self.queries.extend(gen_queries(count));
+		}
+
+		self.used += 1;
+		self.queries[self.used - 1]
+	}
+}
+
+struct Scope {
 	name: String,
-	timer_handle: u32,
-	geo_handle: u32,
+	parent: Option<usize>,
+	depth: usize,
+
+	cpu_start: Instant,
+	cpu_end: Instant,
+
+	start_query: u32,
+	end_query: u32,
+
+	counters: CounterSet,
+
+	/// Workgroups tallied on the CPU. Starts at zero for every counter in `counters`,
+	/// so query results can be added on once available
+	tallied: CounterValues,
+
+	segments: Vec<Segment>,
 }
 
-impl Section {
-	fn new(_gl_ctx: &gl::Context) -> Section {
-		unsafe {
-			let mut handles = [0; 2];
-			gl::raw::GenQueries(2, handles.as_mut_ptr());
-
-			let [timer_handle, geo_handle] = handles;
-			Section {
-				name: String::new(),
-				timer_handle,
-				geo_handle,
+/// A stretch of a scope not covered by any child, with a query for primitives and each pipeline statistic.
+struct Segment {
+	primitives: u32,
+	statistics: Vec<(Counter, u32)>,
+}
+
+impl Scope {
+	fn queries(&self) -> impl Iterator<Item=u32> + '_ {
+		std::iter::once(self.start_query)
+			.chain(std::iter::once(self.end_query))
+			.chain(self.segments.iter().flat_map(Segment::queries))
+	}
+
+	/// The segment currently being counted, while the scope has no open children.
+	fn current_segment(&self) -> &Segment {
+		self.segments.last()
+			.expect("Scope has no segments")
+	}
+
+	fn ready(&self) -> bool {
+		self.queries().all(|query| unsafe {
+			let mut ready = 0;
+			gl::raw::GetQueryObjectiv(query, gl::raw::QUERY_RESULT_AVAILABLE, &mut ready);
+			ready != 0
+		})
+	}
+
+	fn result(&self, frame_start: Instant) -> frame::RawScope {
+		let self_triangles = self.segments.iter()
+			.map(|segment| query_result(segment.primitives) as usize)
+			.sum();
+
+		let mut self_counters = self.tallied;
+
+		for segment in self.segments.iter() {
+			for &(counter, query) in segment.statistics.iter() {
+				self_counters.add(counter, query_result(query));
 			}
 		}
+
+		frame::RawScope {
+			name: self.name.clone(),
+			parent: self.parent,
+			depth: self.depth,
+			gpu_start_ns: query_result(self.start_query),
+			gpu_end_ns: query_result(self.end_query),
+			cpu_start_ns: self.cpu_start.saturating_duration_since(frame_start).as_nanos() as u64,
+			cpu_end_ns: self.cpu_end.saturating_duration_since(frame_start).as_nanos() as u64,
+			self_triangles,
+			self_counters,
+		}
 	}
+}
+
+
+impl Segment {
+	fn begin(queries: &mut QuerySet, counters: CounterSet) -> Segment {
+		let statistics = counters.iter()
+			.filter(|counter| counter.is_pipeline_statistic())
+			.map(|counter| (counter, queries.statistics[counter.index()].acquire()))
+			.collect();
+
+		let segment = Segment {
+			primitives: queries.primitives.acquire(),
+			statistics,
+		};
 
-	fn start(&mut self, name: String) {
 		unsafe {
-			self.name = name;
-			gl::raw::BeginQuery(gl::raw::PRIMITIVES_GENERATED, self.geo_handle);
-			gl::raw::BeginQuery(gl::raw::TIME_ELAPSED, self.timer_handle);
+			gl::raw::BeginQuery(gl::raw::PRIMITIVES_GENERATED, segment.primitives);
+
+			for &(counter, query) in segment.statistics.iter() {
+				gl::raw::BeginQuery(statistic_target(counter), query);
+			}
 		}
+
+		segment
 	}
 
-	fn end() {
+	fn end(&self) {
 		unsafe {
 			gl::raw::EndQuery(gl::raw::PRIMITIVES_GENERATED);
-			gl::raw::EndQuery(gl::raw::TIME_ELAPSED);
+
+			for &(counter, _) in self.statistics.iter() {
+				gl::raw::EndQuery(statistic_target(counter));
+			}
 		}
 	}
 
-	fn ready(&self) -> bool {
-		unsafe {
-			let mut timer_ready = 0;
-			let mut geo_ready = 0;
-			gl::raw::GetQueryObjectiv(self.timer_handle, gl::raw::QUERY_RESULT_AVAILABLE, &mut timer_ready);
-			gl::raw::GetQueryObjectiv(self.geo_handle, gl::raw::QUERY_RESULT_AVAILABLE, &mut geo_ready);
-			(timer_ready != 0) && (geo_ready != 0)
+	fn queries(&self) -> impl Iterator<Item=u32> + '_ {
+		std::iter::once(self.primitives)
+			.chain(self.statistics.iter().map(|&(_, query)| query))
+	}
+}
+
+
+fn gen_queries(count: usize) -> Vec<u32> {
+	let mut queries = vec![0; count];
+	unsafe {
+		gl::raw::GenQueries(count as _, queries.as_mut_ptr());
+	}
+	queries
+}
+
+fn statistic_target(counter: Counter) -> u32 {
+	match counter {
+		Counter::VertexShaderInvocations => gl::raw::VERTEX_SHADER_INVOCATIONS_ARB,
+		Counter::ClippingInputPrimitives => gl::raw::CLIPPING_INPUT_PRIMITIVES_ARB,
+		Counter::ClippingOutputPrimitives => gl::raw::CLIPPING_OUTPUT_PRIMITIVES_ARB,
+		Counter::FragmentShaderInvocations => gl::raw::FRAGMENT_SHADER_INVOCATIONS_ARB,
+		Counter::ComputeShaderInvocations => gl::raw::COMPUTE_SHADER_INVOCATIONS_ARB,
+		Counter::TaskWorkgroups | Counter::MeshWorkgroups => unreachable!("{} is tallied, not queried", counter),
+	}
+}
+
+fn query_result(query: u32) -> u64 {
+	let mut value = 0;
+	unsafe {
+		gl::raw::GetQueryObjectui64v(query, gl::raw::QUERY_RESULT, &mut value);
+	}
+	value
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn guard_ends_sections_left_open_inside() {
+		let mut inst = Instrumenter::disabled();
+		inst.start_section("outer");
+
+		{
+			let mut guard = inst.scope("guarded");
+			guard.start_section("inner");
+			guard.start_section("innermost");
+			assert_eq!(guard.open_scopes.len(), 4);
 		}
+
+		assert_eq!(inst.open_scopes.len(), 1);
+		inst.end_section();
+		assert!(inst.open_scopes.is_empty());
 	}
 
-	fn result(&self) -> (usize, usize) {
-		unsafe {
-			let mut timer_value = 0;
-			let mut geo_value = 0;
-			gl::raw::GetQueryObjectiv(self.timer_handle, gl::raw::QUERY_RESULT, &mut timer_value);
-			gl::raw::GetQueryObjectiv(self.geo_handle, gl::raw::QUERY_RESULT, &mut geo_value);
-			(timer_value as usize, geo_value as usize)
+	#[test]
+	fn guard_ends_nothing_after_frame_ends() {
+		let mut inst = Instrumenter::disabled();
+
+		let mut guard = inst.scope("guarded");
+		guard.end_frame();
+		drop(guard);
+
+		assert!(inst.open_scopes.is_empty());
+	}
+
+	#[test]
+	fn with_section_ends_on_panic() {
+		let mut inst = Instrumenter::disabled();
+
+		let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+			inst.with_section("section", |inst| {
+				inst.start_section("left open");
+				panic!("section failed");
+			})
+		}));
+
+		assert!(result.is_err());
+		assert!(inst.open_scopes.is_empty());
+	}
+
+	#[test]
+	fn perf_scope_lasts_until_end_of_block() {
+		let inst = &mut Instrumenter::disabled();
+
+		{
+			perf_scope!(inst, "block");
+			let expected_depth = if cfg!(feature = "profiling") { 1 } else { 0 };
+			assert_eq!(inst.open_scopes.len(), expected_depth);
 		}
+
+		assert!(inst.open_scopes.is_empty());
 	}
 }
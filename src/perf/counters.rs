@@ -0,0 +1,187 @@
+use std::fmt;
+
+
+/// Optional counters that sections can opt into, on top of time and primitives generated.
+///
+/// Most are counted by the GPU with `ARB_pipeline_statistics_query`. GL has no query for task or
+/// mesh shader invocations, so workgroups are instead tallied on the CPU as draws are submitted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Counter {
+	TaskWorkgroups,
+	MeshWorkgroups,
+	VertexShaderInvocations,
+	ClippingInputPrimitives,
+	ClippingOutputPrimitives,
+	FragmentShaderInvocations,
+	ComputeShaderInvocations,
+}
+
+/// A set of counters, see `Instrumenter::start_section_with_counters`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CounterSet(u16);
+
+/// Counter values for one scope. Counters the scope didn't count are None.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CounterValues([Option<u64>; Counter::ALL.len()]);
+
+
+impl Counter {
+	pub const ALL: [Counter; 7] = [
+		Counter::TaskWorkgroups,
+		Counter::MeshWorkgroups,
+		Counter::VertexShaderInvocations,
+		Counter::ClippingInputPrimitives,
+		Counter::ClippingOutputPrimitives,
+		Counter::FragmentShaderInvocations,
+		Counter::ComputeShaderInvocations,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Counter::TaskWorkgroups => "task workgroups",
+			Counter::MeshWorkgroups => "mesh workgroups",
+			Counter::VertexShaderInvocations => "vertex invocations",
+			Counter::ClippingInputPrimitives => "clipping input",
+			Counter::ClippingOutputPrimitives => "clipping output",
+			Counter::FragmentShaderInvocations => "fragment invocations",
+			Counter::ComputeShaderInvocations => "compute invocations",
+		}
+	}
+
+	/// Column heading for reports.
+	pub fn short_name(self) -> &'static str {
+		match self {
+			Counter::TaskWorkgroups => "task wg",
+			Counter::MeshWorkgroups => "mesh wg",
+			Counter::VertexShaderInvocations => "vert inv",
+			Counter::ClippingInputPrimitives => "clip in",
+			Counter::ClippingOutputPrimitives => "clip out",
+			Counter::FragmentShaderInvocations => "frag inv",
+			Counter::ComputeShaderInvocations => "comp inv",
+		}
+	}
+
+	/// Whether this is counted by the GPU, rather than tallied from draw calls.
+	pub fn is_pipeline_statistic(self) -> bool {
+		!matches!(self, Counter::TaskWorkgroups | Counter::MeshWorkgroups)
+	}
+
+	pub(super) fn index(self) -> usize {
+		self as usize
+	}
+}
+
+
+impl CounterSet {
+	pub const NONE: CounterSet = CounterSet(0);
+	pub const ALL: CounterSet = CounterSet((1 << Counter::ALL.len()) - 1);
+
+	/// Counters that only need draw calls to be tallied, and so are always available.
+	pub const WORKGROUPS: CounterSet = CounterSet(1 << Counter::TaskWorkgroups as u16 | 1 << Counter::MeshWorkgroups as u16);
+
+	/// Counters that need `ARB_pipeline_statistics_query`.
+	pub const PIPELINE_STATISTICS: CounterSet = CounterSet(CounterSet::ALL.0 & !CounterSet::WORKGROUPS.0);
+
+	pub fn with(self, counter: Counter) -> CounterSet {
+		CounterSet(self.0 | 1 << counter.index())
+	}
+
+	pub fn union(self, other: CounterSet) -> CounterSet {
+		CounterSet(self.0 | other.0)
+	}
+
+	pub fn intersection(self, other: CounterSet) -> CounterSet {
+		CounterSet(self.0 & other.0)
+	}
+
+	pub fn contains(self, counter: Counter) -> bool {
+		self.0 & 1 << counter.index() != 0
+	}
+
+	pub fn is_empty(self) -> bool {
+		self.0 == 0
+	}
+
+	pub fn iter(self) -> impl Iterator<Item=Counter> {
+		Counter::ALL.iter().copied().filter(move |&counter| self.contains(counter))
+	}
+}
+
+
+impl CounterValues {
+	/// All counters in `counters` start at zero, and all others are None.
+	pub fn zeroed(counters: CounterSet) -> CounterValues {
+		let mut values = CounterValues::default();
+
+		for counter in counters.iter() {
+			values.0[counter.index()] = Some(0);
+		}
+
+		values
+	}
+
+	pub fn get(&self, counter: Counter) -> Option<u64> {
+		self.0[counter.index()]
+	}
+
+	/// Does nothing if `counter` isn't being counted.
+	pub fn add(&mut self, counter: Counter, value: u64) {
+		if let Some(total) = &mut self.0[counter.index()] {
+			*total += value;
+		}
+	}
+
+	/// Adds every counter present in both.
+	pub fn accumulate(&mut self, other: &CounterValues) {
+		for counter in Counter::ALL.iter().copied() {
+			if let Some(value) = other.get(counter) {
+				self.add(counter, value);
+			}
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item=(Counter, u64)> + '_ {
+		Counter::ALL.iter().copied()
+			.filter_map(move |counter| Some((counter, self.get(counter)?)))
+	}
+}
+
+
+impl fmt::Display for Counter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn only_accumulates_counted_values() {
+		let counters = CounterSet::NONE
+			.with(Counter::MeshWorkgroups)
+			.with(Counter::FragmentShaderInvocations);
+
+		assert!(counters.contains(Counter::MeshWorkgroups));
+		assert!(!counters.contains(Counter::TaskWorkgroups));
+		assert_eq!(counters.iter().collect(): Vec<_>, [Counter::MeshWorkgroups, Counter::FragmentShaderInvocations]);
+
+		let mut parent = CounterValues::zeroed(counters);
+		parent.add(Counter::MeshWorkgroups, 4);
+		parent.add(Counter::ComputeShaderInvocations, 100);
+
+		let mut child = CounterValues::zeroed(CounterSet::ALL);
+		child.add(Counter::FragmentShaderInvocations, 30);
+		child.add(Counter::ComputeShaderInvocations, 10);
+
+		parent.accumulate(&child);
+
+		assert_eq!(parent.get(Counter::MeshWorkgroups), Some(4));
+		assert_eq!(parent.get(Counter::FragmentShaderInvocations), Some(30));
+		assert_eq!(parent.get(Counter::ComputeShaderInvocations), None);
+		assert_eq!(parent.iter().count(), 2);
+	}
+}
@@ -0,0 +1,260 @@
+use super::CounterValues;
+
+
+/// The results of every scope recorded during one frame, and timings for the frame as a whole.
+/// Scopes are listed in the order they were started, so parents always precede their children.
+#[derive(Clone, Debug, Default)]
+pub struct FrameResults {
+	pub scopes: Vec<ScopeResult>,
+
+	/// Counts every frame ended by the instrumenter, including those without results
+	pub index: u64,
+
+	/// When the frame started, relative to when the instrumenter was created
+	pub cpu_start_ns: u64,
+
+	/// Wall time from the end of the previous frame to the end of this one
+	pub cpu_time_ns: u64,
+
+	/// Time spent blocked in buffer swaps, which is included in `cpu_time_ns`
+	pub swap_wait_ns: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeResult {
+	pub name: String,
+
+	/// Index into `FrameResults::scopes`, or None for top level scopes
+	pub parent: Option<usize>,
+	pub depth: usize,
+
+	/// Timestamps relative to the start of the first scope in the frame
+	pub gpu_start_ns: u64,
+	pub gpu_end_ns: u64,
+
+	/// Timestamps relative to the start of the frame
+	pub cpu_start_ns: u64,
+	pub cpu_end_ns: u64,
+
+	/// Time not spent in any child scope
+	pub gpu_self_time_ns: u64,
+	pub cpu_self_time_ns: u64,
+
+	/// Primitives generated by this scope and all of its children
+	pub triangles: usize,
+	pub self_triangles: usize,
+
+	/// Counters the scope opted into, including those of its children
+	pub counters: CounterValues,
+	pub self_counters: CounterValues,
+}
+
+/// Whichever of the CPU or GPU took longer to process a frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+	Cpu,
+	Gpu,
+}
+
+/// CPU side timings for a frame, gathered while it is recorded.
+#[derive(Copy, Clone, Debug, Default)]
+pub(super) struct FrameTiming {
+	pub index: u64,
+	pub cpu_start_ns: u64,
+	pub cpu_time_ns: u64,
+	pub swap_wait_ns: u64,
+}
+
+/// Raw query results for a scope, before self times and inclusive counts are derived.
+#[derive(Clone, Debug)]
+pub(super) struct RawScope {
+	pub name: String,
+	pub parent: Option<usize>,
+	pub depth: usize,
+	pub gpu_start_ns: u64,
+	pub gpu_end_ns: u64,
+	pub cpu_start_ns: u64,
+	pub cpu_end_ns: u64,
+	pub self_triangles: usize,
+	pub self_counters: CounterValues,
+}
+
+
+impl FrameResults {
+	/// `scopes` must be ordered such that parents precede their children.
+	pub(super) fn from_raw(scopes: Vec<RawScope>, timing: FrameTiming) -> FrameResults {
+		let origin = scopes.iter().map(|scope| scope.gpu_start_ns).min().unwrap_or(0);
+
+		let mut results = scopes.into_iter()
+			.map(|raw| ScopeResult {
+				gpu_start_ns: raw.gpu_start_ns.saturating_sub(origin),
+				gpu_end_ns: raw.gpu_end_ns.saturating_sub(origin),
+				gpu_self_time_ns: raw.gpu_end_ns.saturating_sub(raw.gpu_start_ns),
+
+				cpu_start_ns: raw.cpu_start_ns,
+				cpu_end_ns: raw.cpu_end_ns,
+				cpu_self_time_ns: raw.cpu_end_ns.saturating_sub(raw.cpu_start_ns),
+
+				triangles: raw.self_triangles,
+				self_triangles: raw.self_triangles,
+
+				counters: raw.self_counters,
+				self_counters: raw.self_counters,
+
+				name: raw.name,
+				parent: raw.parent,
+				depth: raw.depth,
+			})
+			.collect(): Vec<_>;
+
+		// Walk backwards so that every child is folded into its parent before the parent is folded into its own
+		for index in (0..results.len()).rev() {
+			if let Some(parent) = results[index].parent {
+				let child_gpu_time = results[index].gpu_time_ns();
+				let child_cpu_time = results[index].cpu_time_ns();
+				let child_triangles = results[index].triangles;
+				let child_counters = results[index].counters;
+
+				let parent = &mut results[parent];
+				parent.gpu_self_time_ns = parent.gpu_self_time_ns.saturating_sub(child_gpu_time);
+				parent.cpu_self_time_ns = parent.cpu_self_time_ns.saturating_sub(child_cpu_time);
+				parent.triangles += child_triangles;
+				parent.counters.accumulate(&child_counters);
+			}
+		}
+
+		FrameResults {
+			scopes: results,
+			index: timing.index,
+			cpu_start_ns: timing.cpu_start_ns,
+			cpu_time_ns: timing.cpu_time_ns,
+			swap_wait_ns: timing.swap_wait_ns,
+		}
+	}
+
+	/// Total GPU time of all top level scopes.
+	pub fn gpu_time_ns(&self) -> u64 {
+		self.roots().map(ScopeResult::gpu_time_ns).sum()
+	}
+
+	/// CPU time not spent waiting on buffer swaps.
+	pub fn cpu_busy_ns(&self) -> u64 {
+		self.cpu_time_ns.saturating_sub(self.swap_wait_ns)
+	}
+
+	pub fn bound(&self) -> Bound {
+		match self.gpu_time_ns() > self.cpu_busy_ns() {
+			true => Bound::Gpu,
+			false => Bound::Cpu,
+		}
+	}
+
+	pub fn triangles(&self) -> usize {
+		self.roots().map(|scope| scope.triangles).sum()
+	}
+
+	pub fn roots(&self) -> impl Iterator<Item=&ScopeResult> {
+		self.scopes.iter().filter(|scope| scope.parent.is_none())
+	}
+
+	pub fn children(&self, index: usize) -> impl Iterator<Item=&ScopeResult> {
+		self.scopes.iter().filter(move |scope| scope.parent == Some(index))
+	}
+
+	/// The names of a scope and all its ancestors, joined with '/'.
+	pub fn path(&self, index: usize) -> String {
+		let scope = &self.scopes[index];
+
+		match scope.parent {
+			Some(parent) => format!("{}/{}", self.path(parent), scope.name),
+			None => scope.name.clone(),
+		}
+	}
+}
+
+
+impl ScopeResult {
+	/// Time spent in this scope, including its children.
+	pub fn gpu_time_ns(&self) -> u64 {
+		self.gpu_end_ns.saturating_sub(self.gpu_start_ns)
+	}
+
+	/// Wall time between starting and ending this scope, including its children.
+	pub fn cpu_time_ns(&self) -> u64 {
+		self.cpu_end_ns.saturating_sub(self.cpu_start_ns)
+	}
+}
+
+
+impl Bound {
+	pub fn name(self) -> &'static str {
+		match self {
+			Bound::Cpu => "CPU-bound",
+			Bound::Gpu => "GPU-bound",
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+pub(super) mod test {
+	use super::*;
+
+	fn raw(name: &str, parent: Option<usize>, depth: usize, start: u64, end: u64, triangles: usize) -> RawScope {
+		RawScope {
+			name: name.into(),
+			parent,
+			depth,
+			gpu_start_ns: start,
+			gpu_end_ns: end,
+			cpu_start_ns: start / 10,
+			cpu_end_ns: end / 10,
+			self_triangles: triangles,
+			self_counters: CounterValues::default(),
+		}
+	}
+
+	pub(in crate::perf) fn timing(cpu_time_ns: u64, swap_wait_ns: u64) -> FrameTiming {
+		FrameTiming { cpu_time_ns, swap_wait_ns, ..FrameTiming::default() }
+	}
+
+	#[test]
+	fn derives_self_and_inclusive_values() {
+		let frame = FrameResults::from_raw(vec![
+			raw("scene", None, 0, 1000, 1100, 0),
+			raw("cull", Some(0), 1, 1010, 1030, 0),
+			raw("draw", Some(0), 1, 1030, 1090, 50),
+			raw("inner", Some(2), 2, 1040, 1050, 10),
+			raw("paint", None, 0, 1100, 1105, 2),
+		], timing(200, 50));
+
+		let scene = &frame.scopes[0];
+		assert_eq!(scene.gpu_start_ns, 0);
+		assert_eq!(scene.gpu_time_ns(), 100);
+		assert_eq!(scene.gpu_self_time_ns, 20);
+		assert_eq!(scene.cpu_time_ns(), 10);
+		assert_eq!(scene.cpu_self_time_ns, 2);
+		assert_eq!(scene.triangles, 60);
+		assert_eq!(scene.self_triangles, 0);
+
+		let draw = &frame.scopes[2];
+		assert_eq!(draw.gpu_self_time_ns, 50);
+		assert_eq!(draw.triangles, 60);
+
+		assert_eq!(frame.gpu_time_ns(), 105);
+		assert_eq!(frame.triangles(), 62);
+		assert_eq!(frame.path(3), "scene/draw/inner");
+
+		let children = frame.children(0).map(|scope| scope.name.as_str()).collect(): Vec<_>;
+		assert_eq!(children, ["cull", "draw"]);
+	}
+
+	#[test]
+	fn compares_gpu_time_against_busy_cpu_time() {
+		let scopes = || vec![raw("scene", None, 0, 0, 100, 0)];
+
+		assert_eq!(FrameResults::from_raw(scopes(), timing(150, 0)).bound(), Bound::Cpu);
+		assert_eq!(FrameResults::from_raw(scopes(), timing(150, 60)).bound(), Bound::Gpu);
+	}
+}
@@ -0,0 +1,299 @@
+use std::cell::{Ref, RefCell};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+use super::{FrameResults, Statistics};
+use super::trace::write_json_string;
+
+
+/// Receives results from an `Instrumenter`. Any number of sinks can be added with `Instrumenter::add_sink`.
+/// A sink that returns an error is logged and removed.
+pub trait ProfileSink {
+	/// Called for every frame as soon as its results are available, in frame order.
+	/// `stats` already include the frame.
+	fn frame(&mut self, frame: &FrameResults, stats: &Statistics) -> io::Result<()>;
+
+	/// Called every report interval, and whenever a report is requested with `Instrumenter::report`.
+	fn report(&mut self, _stats: &Statistics) -> io::Result<()> {
+		Ok(())
+	}
+
+	/// Called by `Instrumenter::flush`, once every pending frame has been sent.
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+
+/// Prints the statistics table to stdout on every report, and ignores individual frames.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StdoutSink;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+	/// One row per scope per frame, plus one for the frame as a whole
+	Csv,
+
+	/// One JSON object per frame
+	JsonLines,
+}
+
+/// Writes every frame to a file, or any other writer.
+pub struct FileSink<W: Write = BufWriter<File>> {
+	out: W,
+	format: FileFormat,
+	wrote_header: bool,
+}
+
+/// Keeps the most recent frames in memory.
+/// Clones share the same frames, so one can be added to an instrumenter while another is kept to read from.
+#[derive(Clone, Debug)]
+pub struct MemorySink {
+	frames: Rc<RefCell<VecDeque<FrameResults>>>,
+	capacity: usize,
+}
+
+/// Calls a closure for every frame.
+pub struct CallbackSink<F> {
+	callback: F,
+}
+
+
+impl ProfileSink for StdoutSink {
+	fn frame(&mut self, _: &FrameResults, _: &Statistics) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn report(&mut self, stats: &Statistics) -> io::Result<()> {
+		write!(io::stdout().lock(), "{}", stats)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		io::stdout().flush()
+	}
+}
+
+
+impl FileSink {
+	/// Picks the format from the extension of `path`, which is CSV unless it ends in .json or .jsonl.
+	pub fn create(path: &Path) -> io::Result<FileSink> {
+		let format = match path.extension().and_then(|ext| ext.to_str()) {
+			Some("json") | Some("jsonl") => FileFormat::JsonLines,
+			_ => FileFormat::Csv,
+		};
+
+		Ok(FileSink::new(BufWriter::new(File::create(path)?), format))
+	}
+}
+
+impl<W: Write> FileSink<W> {
+	pub fn new(out: W, format: FileFormat) -> FileSink<W> {
+		FileSink {
+			out,
+			format,
+			wrote_header: false,
+		}
+	}
+
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+
+	fn write_csv(&mut self, frame: &FrameResults) -> io::Result<()> {
+		if !self.wrote_header {
+			writeln!(self.out, "frame,section,depth,gpu_ms,gpu_self_ms,cpu_ms,cpu_self_ms,triangles")?;
+			self.wrote_header = true;
+		}
+
+		let ms = |ns: u64| ns as f64 / 1.0e6;
+
+		// Self times don't apply to the frame as a whole, and its CPU time excludes swap waits
+		writeln!(self.out, "{},(frame),,{:.4},,{:.4},,{}", frame.index,
+			ms(frame.gpu_time_ns()), ms(frame.cpu_busy_ns()), frame.triangles())?;
+
+		for (index, scope) in frame.scopes.iter().enumerate() {
+			writeln!(self.out, "{},{},{},{:.4},{:.4},{:.4},{:.4},{}", frame.index, csv_field(&frame.path(index)), scope.depth,
+				ms(scope.gpu_time_ns()), ms(scope.gpu_self_time_ns),
+				ms(scope.cpu_time_ns()), ms(scope.cpu_self_time_ns),
+				scope.triangles)?;
+		}
+
+		Ok(())
+	}
+
+	fn write_json_line(&mut self, frame: &FrameResults) -> io::Result<()> {
+		let out = &mut self.out;
+
+		write!(out, "{{\"index\":{},\"cpu_start_ns\":{},\"cpu_time_ns\":{},\"swap_wait_ns\":{},\"scopes\":[",
+			frame.index, frame.cpu_start_ns, frame.cpu_time_ns, frame.swap_wait_ns)?;
+
+		for (index, scope) in frame.scopes.iter().enumerate() {
+			if index > 0 {
+				write!(out, ",")?;
+			}
+
+			write!(out, "{{\"name\":")?;
+			write_json_string(out, &scope.name)?;
+
+			match scope.parent {
+				Some(parent) => write!(out, ",\"parent\":{}", parent)?,
+				None => write!(out, ",\"parent\":null")?,
+			}
+
+			write!(out, ",\"depth\":{},\"gpu_start_ns\":{},\"gpu_end_ns\":{},\"cpu_start_ns\":{},\"cpu_end_ns\":{},\"triangles\":{},\"counters\":{{",
+				scope.depth, scope.gpu_start_ns, scope.gpu_end_ns, scope.cpu_start_ns, scope.cpu_end_ns, scope.triangles)?;
+
+			for (counter_index, (counter, value)) in scope.counters.iter().enumerate() {
+				if counter_index > 0 {
+					write!(out, ",")?;
+				}
+
+				write!(out, "\"{}\":{}", counter.name(), value)?;
+			}
+
+			write!(out, "}}}}")?;
+		}
+
+		writeln!(out, "]}}")
+	}
+}
+
+impl<W: Write> ProfileSink for FileSink<W> {
+	fn frame(&mut self, frame: &FrameResults, _: &Statistics) -> io::Result<()> {
+		match self.format {
+			FileFormat::Csv => self.write_csv(frame),
+			FileFormat::JsonLines => self.write_json_line(frame),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.out.flush()
+	}
+}
+
+
+impl MemorySink {
+	pub fn new(capacity: usize) -> MemorySink {
+		MemorySink {
+			frames: Rc::new(RefCell::new(VecDeque::with_capacity(capacity))),
+			capacity: capacity.max(1),
+		}
+	}
+
+	/// Oldest first.
+	pub fn frames(&self) -> Ref<'_, VecDeque<FrameResults>> {
+		self.frames.borrow()
+	}
+
+	pub fn take_frames(&self) -> Vec<FrameResults> {
+		self.frames.borrow_mut().drain(..).collect()
+	}
+
+	pub fn clear(&self) {
+		self.frames.borrow_mut().clear();
+	}
+}
+
+impl ProfileSink for MemorySink {
+	fn frame(&mut self, frame: &FrameResults, _: &Statistics) -> io::Result<()> {
+		let mut frames = self.frames.borrow_mut();
+
+		if frames.len() == self.capacity {
+			frames.pop_front();
+		}
+
+		frames.push_back(frame.clone());
+		Ok(())
+	}
+}
+
+
+impl<F> CallbackSink<F> where F: FnMut(&FrameResults, &Statistics) {
+	pub fn new(callback: F) -> CallbackSink<F> {
+		CallbackSink { callback }
+	}
+}
+
+impl<F> ProfileSink for CallbackSink<F> where F: FnMut(&FrameResults, &Statistics) {
+	fn frame(&mut self, frame: &FrameResults, stats: &Statistics) -> io::Result<()> {
+		(self.callback)(frame, stats);
+		Ok(())
+	}
+}
+
+
+pub(crate) fn csv_field(value: &str) -> String {
+	match value.contains(|c| c == ',' || c == '"') {
+		true => format!("\"{}\"", value.replace('"', "\"\"")),
+		false => value.into(),
+	}
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use super::super::frame::{FrameTiming, RawScope};
+
+	fn frame(index: u64) -> FrameResults {
+		let scope = |name: &str, parent, depth, self_triangles| RawScope {
+			name: name.into(),
+			parent,
+			depth,
+			gpu_start_ns: 0,
+			gpu_end_ns: 2_000_000,
+			cpu_start_ns: 0,
+			cpu_end_ns: 1_000_000,
+			self_triangles,
+			self_counters: Default::default(),
+		};
+
+		FrameResults::from_raw(vec![
+			scope("draw", None, 0, 0),
+			scope("terrain, far", Some(0), 1, 30),
+		], FrameTiming { index, cpu_start_ns: 0, cpu_time_ns: 4_000_000, swap_wait_ns: 1_000_000 })
+	}
+
+	#[test]
+	fn memory_sinks_share_recent_frames() {
+		let stats = Statistics::new(10);
+		let reader = MemorySink::new(2);
+		let mut sink = reader.clone();
+
+		for index in 0..3 {
+			sink.frame(&frame(index), &stats).unwrap();
+		}
+
+		assert_eq!(reader.frames().iter().map(|frame| frame.index).collect(): Vec<_>, [1, 2]);
+		assert_eq!(reader.take_frames().len(), 2);
+		assert!(sink.frames().is_empty());
+	}
+
+	#[test]
+	fn writes_csv_rows_and_json_lines() {
+		let stats = Statistics::new(10);
+
+		let mut csv = FileSink::new(Vec::new(), FileFormat::Csv);
+		csv.frame(&frame(7), &stats).unwrap();
+		csv.frame(&frame(8), &stats).unwrap();
+
+		let csv = String::from_utf8(csv.into_inner()).unwrap();
+		let lines = csv.lines().collect(): Vec<_>;
+
+		assert_eq!(lines.len(), 7);
+		assert_eq!(lines[0], "frame,section,depth,gpu_ms,gpu_self_ms,cpu_ms,cpu_self_ms,triangles");
+		assert_eq!(lines[1], "7,(frame),,2.0000,,3.0000,,30");
+		assert_eq!(lines[3], "7,\"draw/terrain, far\",1,2.0000,2.0000,1.0000,1.0000,30");
+
+		let mut json = FileSink::new(Vec::new(), FileFormat::JsonLines);
+		json.frame(&frame(7), &stats).unwrap();
+
+		let json = String::from_utf8(json.into_inner()).unwrap();
+		assert_eq!(json.lines().count(), 1);
+		assert!(json.starts_with("{\"index\":7,\"cpu_start_ns\":0,\"cpu_time_ns\":4000000,\"swap_wait_ns\":1000000,\"scopes\":[{\"name\":\"draw\",\"parent\":null,"));
+		assert!(json.contains("{\"name\":\"terrain, far\",\"parent\":0,\"depth\":1,"));
+	}
+}
@@ -0,0 +1,520 @@
+use std::collections::VecDeque;
+use std::fmt;
+use super::{Bound, Counter, FrameResults};
+
+
+/// The most recent `capacity` samples of some value, oldest first.
+#[derive(Clone, Debug)]
+pub struct RollingWindow {
+	samples: VecDeque<f64>,
+	capacity: usize,
+}
+
+/// Summary of the samples in a `RollingWindow`.
+/// Percentiles interpolate linearly between the closest ranks.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Summary {
+	pub count: usize,
+	pub min: f64,
+	pub max: f64,
+	pub mean: f64,
+	pub median: f64,
+	pub p95: f64,
+	pub p99: f64,
+}
+
+/// Rolling statistics for each scope, and for whole frames, over the last `window_size` frames.
+/// Scopes are identified by their path, and are reported in the order they were first recorded.
+#[derive(Clone, Debug)]
+pub struct Statistics {
+	window_size: usize,
+	scopes: Vec<ScopeStats>,
+	frame: FrameStats,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScopeStats {
+	pub path: String,
+	pub name: String,
+	pub depth: usize,
+
+	/// Including children
+	pub gpu_time_ms: RollingWindow,
+	pub gpu_self_time_ms: RollingWindow,
+	pub cpu_time_ms: RollingWindow,
+	pub cpu_self_time_ms: RollingWindow,
+	pub triangles: RollingWindow,
+
+	/// One window per `Counter`, which stays empty unless the scope counts it
+	counters: Vec<RollingWindow>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+	pub gpu_time_ms: RollingWindow,
+	pub cpu_time_ms: RollingWindow,
+
+	/// CPU time not spent waiting on buffer swaps
+	pub cpu_busy_ms: RollingWindow,
+	pub swap_wait_ms: RollingWindow,
+	pub triangles: RollingWindow,
+}
+
+
+impl RollingWindow {
+	pub fn new(capacity: usize) -> RollingWindow {
+		RollingWindow {
+			samples: VecDeque::with_capacity(capacity),
+			capacity: capacity.max(1),
+		}
+	}
+
+	pub fn push(&mut self, value: f64) {
+		if self.samples.len() == self.capacity {
+			self.samples.pop_front();
+		}
+
+		self.samples.push_back(value);
+	}
+
+	/// Discards the oldest samples if the window shrinks.
+	pub fn set_capacity(&mut self, capacity: usize) {
+		self.capacity = capacity.max(1);
+
+		while self.samples.len() > self.capacity {
+			self.samples.pop_front();
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.samples.clear();
+	}
+
+	pub fn len(&self) -> usize {
+		self.samples.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.samples.is_empty()
+	}
+
+	pub fn summary(&self) -> Option<Summary> {
+		if self.samples.is_empty() {
+			return None
+		}
+
+		let mut sorted = self.samples.iter().copied().collect(): Vec<_>;
+		sorted.sort_by(f64::total_cmp);
+
+		let count = sorted.len();
+
+		Some(Summary {
+			count,
+			min: sorted[0],
+			max: sorted[count - 1],
+			mean: sorted.iter().sum::<f64>() / count as f64,
+			median: percentile(&sorted, 0.5),
+			p95: percentile(&sorted, 0.95),
+			p99: percentile(&sorted, 0.99),
+		})
+	}
+}
+
+
+impl Statistics {
+	pub fn new(window_size: usize) -> Statistics {
+		Statistics {
+			window_size,
+			scopes: Vec::new(),
+			frame: FrameStats::new(window_size),
+		}
+	}
+
+	pub fn window_size(&self) -> usize {
+		self.window_size
+	}
+
+	pub fn set_window_size(&mut self, window_size: usize) {
+		self.window_size = window_size;
+
+		for stats in self.scopes.iter_mut() {
+			for window in stats.windows_mut().iter_mut() {
+				window.set_capacity(window_size);
+			}
+		}
+
+		for window in self.frame.windows_mut().iter_mut() {
+			window.set_capacity(window_size);
+		}
+	}
+
+	pub fn record_frame(&mut self, frame: &FrameResults) {
+		for (index, scope) in frame.scopes.iter().enumerate() {
+			let path = frame.path(index);
+
+			let stats_index = match self.scopes.iter().position(|stats| stats.path == path) {
+				Some(stats_index) => stats_index,
+				None => {
+					self.scopes.push(ScopeStats::new(&path, &scope.name, scope.depth, self.window_size));
+					self.scopes.len() - 1
+				}
+			};
+
+			let stats = &mut self.scopes[stats_index];
+			stats.gpu_time_ms.push(nanos_to_ms(scope.gpu_time_ns()));
+			stats.gpu_self_time_ms.push(nanos_to_ms(scope.gpu_self_time_ns));
+			stats.cpu_time_ms.push(nanos_to_ms(scope.cpu_time_ns()));
+			stats.cpu_self_time_ms.push(nanos_to_ms(scope.cpu_self_time_ns));
+			stats.triangles.push(scope.triangles as f64);
+
+			for (counter, value) in scope.counters.iter() {
+				stats.counters[counter.index()].push(value as f64);
+			}
+		}
+
+		self.frame.gpu_time_ms.push(nanos_to_ms(frame.gpu_time_ns()));
+		self.frame.cpu_time_ms.push(nanos_to_ms(frame.cpu_time_ns));
+		self.frame.cpu_busy_ms.push(nanos_to_ms(frame.cpu_busy_ns()));
+		self.frame.swap_wait_ms.push(nanos_to_ms(frame.swap_wait_ns));
+		self.frame.triangles.push(frame.triangles() as f64);
+	}
+
+	/// Stats for the scope at `path`, as returned by `FrameResults::path`.
+	pub fn scope(&self, path: &str) -> Option<&ScopeStats> {
+		self.scopes.iter().find(|stats| stats.path == path)
+	}
+
+	pub fn scopes(&self) -> &[ScopeStats] {
+		&self.scopes
+	}
+
+	pub fn frame(&self) -> &FrameStats {
+		&self.frame
+	}
+
+	/// Whichever of the CPU or GPU took longer on average, or None if no frames have been recorded.
+	pub fn bound(&self) -> Option<Bound> {
+		let gpu = self.frame.gpu_time_ms.summary()?;
+		let cpu_busy = self.frame.cpu_busy_ms.summary()?;
+
+		match gpu.mean > cpu_busy.mean {
+			true => Some(Bound::Gpu),
+			false => Some(Bound::Cpu),
+		}
+	}
+
+	/// Forgets all samples and scopes.
+	pub fn reset(&mut self) {
+		self.scopes.clear();
+
+		for window in self.frame.windows_mut().iter_mut() {
+			window.clear();
+		}
+	}
+}
+
+
+impl ScopeStats {
+	fn new(path: &str, name: &str, depth: usize, window_size: usize) -> ScopeStats {
+		ScopeStats {
+			path: path.into(),
+			name: name.into(),
+			depth,
+			gpu_time_ms: RollingWindow::new(window_size),
+			gpu_self_time_ms: RollingWindow::new(window_size),
+			cpu_time_ms: RollingWindow::new(window_size),
+			cpu_self_time_ms: RollingWindow::new(window_size),
+			triangles: RollingWindow::new(window_size),
+			counters: Counter::ALL.iter().map(|_| RollingWindow::new(window_size)).collect(),
+		}
+	}
+
+	pub fn counter(&self, counter: Counter) -> &RollingWindow {
+		&self.counters[counter.index()]
+	}
+
+	/// Mean of `counter`, or None if the scope hasn't counted it.
+	pub fn counter_mean(&self, counter: Counter) -> Option<f64> {
+		Some(self.counter(counter).summary()?.mean)
+	}
+
+	pub fn has_counters(&self) -> bool {
+		self.counters.iter().any(|window| !window.is_empty())
+	}
+
+	/// Mean triangles generated per mesh shader workgroup.
+	pub fn triangles_per_mesh_workgroup(&self) -> Option<f64> {
+		ratio(self.triangles.summary()?.mean, self.counter_mean(Counter::MeshWorkgroups)?)
+	}
+
+	/// Mean fragment shader invocations per triangle generated, which shows how much each triangle covers.
+	pub fn fragments_per_triangle(&self) -> Option<f64> {
+		ratio(self.counter_mean(Counter::FragmentShaderInvocations)?, self.triangles.summary()?.mean)
+	}
+
+	fn windows_mut(&mut self) -> Vec<&mut RollingWindow> {
+		let mut windows = vec![&mut self.gpu_time_ms, &mut self.gpu_self_time_ms, &mut self.cpu_time_ms, &mut self.cpu_self_time_ms, &mut self.triangles];
+		windows.extend(self.counters.iter_mut());
+		windows
+	}
+}
+
+
+impl FrameStats {
+	fn new(window_size: usize) -> FrameStats {
+		FrameStats {
+			gpu_time_ms: RollingWindow::new(window_size),
+			cpu_time_ms: RollingWindow::new(window_size),
+			cpu_busy_ms: RollingWindow::new(window_size),
+			swap_wait_ms: RollingWindow::new(window_size),
+			triangles: RollingWindow::new(window_size),
+		}
+	}
+
+	fn windows_mut(&mut self) -> [&mut RollingWindow; 5] {
+		[&mut self.gpu_time_ms, &mut self.cpu_time_ms, &mut self.cpu_busy_ms, &mut self.swap_wait_ms, &mut self.triangles]
+	}
+}
+
+
+impl fmt::Display for Statistics {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let frame_count = self.frame.cpu_time_ms.len();
+
+		write_header(f, "gpu", &format!("{:>9} {:>12}", "self mean", "mean tris"))?;
+		for stats in self.scopes.iter() {
+			let extra = match (stats.gpu_self_time_ms.summary(), stats.triangles.summary()) {
+				(Some(self_time), Some(triangles)) => format!("{:>9.3} {:>12.0}", self_time.mean, triangles.mean),
+				_ => continue,
+			};
+
+			write_row(f, &stats.name, stats.depth, &stats.gpu_time_ms, &extra)?;
+		}
+
+		writeln!(f)?;
+		write_header(f, "cpu", &format!("{:>9}", "self mean"))?;
+		for stats in self.scopes.iter() {
+			let extra = match stats.cpu_self_time_ms.summary() {
+				Some(self_time) => format!("{:>9.3}", self_time.mean),
+				None => continue,
+			};
+
+			write_row(f, &stats.name, stats.depth, &stats.cpu_time_ms, &extra)?;
+		}
+
+		write_counters(f, &self.scopes)?;
+
+		writeln!(f)?;
+		write_header(f, "frame", "")?;
+		write_row(f, "gpu", 1, &self.frame.gpu_time_ms, "")?;
+		write_row(f, "cpu", 1, &self.frame.cpu_time_ms, "")?;
+		write_row(f, "cpu busy", 1, &self.frame.cpu_busy_ms, "")?;
+		write_row(f, "swap wait", 1, &self.frame.swap_wait_ms, "")?;
+
+		if let Some(bound) = self.bound() {
+			writeln!(f, "{} on average over the last {} frames", bound.name(), frame_count)?;
+		}
+
+		Ok(())
+	}
+}
+
+
+/// Writes the mean of each counter for scopes that opted into any, along with derived ratios.
+/// Only counters that some scope counted get a column.
+fn write_counters(f: &mut fmt::Formatter<'_>, scopes: &[ScopeStats]) -> fmt::Result {
+	let counters = Counter::ALL.iter().copied()
+		.filter(|&counter| scopes.iter().any(|stats| !stats.counter(counter).is_empty()))
+		.collect(): Vec<_>;
+
+	if counters.is_empty() {
+		return Ok(())
+	}
+
+	let format_value = |value: Option<f64>, precision: usize| match value {
+		Some(value) => format!("{:>10.precision$}", value, precision = precision),
+		None => format!("{:>10}", "-"),
+	};
+
+	writeln!(f)?;
+	write!(f, "{:<20}", "counters")?;
+	for counter in counters.iter() {
+		write!(f, " {:>10}", counter.short_name())?;
+	}
+	writeln!(f, " {:>10} {:>10}", "tris/mesh", "frags/tri")?;
+
+	for stats in scopes.iter().filter(|stats| stats.has_counters()) {
+		write!(f, "{:<20}", format!("{:indent$}{}", "", stats.name, indent = stats.depth * 2))?;
+
+		for &counter in counters.iter() {
+			write!(f, " {}", format_value(stats.counter_mean(counter), 0))?;
+		}
+
+		writeln!(f, " {} {}", format_value(stats.triangles_per_mesh_workgroup(), 1), format_value(stats.fragments_per_triangle(), 1))?;
+	}
+
+	Ok(())
+}
+
+fn write_header(f: &mut fmt::Formatter<'_>, title: &str, extra: &str) -> fmt::Result {
+	writeln!(f, "{:<20} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {}",
+		title, "min", "max", "mean", "median", "p95", "p99", extra)
+}
+
+/// Writes a row of time statistics in milliseconds, skipping empty windows.
+fn write_row(f: &mut fmt::Formatter<'_>, name: &str, depth: usize, window: &RollingWindow, extra: &str) -> fmt::Result {
+	let time = match window.summary() {
+		Some(time) => time,
+		None => return Ok(()),
+	};
+
+	let name = format!("{:indent$}{}", "", name, indent = depth * 2);
+
+	writeln!(f, "{:<20} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {}",
+		name, time.min, time.max, time.mean, time.median, time.p95, time.p99, extra)
+}
+
+
+fn ratio(numerator: f64, denominator: f64) -> Option<f64> {
+	match denominator > 0.0 {
+		true => Some(numerator / denominator),
+		false => None,
+	}
+}
+
+fn nanos_to_ms(nanos: u64) -> f64 {
+	nanos as f64 / 1000_000.0
+}
+
+/// `sorted` must be non-empty. `fraction` is in [0, 1].
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+	let rank = fraction * (sorted.len() - 1) as f64;
+	let lower = rank.floor() as usize;
+	let upper = rank.ceil() as usize;
+
+	let t = rank - lower as f64;
+	sorted[lower] + (sorted[upper] - sorted[lower]) * t
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn summarises_window() {
+		let mut window = RollingWindow::new(100);
+		assert_eq!(window.summary(), None);
+
+		for value in (1..=100).rev() {
+			window.push(value as f64);
+		}
+
+		let summary = window.summary().unwrap();
+		assert_eq!(summary.count, 100);
+		assert_eq!(summary.min, 1.0);
+		assert_eq!(summary.max, 100.0);
+		assert_eq!(summary.mean, 50.5);
+		assert_eq!(summary.median, 50.5);
+		assert!((summary.p95 - 95.05).abs() < 1e-9);
+		assert!((summary.p99 - 99.01).abs() < 1e-9);
+	}
+
+	#[test]
+	fn window_drops_oldest_samples() {
+		let mut window = RollingWindow::new(3);
+
+		for value in &[10.0, 1.0, 2.0, 3.0] {
+			window.push(*value);
+		}
+
+		assert_eq!(window.summary().unwrap().max, 3.0);
+
+		window.set_capacity(1);
+		assert_eq!(window.len(), 1);
+		assert_eq!(window.summary().unwrap().min, 3.0);
+	}
+
+	#[test]
+	fn scopes_keep_first_recorded_order() {
+		use super::super::frame::{RawScope, test::timing};
+
+		let raw = |name: &str, parent, depth, start, end| RawScope {
+			name: name.into(),
+			parent,
+			depth,
+			gpu_start_ns: start,
+			gpu_end_ns: end,
+			cpu_start_ns: start / 2,
+			cpu_end_ns: end / 2,
+			self_triangles: 100,
+			self_counters: Default::default(),
+		};
+
+		let mut stats = Statistics::new(10);
+		stats.record_frame(&FrameResults::from_raw(vec![
+			raw("terrain", None, 0, 0, 1000_000),
+			raw("paint", None, 0, 1000_000, 3000_000),
+		], timing(4000_000, 0)));
+
+		stats.record_frame(&FrameResults::from_raw(vec![
+			raw("terrain", None, 0, 0, 3000_000),
+			raw("patches", Some(0), 1, 0, 2000_000),
+		], timing(4000_000, 2000_000)));
+
+		let paths = stats.scopes().iter().map(|stats| stats.path.as_str()).collect(): Vec<_>;
+		assert_eq!(paths, ["terrain", "paint", "terrain/patches"]);
+
+		let terrain = stats.scope("terrain").unwrap();
+		assert_eq!(terrain.gpu_time_ms.summary().unwrap().mean, 2.0);
+		assert_eq!(terrain.gpu_self_time_ms.summary().unwrap().max, 1.0);
+		assert_eq!(terrain.cpu_time_ms.summary().unwrap().mean, 1.0);
+		assert_eq!(terrain.triangles.summary().unwrap().max, 200.0);
+
+		// Mean GPU time of 3ms, against a mean busy CPU time of 3ms
+		assert_eq!(stats.frame().gpu_time_ms.summary().unwrap().mean, 3.0);
+		assert_eq!(stats.frame().cpu_busy_ms.summary().unwrap().mean, 3.0);
+		assert_eq!(stats.bound(), Some(Bound::Cpu));
+
+		stats.reset();
+		assert!(stats.scopes().is_empty());
+		assert_eq!(stats.bound(), None);
+	}
+
+	#[test]
+	fn derives_counter_ratios() {
+		use super::super::{CounterSet, CounterValues};
+		use super::super::frame::{RawScope, test::timing};
+
+		let mut counters = CounterValues::zeroed(CounterSet::WORKGROUPS.with(Counter::FragmentShaderInvocations));
+		counters.add(Counter::MeshWorkgroups, 8);
+		counters.add(Counter::FragmentShaderInvocations, 4000);
+
+		let mut stats = Statistics::new(10);
+		stats.record_frame(&FrameResults::from_raw(vec![
+			RawScope {
+				name: "scene".into(),
+				parent: None,
+				depth: 0,
+				gpu_start_ns: 0,
+				gpu_end_ns: 100,
+				cpu_start_ns: 0,
+				cpu_end_ns: 100,
+				self_triangles: 800,
+				self_counters: counters,
+			},
+		], timing(200, 0)));
+
+		let scene = stats.scope("scene").unwrap();
+		assert!(scene.has_counters());
+		assert_eq!(scene.counter_mean(Counter::TaskWorkgroups), Some(0.0));
+		assert_eq!(scene.counter_mean(Counter::ClippingInputPrimitives), None);
+		assert_eq!(scene.triangles_per_mesh_workgroup(), Some(100.0));
+		assert_eq!(scene.fragments_per_triangle(), Some(5.0));
+
+		let report = stats.to_string();
+		assert!(report.contains("mesh wg"));
+		assert!(!report.contains("clip in"));
+	}
+}
@@ -0,0 +1,246 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use super::FrameResults;
+
+
+const DEFAULT_TRACE_FRAMES: usize = 60;
+const DEFAULT_TRACE_PATH: &str = "trace.json";
+
+const PROCESS_ID: u32 = 1;
+const CPU_THREAD_ID: u32 = 1;
+const GPU_THREAD_ID: u32 = 2;
+
+
+/// Collects frame results, and writes them as Chrome Trace Event JSON that can be opened
+/// in chrome://tracing or Perfetto.
+///
+/// Scopes are written as complete events on separate CPU and GPU tracks, with triangle counts as args.
+/// GPU timestamps have no common clock with the CPU, so each frame's GPU scopes are placed relative
+/// to when its first scope was submitted.
+#[derive(Clone, Debug)]
+pub struct TraceRecorder {
+	path: PathBuf,
+	frame_count: usize,
+	frames: Vec<FrameResults>,
+}
+
+/// A trace requested with `--trace [FRAMES] [--trace-output PATH]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceRequest {
+	pub frames: usize,
+	pub path: PathBuf,
+}
+
+
+impl TraceRecorder {
+	pub fn new(path: impl Into<PathBuf>, frame_count: usize) -> TraceRecorder {
+		TraceRecorder {
+			path: path.into(),
+			frame_count,
+			frames: Vec::with_capacity(frame_count),
+		}
+	}
+
+	pub fn path(&self) -> &std::path::Path {
+		&self.path
+	}
+
+	pub fn is_complete(&self) -> bool {
+		self.frames.len() >= self.frame_count
+	}
+
+	pub fn record(&mut self, frame: &FrameResults) {
+		if !self.is_complete() {
+			self.frames.push(frame.clone());
+		}
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		let mut file = io::BufWriter::new(std::fs::File::create(&self.path)?);
+		self.write(&mut file)?;
+		file.flush()
+	}
+
+	pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+		write!(out, "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[")?;
+
+		write!(out, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{},\"tid\":{},\"args\":{{\"name\":\"CPU\"}}}}", PROCESS_ID, CPU_THREAD_ID)?;
+		write!(out, ",\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{},\"tid\":{},\"args\":{{\"name\":\"GPU\"}}}}", PROCESS_ID, GPU_THREAD_ID)?;
+
+		for frame in self.frames.iter() {
+			let frame_name = format!("frame {}", frame.index);
+			let frame_args = format!("{{\"triangles\":{}}}", frame.triangles());
+
+			write_event(out, &frame_name, "frame", CPU_THREAD_ID, frame.cpu_start_ns, frame.cpu_time_ns, &frame_args)?;
+
+			let swap_start_ns = frame.cpu_start_ns + frame.cpu_busy_ns();
+			write_event(out, "swap wait", "frame", CPU_THREAD_ID, swap_start_ns, frame.swap_wait_ns, "{}")?;
+
+			// GPU scope timestamps are relative to the first scope started in the frame
+			let gpu_origin_ns = frame.cpu_start_ns + frame.scopes.first().map_or(0, |scope| scope.cpu_start_ns);
+			let gpu_end_ns = frame.scopes.iter().map(|scope| scope.gpu_end_ns).max().unwrap_or(0);
+			write_event(out, &frame_name, "frame", GPU_THREAD_ID, gpu_origin_ns, gpu_end_ns, &frame_args)?;
+
+			for scope in frame.scopes.iter() {
+				let mut args = format!("{{\"triangles\":{},\"self_triangles\":{}", scope.triangles, scope.self_triangles);
+
+				for (counter, value) in scope.counters.iter() {
+					args += &format!(",\"{}\":{}", counter.name(), value);
+				}
+
+				args += "}";
+
+				write_event(out, &scope.name, "cpu", CPU_THREAD_ID,
+					frame.cpu_start_ns + scope.cpu_start_ns, scope.cpu_time_ns(), &args)?;
+
+				write_event(out, &scope.name, "gpu", GPU_THREAD_ID,
+					gpu_origin_ns + scope.gpu_start_ns, scope.gpu_time_ns(), &args)?;
+			}
+		}
+
+		writeln!(out, "]}}")
+	}
+}
+
+
+impl TraceRequest {
+	/// Removes `--trace [FRAMES]` and `--trace-output PATH` from `args`, leaving any others in place.
+	pub fn take_from_args(args: &mut Vec<String>) -> Result<Option<TraceRequest>, String> {
+		let mut request = None;
+		let mut path = None;
+		let mut index = 0;
+
+		while index < args.len() {
+			match args[index].as_str() {
+				"--trace" => {
+					args.remove(index);
+
+					let frames = match args.get(index).map(|arg| arg.parse()) {
+						Some(Ok(frames)) => {
+							args.remove(index);
+							frames
+						}
+
+						_ => DEFAULT_TRACE_FRAMES,
+					};
+
+					request = Some(frames);
+				}
+
+				"--trace-output" => {
+					args.remove(index);
+
+					if index >= args.len() {
+						return Err("--trace-output expects a value".into())
+					}
+
+					path = Some(PathBuf::from(args.remove(index)));
+				}
+
+				_ => { index += 1 }
+			}
+		}
+
+		match (request, path) {
+			(Some(frames), path) => Ok(Some(TraceRequest {
+				frames,
+				path: path.unwrap_or_else(|| DEFAULT_TRACE_PATH.into()),
+			})),
+
+			(None, Some(_)) => Err("--trace-output requires --trace".into()),
+			(None, None) => Ok(None),
+		}
+	}
+}
+
+impl Default for TraceRequest {
+	fn default() -> Self {
+		TraceRequest {
+			frames: DEFAULT_TRACE_FRAMES,
+			path: DEFAULT_TRACE_PATH.into(),
+		}
+	}
+}
+
+
+/// Writes a complete event. Times are given in nanoseconds, but written in microseconds.
+fn write_event(out: &mut impl Write, name: &str, category: &str, thread_id: u32, start_ns: u64, duration_ns: u64, args: &str) -> io::Result<()> {
+	write!(out, ",\n{{\"name\":")?;
+	write_json_string(out, name)?;
+
+	write!(out, ",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":{},\"tid\":{},\"ts\":{:.3},\"dur\":{:.3},\"args\":{}}}",
+		category, PROCESS_ID, thread_id, start_ns as f64 / 1000.0, duration_ns as f64 / 1000.0, args)
+}
+
+pub(crate) fn write_json_string(out: &mut impl Write, value: &str) -> io::Result<()> {
+	write!(out, "\"")?;
+
+	for c in value.chars() {
+		match c {
+			'"' => write!(out, "\\\"")?,
+			'\\' => write!(out, "\\\\")?,
+			'\n' => write!(out, "\\n")?,
+			c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+			c => write!(out, "{}", c)?,
+		}
+	}
+
+	write!(out, "\"")
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::args::owned as args;
+	use super::super::frame::{FrameTiming, RawScope};
+
+	#[test]
+	fn takes_trace_args() {
+		let mut list = args(&["--headless", "--trace", "30", "--frames", "10", "--trace-output", "out.json"]);
+		let request = TraceRequest::take_from_args(&mut list).unwrap();
+
+		assert_eq!(request, Some(TraceRequest { frames: 30, path: "out.json".into() }));
+		assert_eq!(list, args(&["--headless", "--frames", "10"]));
+
+		let mut list = args(&["--trace", "--headless"]);
+		assert_eq!(TraceRequest::take_from_args(&mut list).unwrap(), Some(TraceRequest::default()));
+		assert_eq!(list, args(&["--headless"]));
+
+		assert_eq!(TraceRequest::take_from_args(&mut args(&["--headless"])).unwrap(), None);
+		assert!(TraceRequest::take_from_args(&mut args(&["--trace-output", "out.json"])).is_err());
+	}
+
+	#[test]
+	fn writes_events_on_both_tracks() {
+		let frame = FrameResults::from_raw(vec![
+			RawScope {
+				name: "draw \"terrain\"".into(),
+				parent: None,
+				depth: 0,
+				gpu_start_ns: 5000,
+				gpu_end_ns: 7000,
+				cpu_start_ns: 1000,
+				cpu_end_ns: 2500,
+				self_triangles: 12,
+				self_counters: Default::default(),
+			},
+		], FrameTiming { index: 3, cpu_start_ns: 10_000, cpu_time_ns: 4000, swap_wait_ns: 1000 });
+
+		let mut trace = TraceRecorder::new("unused.json", 1);
+		trace.record(&frame);
+		trace.record(&frame);
+		assert!(trace.is_complete());
+
+		let mut output = Vec::new();
+		trace.write(&mut output).unwrap();
+		let output = String::from_utf8(output).unwrap();
+
+		assert_eq!(output.matches("\"ph\":\"X\"").count(), 5);
+		assert!(output.contains("{\"name\":\"frame 3\",\"cat\":\"frame\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":10.000,\"dur\":4.000,\"args\":{\"triangles\":12}}"));
+		assert!(output.contains("{\"name\":\"swap wait\",\"cat\":\"frame\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":13.000,\"dur\":1.000"));
+		assert!(output.contains("{\"name\":\"draw \\\"terrain\\\"\",\"cat\":\"cpu\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":11.000,\"dur\":1.500"));
+		assert!(output.contains("{\"name\":\"draw \\\"terrain\\\"\",\"cat\":\"gpu\",\"ph\":\"X\",\"pid\":1,\"tid\":2,\"ts\":11.000,\"dur\":2.000"));
+	}
+}
@@ -1,18 +1,24 @@
 
 use std::error::Error;
+use std::mem::size_of;
 use common::math::*;
-use crate::{gl, mesh, perf};
+use crate::{gl, mesh, perf, perf_scope, GLOBAL_UNIFORMS};
+use crate::gl::GpuLayout;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+const WORKGROUP_SIZE: u32 = 32;
+
+#[derive(Copy, Clone, Debug, GpuLayout)]
 struct Vertex {
-	pos: Vec3, _1: f32,
-	color: Vec3, _2: f32,
+	position: Vec3,
+	color: Vec3,
 }
 
+const VERTEX_DATA: gl::ExpectedResource = gl::ExpectedResource::storage_block("VertexData", 0, Vertex::STD430.size).with_members(Vertex::FIELDS_STD430);
+const MESHLET_DATA: gl::ExpectedResource = gl::ExpectedResource::storage_block("MeshletData", 1, size_of::<u32>());
+
 impl Vertex {
-	fn new(pos: Vec3, color: Vec3) -> Vertex {
-		Vertex { pos, color, _1: 0.0, _2: 0.0 }
+	fn new(position: Vec3, color: Vec3) -> Vertex {
+		Vertex { position, color }
 	}
 }
 
@@ -23,14 +29,44 @@ pub struct SceneView {
 	vertex_ssbo: gl::Buffer,
 	meshlet_data_ssbo: gl::Buffer,
 	num_meshlets: u32,
+
+	/// Only built for the vertex pipeline fallback, which draws with these instead of mesh shaders
+	index_vao: Option<gl::VertexArray>,
+	num_indices: u32,
 }
 
 impl SceneView {
-	pub fn new(gl_ctx: &gl::Context) -> Result<SceneView, Box<dyn Error>> {
-		let program = gl_ctx.new_shader(&[
-			(gl::raw::MESH_SHADER_NV, include_str!("shaders/scene.mesh.glsl")),
-			(gl::raw::FRAGMENT_SHADER, include_str!("shaders/scene.frag.glsl")),
-		]);
+	pub fn new(gl_ctx: &impl gl::Backend) -> Result<SceneView, Box<dyn Error>> {
+		let capabilities = gl_ctx.capabilities();
+		let render_path = capabilities.render_path();
+
+		let program = match render_path {
+			gl::RenderPath::MeshShaders => {
+				if mesh::MAX_MESHLET_VERTICES > capabilities.max_mesh_output_vertices as usize
+					|| mesh::MAX_MESHLET_TRIANGLES > capabilities.max_mesh_output_primitives as usize
+				{
+					return Err(format!("scene view meshlets exceed device mesh output limits ({} vertices, {} primitives)",
+						capabilities.max_mesh_output_vertices, capabilities.max_mesh_output_primitives).into())
+				}
+
+				gl_ctx.new_shader("scene", &[
+					(gl::raw::MESH_SHADER_NV, include_str!("shaders/scene.mesh.glsl")),
+					(gl::raw::FRAGMENT_SHADER, include_str!("shaders/scene.frag.glsl")),
+				], &gl::Defines::new()
+					.with("WORKGROUP_SIZE", WORKGROUP_SIZE)
+					.with("MAX_MESHLET_VERTICES", mesh::MAX_MESHLET_VERTICES)
+					.with("MAX_MESHLET_TRIANGLES", mesh::MAX_MESHLET_TRIANGLES),
+				&[GLOBAL_UNIFORMS, VERTEX_DATA, MESHLET_DATA])?
+			}
+
+			gl::RenderPath::VertexPipeline => {
+				gl_ctx.new_shader("scene", &[
+					(gl::raw::VERTEX_SHADER, include_str!("shaders/scene.vert.glsl")),
+					(gl::raw::FRAGMENT_SHADER, include_str!("shaders/scene.frag.glsl")),
+				], &gl::Defines::new().with("VERTEX_PIPELINE", 1),
+				&[GLOBAL_UNIFORMS, VERTEX_DATA])?
+			}
+		};
 
 
 		let toy_project = toy::load(include_bytes!("fish.toy"))?;
@@ -61,28 +97,78 @@ impl SceneView {
 
 		let mesh = mb.build();
 
-		let vertex_ssbo = gl_ctx.new_buffer();
-		let meshlet_data_ssbo = gl_ctx.new_buffer();
+		let vertex_ssbo = gl_ctx.new_buffer("scene vertices");
+		let meshlet_data_ssbo = gl_ctx.new_buffer("scene meshlets");
+
+		gl_ctx.upload_std430(vertex_ssbo, &mesh.vertex_data, gl::BufferUsage::Static);
+		gl_ctx.upload(meshlet_data_ssbo, &mesh.meshlet_data, gl::BufferUsage::Static);
+
+		let index_vao = match render_path {
+			gl::RenderPath::MeshShaders => None,
+			gl::RenderPath::VertexPipeline => {
+				let index_buffer = gl_ctx.new_buffer("scene indices");
+				gl_ctx.upload(index_buffer, &mesh.indices, gl::BufferUsage::Static);
 
-		vertex_ssbo.upload(&mesh.vertex_data, gl::BufferUsage::Static);
-		meshlet_data_ssbo.upload(&mesh.meshlet_data, gl::BufferUsage::Static);
+				let index_vao = gl_ctx.new_vertex_array("scene");
+				gl_ctx.set_index_buffer(index_vao, index_buffer);
+				Some(index_vao)
+			}
+		};
 
 		Ok(SceneView {
 			program,
 			vertex_ssbo,
 			meshlet_data_ssbo,
 			num_meshlets: mesh.num_meshlets as _,
+
+			index_vao,
+			num_indices: mesh.indices.len() as _,
 		})
 	}
 
-	pub fn draw(&self, gl_ctx: &gl::Context, inst: &mut perf::Instrumenter) {
-		gl_ctx.bind_shader_storage_buffer(0, self.vertex_ssbo);
-		gl_ctx.bind_shader_storage_buffer(1, self.meshlet_data_ssbo);
+	pub fn draw(&self, gl_ctx: &impl gl::Backend, inst: &mut perf::Instrumenter) {
+		gl_ctx.bind_shader_storage_buffer(VERTEX_DATA.binding, self.vertex_ssbo);
+		gl_ctx.bind_shader_storage_buffer(MESHLET_DATA.binding, self.meshlet_data_ssbo);
 
 		gl_ctx.use_program(self.program);
 
-		inst.start_section("scene");
-		gl_ctx.draw_mesh_tasks(0, self.num_meshlets);
-		inst.end_section();
+		perf_scope!(inst, "scene", perf::CounterSet::PIPELINE_STATISTICS.with(perf::Counter::MeshWorkgroups));
+
+		match self.index_vao {
+			None => {
+				gl_ctx.draw_mesh_tasks(&gl::PipelineState::DEFAULT, 0, self.num_meshlets);
+				inst.count_mesh_workgroups(self.num_meshlets);
+			}
+
+			Some(index_vao) => gl_ctx.draw_indexed(&gl::PipelineState::DEFAULT, index_vao, self.num_indices),
+		}
 	}
-}
\ No newline at end of file
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::gl::{Command, RecordingBackend};
+
+	fn created_labels(commands: &[Command]) -> Vec<&str> {
+		commands.iter()
+			.filter_map(|command| match command {
+				Command::NewBuffer { label, .. } | Command::NewVertexArray { label, .. } => Some(label.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn only_builds_index_buffer_for_vertex_pipeline() {
+		let gl_ctx = RecordingBackend::with_mesh_shaders();
+		SceneView::new(&gl_ctx).unwrap();
+		assert_eq!(created_labels(&gl_ctx.take_commands()), ["scene vertices", "scene meshlets"]);
+
+		let gl_ctx = RecordingBackend::without_mesh_shaders();
+		SceneView::new(&gl_ctx).unwrap();
+		assert_eq!(created_labels(&gl_ctx.take_commands()), ["scene vertices", "scene meshlets", "scene indices", "scene"]);
+	}
+}
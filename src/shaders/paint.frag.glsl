@@ -3,7 +3,7 @@
 layout(binding = 0) uniform sampler2D u_paint_sampler;
 
 
-in PerVertexData {
+layout(location = 0) in PerVertexData {
 	vec2 uv;
 } vert_in;
 
@@ -8,7 +8,7 @@ layout(local_size_x=2) in;
 layout(max_vertices=4, max_primitives=2) out;
 
 
-out PerVertexData {
+layout(location = 0) out PerVertexData {
 	vec2 uv;
 } vert_out[];
 
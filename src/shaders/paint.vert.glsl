@@ -0,0 +1,28 @@
+#version 450
+
+#import global
+
+
+layout(location = 0) out PerVertexData {
+	vec2 uv;
+} vert_out;
+
+
+const vec2 positions[] = {
+	vec2( 0.0, 0.0),
+	vec2( 1.0, 0.0),
+	vec2( 1.0, 1.0),
+	vec2( 0.0, 1.0),
+};
+
+const uint indices[] = {0, 1, 2,  0, 2, 3};
+
+
+// Fallback for paint.mesh.glsl
+void main() {
+	vec2 position = positions[indices[gl_VertexID]];
+	vec2 position_world = (position - 0.5) * u_world_size;
+
+	gl_Position = u_projection_view * vec4(position_world.x, 0.0, position_world.y, 1.0);
+	vert_out.uv = position;
+}
@@ -2,7 +2,7 @@
 
 #import global
 
-layout(local_size_x=8, local_size_y=8, local_size_z=1) in;
+layout(local_size_x=WORKGROUP_SIZE, local_size_y=WORKGROUP_SIZE, local_size_z=1) in;
 
 layout(std140, binding = 1) uniform BrushData {
 	vec2 center;
@@ -7,8 +7,8 @@
 
 layout(triangles) out;
 
-layout(local_size_x=32) in;
-layout(max_vertices=64, max_primitives=64) out;
+layout(local_size_x=WORKGROUP_SIZE) in;
+layout(max_vertices=PARTICLES_PER_WORKGROUP*4, max_primitives=PARTICLES_PER_WORKGROUP*3) out;
 
 
 taskNV in Task {
@@ -17,12 +17,12 @@ taskNV in Task {
 };
 
 
-out PerVertexData {
+layout(location = 0) out PerVertexData {
 	// vec2 uv;
 	float affect;
 } vert_out[];
 
-perprimitiveNV out PerPrimitiveData {
+layout(location = 1) perprimitiveNV out PerPrimitiveData {
 	vec3 color;
 } prim_out[];
 
@@ -61,14 +61,14 @@ void main() {
 
 	const uint local_id = gl_LocalInvocationID.x;
 
-	const uint work_group_base = (t_base_particle_id + gl_WorkGroupID.x) * num_threads;
+	const uint work_group_base = (t_base_particle_id + gl_WorkGroupID.x) * PARTICLES_PER_WORKGROUP;
 
 
 	const uint vertex_iterations = (max_vertices + num_threads - 1) / num_threads;
 
 	for (int v = 0; v < vertex_iterations; v++) {
 		uint vertex_index = min(v * num_threads + local_id, max_vertices-1);
-		uint particle_index = (work_group_base * vertex_iterations + vertex_index) / positions.length();
+		uint particle_index = work_group_base + vertex_index / positions.length();
 
 		Particle particle = g_particles[particle_index];
 
@@ -108,7 +108,7 @@ void main() {
 	}
 
 	if (local_id == 0) {
-		const uint primitive_count = min(16 * primitives_per_particle, max_primitives);
+		const uint primitive_count = min(PARTICLES_PER_WORKGROUP * primitives_per_particle, max_primitives);
 		gl_PrimitiveCountNV = primitive_count;
 	}
 }
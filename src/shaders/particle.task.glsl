@@ -16,7 +16,7 @@ taskNV out Task {
 void main() {
 	const uint global_id = gl_WorkGroupID.x;
 
-	const uint particles_per_task = 16;
+	const uint particles_per_task = PARTICLES_PER_WORKGROUP;
 	const uint max_particles = particle_buffer_size / particles_per_task;
 
 	const uint already_spawned = max_task_output_count * global_id;
@@ -0,0 +1,55 @@
+#version 450
+
+#import global
+#import particle
+
+
+layout(location = 0) out PerVertexData {
+	float affect;
+} vert_out;
+
+layout(location = 1) out PerPrimitiveData {
+	flat vec3 color;
+} prim_out;
+
+
+const vec2 positions[] = {
+	vec2( 0.0,  0.0),
+	vec2( 0.5, -0.25),
+	vec2( 0.0,  0.5),
+	vec2(-0.5, -0.25),
+};
+
+const float affect[] = {
+	1.0, 0.0, 0.0, 0.0
+};
+
+const uint indices[] = {0, 1, 2,  0, 2, 3,  0, 3, 1};
+
+
+// Fallback for particle.mesh.glsl - each instance is one particle
+void main() {
+	const uint primitives_per_particle = indices.length() / 3;
+
+	uint particle_index = uint(gl_InstanceID);
+	uint corner = indices[gl_VertexID];
+
+	Particle particle = g_particles[particle_index];
+
+	vec2 pos_uv = positions[corner];
+	vec3 pos_local = pos_uv.x * u_camera_right.xyz + pos_uv.y * u_camera_up.xyz;
+
+	float size = 30.0;
+	vec3 pos = mix(particle.tail, particle.position, affect[corner]) + pos_local * 0.01 * size;
+
+	gl_Position = u_projection_view * vec4(pos, 1.0);
+	vert_out.affect = affect[corner];
+
+	// Mirrors the per primitive colours calculated in particle.mesh.glsl
+	uint local_particle = particle_index % PARTICLES_PER_WORKGROUP;
+	uint primitive_index = local_particle * primitives_per_particle + uint(gl_VertexID) / 3;
+	uint color_base = local_particle * positions.length() + primitive_index;
+	uvec3 color_idx = uvec3(color_base / 2, color_base / 4, color_base / 3);
+
+	prim_out.color = mix(vec3(1.0, 0.5, 1.0), vec3(0.7, 0.3, 0.3), fract(vec3(color_idx) / 5.0));
+}
@@ -3,7 +3,7 @@
 #import global
 #import particle
 
-layout(local_size_x=16, local_size_y=1, local_size_z=1) in;
+layout(local_size_x=WORKGROUP_SIZE, local_size_y=1, local_size_z=1) in;
 
 
 layout(binding = 0) uniform sampler2D u_paint_sampler;
@@ -1,12 +1,18 @@
 #version 450
 
-in PerVertexData {
+layout(location = 0) in PerVertexData {
 	vec3 color;
 } vert_in;
 
-perprimitiveNV in PerPrimitiveData {
+#ifdef VERTEX_PIPELINE
+layout(location = 1) in PerPrimitiveData {
+	flat vec3 color;
+} prim_in;
+#else
+layout(location = 1) perprimitiveNV in PerPrimitiveData {
 	vec3 color;
 } prim_in;
+#endif
 
 layout(location = 0) out vec4 out_color;
 
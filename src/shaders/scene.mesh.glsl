@@ -4,8 +4,8 @@
 #import global
 
 layout(triangles) out;
-layout(local_size_x=32) in;
-layout(max_vertices=64, max_primitives=126) out;
+layout(local_size_x=WORKGROUP_SIZE) in;
+layout(max_vertices=MAX_MESHLET_VERTICES, max_primitives=MAX_MESHLET_TRIANGLES) out;
 
 
 struct Vertex {
@@ -29,11 +29,11 @@ layout(std430, binding = 1) buffer MeshletData {
 };
 
 
-out PerVertexData {
+layout(location = 0) out PerVertexData {
 	vec3 color;
 } vert_out[];
 
-perprimitiveNV out PerPrimitiveData {
+layout(location = 1) perprimitiveNV out PerPrimitiveData {
 	vec3 color;
 } prim_out[];
 
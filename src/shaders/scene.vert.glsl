@@ -0,0 +1,32 @@
+#version 450
+
+#import global
+
+
+struct Vertex {
+	vec3 position;
+	vec3 color;
+};
+
+layout(std430, binding = 0) buffer VertexData {
+	Vertex vertices[];
+};
+
+
+layout(location = 0) out PerVertexData {
+	vec3 color;
+} vert_out;
+
+layout(location = 1) out PerPrimitiveData {
+	flat vec3 color;
+} prim_out;
+
+
+// Fallback for scene.mesh.glsl - meshlets are expanded into an index buffer on the cpu
+void main() {
+	Vertex vertex = vertices[gl_VertexID];
+
+	gl_Position = u_projection_view * vec4(vertex.position, 1.0);
+	vert_out.color = vertex.color;
+	prim_out.color = vec3(1.0);
+}
@@ -2,7 +2,7 @@
 
 layout(binding = 0) uniform sampler2D u_heightmap_sampler;
 
-in PerVertexData {
+layout(location = 0) in PerVertexData {
 	vec3 debug_col;
 	vec2 uv;
 } vert_in;
@@ -13,6 +13,6 @@ layout(location = 0) out vec4 out_color;
 
 
 void main() {
-	float height = texture2D(u_heightmap_sampler, vert_in.uv).r;
+	float height = texture(u_heightmap_sampler, vert_in.uv).r;
     out_color = vec4(vert_in.debug_col.xy, fract(height), 1.0);
 }
@@ -4,13 +4,13 @@
 #import global
 
 layout(triangles) out;
-layout(local_size_x=32) in;
-layout(max_vertices=64, max_primitives=126) out;
+layout(local_size_x=WORKGROUP_SIZE) in;
+layout(max_vertices=(QUAD_SPAN_PER_PATCH+1)*(QUAD_SPAN_PER_PATCH+1), max_primitives=QUAD_SPAN_PER_PATCH*QUAD_SPAN_PER_PATCH*2) out;
 
 
 layout(binding = 0) uniform sampler2D u_heightmap_sampler;
 
-out PerVertexData {
+layout(location = 0) out PerVertexData {
 	vec3 debug_col;
 	vec2 uv;
 } vert_out[];
@@ -69,14 +69,14 @@ void main() {
 	const uint local_id = gl_LocalInvocationID.x;
 	const uint patch_id = gl_WorkGroupID.x;
 
-	const uint quad_span_per_patch = 4;
+	const uint quad_span_per_patch = QUAD_SPAN_PER_PATCH;
 	const uint quads_per_patch = quad_span_per_patch * quad_span_per_patch;
 	const uint vertices_per_patch = (quad_span_per_patch+1) * (quad_span_per_patch+1);
 
-	const uint subdivisions = 8;
+	const uint subdivisions = SUBDIVISIONS;
 	const uint total_quad_span = 1 << subdivisions;
 
-	const uint total_patch_span = max(total_quad_span / quads_per_patch, total_quad_span);
+	const uint total_patch_span = total_quad_span / quad_span_per_patch;
 
 	const vec2 patch_size = vec2(float(quad_span_per_patch));
 	const vec2 patch_offset = calculate_patch_offset(total_patch_span, patch_id) * patch_size;
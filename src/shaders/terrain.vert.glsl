@@ -0,0 +1,49 @@
+#version 450
+
+#import global
+
+
+layout(binding = 0) uniform sampler2D u_heightmap_sampler;
+
+layout(location = 0) out PerVertexData {
+	vec3 debug_col;
+	vec2 uv;
+} vert_out;
+
+
+const uvec2 index_offsets[] = {
+	uvec2(0, 0),
+	uvec2(1, 0),
+	uvec2(0, 1),
+
+	uvec2(1, 0),
+	uvec2(1, 1),
+	uvec2(0, 1),
+};
+
+
+// Fallback for terrain.mesh.glsl - each instance is one patch, drawn as a list of unindexed quads
+void main() {
+	const uint quad_span_per_patch = QUAD_SPAN_PER_PATCH;
+
+	const uint subdivisions = SUBDIVISIONS;
+	const uint total_quad_span = 1 << subdivisions;
+
+	const uint total_patch_span = total_quad_span / quad_span_per_patch;
+
+	uint patch_id = uint(gl_InstanceID);
+	uint quad_index = uint(gl_VertexID) / 6;
+	uvec2 quad_vertex = uvec2(quad_index % quad_span_per_patch, quad_index / quad_span_per_patch)
+		+ index_offsets[uint(gl_VertexID) % 6];
+
+	vec2 patch_offset = vec2(patch_id % total_patch_span, patch_id / total_patch_span) * float(quad_span_per_patch);
+
+	vec2 position = (vec2(quad_vertex) + patch_offset) / float(quad_span_per_patch * total_patch_span);
+	vec2 position_world = (position - 0.5) * u_world_size;
+
+	float height = texture(u_heightmap_sampler, position).r;
+
+	gl_Position = u_projection_view * vec4(position_world.x, height, position_world.y, 1.0);
+	vert_out.debug_col = vec3(float(patch_id / 200) / 200.0, float(patch_id % 200) / 200.0, 0.0);
+	vert_out.uv = position;
+}
@@ -0,0 +1,30 @@
+#version 450
+
+// Two words per 8x8 glyph, four rows per word with the top row in the lowest byte
+layout(binding = 1, std430) readonly buffer FontData {
+	uint s_font[];
+};
+
+
+layout(location = 0) in PerVertexData {
+	vec2 uv;
+	vec4 color;
+	flat uint glyph;
+} vert_in;
+
+layout(location = 0) out vec4 out_color;
+
+
+
+void main() {
+	ivec2 texel = clamp(ivec2(vert_in.uv * 8.0), ivec2(0), ivec2(7));
+
+	uint word = s_font[vert_in.glyph * 2 + texel.y / 4];
+	uint row = (word >> ((texel.y % 4) * 8)) & 0xFF;
+
+	if ((row & (1u << texel.x)) == 0) {
+		discard;
+	}
+
+	out_color = vert_in.color;
+}
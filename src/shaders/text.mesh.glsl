@@ -0,0 +1,71 @@
+#version 450
+#extension GL_NV_mesh_shader : require
+
+layout(triangles) out;
+layout(local_size_x=WORKGROUP_SIZE) in;
+layout(max_vertices=MAX_VERTICES, max_primitives=MAX_PRIMITIVES) out;
+
+
+struct Quad {
+	vec2 pos;
+	vec2 size;
+	vec4 color;
+	uint glyph;
+};
+
+layout(binding = 1, std140) uniform TextData {
+	vec2 u_screen_size;
+	uint u_num_quads;
+};
+
+layout(binding = 0, std430) readonly buffer QuadData {
+	Quad s_quads[];
+};
+
+
+layout(location = 0) out PerVertexData {
+	vec2 uv;
+	vec4 color;
+	flat uint glyph;
+} vert_out[];
+
+
+const vec2 corners[] = {
+	vec2(0.0, 0.0),
+	vec2(1.0, 0.0),
+	vec2(1.0, 1.0),
+	vec2(0.0, 1.0),
+};
+
+const uint indices[] = {0, 1, 2,  0, 2, 3};
+
+
+// Each thread emits one quad
+void main() {
+	const uint num_threads = gl_WorkGroupSize.x;
+	const uint local_id = gl_LocalInvocationID.x;
+	const uint first_quad = gl_WorkGroupID.x * num_threads;
+	const uint quad_index = first_quad + local_id;
+
+	if (quad_index < u_num_quads) {
+		Quad quad = s_quads[quad_index];
+
+		for (uint c = 0; c < 4; c++) {
+			uint vertex_index = local_id * 4 + c;
+			vec2 position = (quad.pos + corners[c] * quad.size) / u_screen_size;
+
+			gl_MeshVerticesNV[vertex_index].gl_Position = vec4(position.x * 2.0 - 1.0, 1.0 - position.y * 2.0, 0.0, 1.0);
+			vert_out[vertex_index].uv = corners[c];
+			vert_out[vertex_index].color = quad.color;
+			vert_out[vertex_index].glyph = quad.glyph;
+		}
+
+		for (uint i = 0; i < 6; i++) {
+			gl_PrimitiveIndicesNV[local_id * 6 + i] = local_id * 4 + indices[i];
+		}
+	}
+
+	if (local_id == 0) {
+		gl_PrimitiveCountNV = min(num_threads, u_num_quads - first_quad) * 2;
+	}
+}
@@ -0,0 +1,49 @@
+#version 450
+
+
+struct Quad {
+	vec2 pos;
+	vec2 size;
+	vec4 color;
+	uint glyph;
+};
+
+layout(binding = 1, std140) uniform TextData {
+	vec2 u_screen_size;
+	uint u_num_quads;
+};
+
+layout(binding = 0, std430) readonly buffer QuadData {
+	Quad s_quads[];
+};
+
+
+layout(location = 0) out PerVertexData {
+	vec2 uv;
+	vec4 color;
+	flat uint glyph;
+} vert_out;
+
+
+const vec2 corners[] = {
+	vec2(0.0, 0.0),
+	vec2(1.0, 0.0),
+	vec2(1.0, 1.0),
+	vec2(0.0, 1.0),
+};
+
+const uint indices[] = {0, 1, 2,  0, 2, 3};
+
+
+// Fallback for text.mesh.glsl, with one instance per quad
+void main() {
+	Quad quad = s_quads[gl_InstanceID];
+
+	vec2 corner = corners[indices[gl_VertexID]];
+	vec2 position = (quad.pos + corner * quad.size) / u_screen_size;
+
+	gl_Position = vec4(position.x * 2.0 - 1.0, 1.0 - position.y * 2.0, 0.0, 1.0);
+	vert_out.uv = corner;
+	vert_out.color = quad.color;
+	vert_out.glyph = quad.glyph;
+}
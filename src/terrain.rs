@@ -1,34 +1,139 @@
+use std::error::Error;
 use common::math::*;
-use crate::{gl, perf, paint};
+use crate::{gl, perf, perf_scope, paint, GLOBAL_UNIFORMS};
 
 // https://onrendering.com/data/papers/isubd/isubd.pdf
 // https://victorbush.com/2015/01/tessellated-terrain/
 
+const WORKGROUP_SIZE: u32 = 32;
+const QUAD_SPAN_PER_PATCH: u32 = 4;
+
+const HEIGHTMAP_SAMPLER: gl::ExpectedResource = gl::ExpectedResource::sampler("u_heightmap_sampler", 0);
+
+/// At least one whole patch
+pub const MIN_SUBDIVISIONS: u32 = QUAD_SPAN_PER_PATCH.trailing_zeros();
+
+/// 128x128 patches, well within the 65535 mesh tasks a single draw can launch
+pub const MAX_SUBDIVISIONS: u32 = 9;
+
 pub struct Terrain {
+	render_path: gl::RenderPath,
+	programs: gl::ShaderPermutations,
 	program: gl::Program,
+
+	subdivisions: u32,
 }
 
 impl Terrain {
-	pub fn new(gl_ctx: &gl::Context) -> Terrain {
-		let program = gl_ctx.new_shader(&[
-			(gl::raw::MESH_SHADER_NV, include_str!("shaders/terrain.mesh.glsl")),
+	pub fn new(gl_ctx: &impl gl::Backend) -> Result<Terrain, Box<dyn Error>> {
+		let render_path = gl_ctx.capabilities().render_path();
+
+		let geometry_shader = match render_path {
+			gl::RenderPath::MeshShaders => (gl::raw::MESH_SHADER_NV, include_str!("shaders/terrain.mesh.glsl")),
+			gl::RenderPath::VertexPipeline => (gl::raw::VERTEX_SHADER, include_str!("shaders/terrain.vert.glsl")),
+		};
+
+		let mut programs = gl::ShaderPermutations::new("terrain", &[
+			geometry_shader,
 			(gl::raw::FRAGMENT_SHADER, include_str!("shaders/terrain.frag.glsl")),
-		]);
+		], &[GLOBAL_UNIFORMS, HEIGHTMAP_SAMPLER]);
 
-		Terrain {
-			program
-		}
+		let subdivisions = 8;
+		let program = programs.get(gl_ctx, &Terrain::defines(subdivisions))?;
+
+		Ok(Terrain {
+			render_path,
+			programs,
+			program,
+
+			subdivisions,
+		})
+	}
+
+	pub fn subdivisions(&self) -> u32 {
+		self.subdivisions
+	}
+
+	pub fn set_subdivisions(&mut self, gl_ctx: &impl gl::Backend, subdivisions: u32) -> Result<(), Box<dyn Error>> {
+		let subdivisions = subdivisions.clamp(MIN_SUBDIVISIONS, MAX_SUBDIVISIONS);
+
+		self.program = self.programs.get(gl_ctx, &Terrain::defines(subdivisions))?;
+		self.subdivisions = subdivisions;
+		Ok(())
 	}
 
-	pub fn draw(&self, gl_ctx: &gl::Context, inst: &mut perf::Instrumenter, paint_resources: paint::Resources) {
-		paint_resources.bind(gl_ctx, 0);
+	pub fn draw(&self, gl_ctx: &impl gl::Backend, inst: &mut perf::Instrumenter, paint_resources: paint::Resources) {
+		paint_resources.bind(gl_ctx, HEIGHTMAP_SAMPLER.binding, gl::Filter::Linear);
 
 		gl_ctx.use_program(self.program);
 
-		inst.start_section("terrain");
-		gl_ctx.draw_mesh_tasks(0, 60000);
-		inst.end_section();
+		// Mirrors the patch layout calculated in terrain.mesh.glsl and terrain.vert.glsl
+		let quads_per_patch = QUAD_SPAN_PER_PATCH * QUAD_SPAN_PER_PATCH;
+		let total_quad_span = 1 << self.subdivisions;
+		let total_patch_span = total_quad_span / QUAD_SPAN_PER_PATCH;
+		let num_patches = total_patch_span * total_patch_span;
+
+		perf_scope!(inst, "terrain", perf::CounterSet::PIPELINE_STATISTICS.with(perf::Counter::MeshWorkgroups));
+
+		match self.render_path {
+			gl::RenderPath::MeshShaders => {
+				gl_ctx.draw_mesh_tasks(&gl::PipelineState::DEFAULT, 0, num_patches);
+				inst.count_mesh_workgroups(num_patches);
+			}
+
+			gl::RenderPath::VertexPipeline => gl_ctx.draw_instanced(&gl::PipelineState::DEFAULT, quads_per_patch * 6, num_patches),
+		}
+	}
+
+	fn defines(subdivisions: u32) -> gl::Defines {
+		gl::Defines::new()
+			.with("WORKGROUP_SIZE", WORKGROUP_SIZE)
+			.with("QUAD_SPAN_PER_PATCH", QUAD_SPAN_PER_PATCH)
+			.with("SUBDIVISIONS", subdivisions)
 	}
 }
 
 
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::gl::{Command, RecordingBackend};
+
+	fn draw_commands(gl_ctx: &RecordingBackend, subdivisions: u32) -> Vec<Command> {
+		let mut inst = perf::Instrumenter::disabled();
+		let paint_system = paint::PaintSystem::new(gl_ctx).unwrap();
+		let mut terrain = Terrain::new(gl_ctx).unwrap();
+		terrain.set_subdivisions(gl_ctx, subdivisions).unwrap();
+
+		gl_ctx.take_commands();
+		terrain.draw(gl_ctx, &mut inst, paint_system.resources());
+
+		gl_ctx.take_commands().into_iter()
+			.filter(|command| matches!(command, Command::DrawMeshTasks{..} | Command::DrawInstanced{..}))
+			.collect()
+	}
+
+	#[test]
+	fn draws_one_task_per_patch() {
+		let gl_ctx = RecordingBackend::with_mesh_shaders();
+		let draw = |count| Command::DrawMeshTasks { state: gl::PipelineState::DEFAULT, offset: 0, count };
+
+		assert_eq!(draw_commands(&gl_ctx, MIN_SUBDIVISIONS), [draw(1)]);
+		assert_eq!(draw_commands(&gl_ctx, 8), [draw(64 * 64)]);
+		assert_eq!(draw_commands(&gl_ctx, MAX_SUBDIVISIONS), [draw(128 * 128)]);
+
+		// Out of range values are clamped
+		assert_eq!(draw_commands(&gl_ctx, 0), [draw(1)]);
+		assert_eq!(draw_commands(&gl_ctx, MAX_SUBDIVISIONS + 1), [draw(128 * 128)]);
+	}
+
+	#[test]
+	fn draws_one_instance_per_patch_without_mesh_shaders() {
+		let gl_ctx = RecordingBackend::without_mesh_shaders();
+		let draw = |instance_count| Command::DrawInstanced { state: gl::PipelineState::DEFAULT, vertex_count: 16 * 6, instance_count };
+
+		assert_eq!(draw_commands(&gl_ctx, MIN_SUBDIVISIONS), [draw(1)]);
+		assert_eq!(draw_commands(&gl_ctx, MAX_SUBDIVISIONS), [draw(128 * 128)]);
+	}
+}
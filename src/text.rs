@@ -0,0 +1,252 @@
+use std::error::Error;
+use common::math::*;
+use crate::gl;
+use crate::gl::GpuLayout;
+
+mod font;
+
+
+/// text.mesh.glsl emits one quad per thread.
+const QUADS_PER_WORKGROUP: u32 = 32;
+
+/// Width and height of each glyph in the font, in texels.
+pub const GLYPH_SIZE: f32 = 8.0;
+
+const SOLID_GLYPH: u32 = font::GLYPHS.len() as u32 - 1;
+
+
+#[derive(Copy, Clone, Debug, GpuLayout)]
+struct Quad {
+	pos: Vec2,
+	size: Vec2,
+	color: Vec4,
+	glyph: u32,
+}
+
+#[derive(Copy, Clone, Debug, GpuLayout)]
+struct TextUniforms {
+	screen_size: Vec2,
+	num_quads: u32,
+}
+
+
+const QUAD_DATA: gl::ExpectedResource = gl::ExpectedResource::storage_block("QuadData", 0, Quad::STD430.size).with_members(Quad::FIELDS_STD430);
+const FONT_DATA: gl::ExpectedResource = gl::ExpectedResource::storage_block("FontData", 1, std::mem::size_of::<u32>());
+const TEXT_DATA: gl::ExpectedResource = gl::ExpectedResource::uniform_block("TextData", 1, TextUniforms::STD140.size).with_members(TextUniforms::FIELDS_STD140);
+
+
+const PIPELINE_STATE: gl::PipelineState = gl::PipelineState {
+	blend: gl::BlendMode::Alpha,
+	depth_test: false,
+	depth_write: false,
+	..gl::PipelineState::DEFAULT
+};
+
+
+/// Batches text and filled rectangles in screen space, and draws them with an 8x8 bitmap font.
+/// Positions and sizes are in pixels from the top left of the screen.
+pub struct TextRenderer {
+	render_path: gl::RenderPath,
+	program: gl::Program,
+
+	quad_buffer: gl::Buffer,
+	font_buffer: gl::Buffer,
+	uniform_buffer: gl::Buffer,
+
+	/// Pixels per font texel
+	scale: f32,
+	quads: Vec<Quad>,
+}
+
+impl TextRenderer {
+	pub fn new(gl_ctx: &impl gl::Backend) -> Result<TextRenderer, Box<dyn Error>> {
+		let render_path = gl_ctx.capabilities().render_path();
+
+		let geometry_shader = match render_path {
+			gl::RenderPath::MeshShaders => (gl::raw::MESH_SHADER_NV, include_str!("shaders/text.mesh.glsl")),
+			gl::RenderPath::VertexPipeline => (gl::raw::VERTEX_SHADER, include_str!("shaders/text.vert.glsl")),
+		};
+
+		let program = gl_ctx.new_shader("text", &[
+			geometry_shader,
+			(gl::raw::FRAGMENT_SHADER, include_str!("shaders/text.frag.glsl")),
+		], &TextRenderer::defines(), &[QUAD_DATA, FONT_DATA, TEXT_DATA])?;
+
+		let quad_buffer = gl_ctx.new_buffer("text quads");
+		let font_buffer = gl_ctx.new_buffer("font");
+		let uniform_buffer = gl_ctx.new_buffer("text uniforms");
+
+		gl_ctx.upload(font_buffer, &pack_font(), gl::BufferUsage::Static);
+
+		Ok(TextRenderer {
+			render_path,
+			program,
+
+			quad_buffer,
+			font_buffer,
+			uniform_buffer,
+
+			scale: 2.0,
+			quads: Vec::new(),
+		})
+	}
+
+	pub fn scale(&self) -> f32 {
+		self.scale
+	}
+
+	/// Glyphs are scaled by whole numbers of pixels to keep them sharp.
+	pub fn set_scale(&mut self, scale: u32) {
+		self.scale = scale.max(1) as f32;
+	}
+
+	pub fn line_height(&self) -> f32 {
+		GLYPH_SIZE * self.scale
+	}
+
+	pub fn text_width(&self, text: &str) -> f32 {
+		text.chars().count() as f32 * GLYPH_SIZE * self.scale
+	}
+
+	/// Queues a line of text with its top left corner at `pos`. Characters outside printable ASCII are drawn as '?'.
+	pub fn text(&mut self, pos: Vec2, text: &str, color: Vec4) {
+		let advance = GLYPH_SIZE * self.scale;
+
+		for (index, character) in text.chars().enumerate() {
+			let glyph = glyph_index(character);
+			if glyph == 0 { continue }
+
+			self.quads.push(Quad {
+				pos: pos + Vec2::new(index as f32 * advance, 0.0),
+				size: Vec2::splat(advance),
+				color,
+				glyph,
+			});
+		}
+	}
+
+	pub fn rect(&mut self, pos: Vec2, size: Vec2, color: Vec4) {
+		self.quads.push(Quad {
+			pos,
+			size,
+			color,
+			glyph: SOLID_GLYPH,
+		});
+	}
+
+	pub fn num_quads(&self) -> usize {
+		self.quads.len()
+	}
+
+	/// Draws and clears everything queued since the last draw, over whatever framebuffer is bound.
+	pub fn draw(&mut self, gl_ctx: &impl gl::Backend, screen_size: Vec2) {
+		if self.quads.is_empty() { return }
+
+		let num_quads = self.quads.len() as u32;
+
+		gl_ctx.upload_std430(self.quad_buffer, &self.quads, gl::BufferUsage::Stream);
+		gl_ctx.upload_std140(self.uniform_buffer, &[TextUniforms { screen_size, num_quads }], gl::BufferUsage::Stream);
+
+		gl_ctx.bind_shader_storage_buffer(QUAD_DATA.binding, self.quad_buffer);
+		gl_ctx.bind_shader_storage_buffer(FONT_DATA.binding, self.font_buffer);
+		gl_ctx.bind_uniform_buffer(TEXT_DATA.binding, self.uniform_buffer);
+		gl_ctx.use_program(self.program);
+
+		match self.render_path {
+			gl::RenderPath::MeshShaders => {
+				let num_workgroups = (num_quads + QUADS_PER_WORKGROUP - 1) / QUADS_PER_WORKGROUP;
+				gl_ctx.draw_mesh_tasks(&PIPELINE_STATE, 0, num_workgroups);
+			}
+
+			gl::RenderPath::VertexPipeline => gl_ctx.draw_instanced(&PIPELINE_STATE, 6, num_quads),
+		}
+
+		self.quads.clear();
+	}
+
+	fn defines() -> gl::Defines {
+		gl::Defines::new()
+			.with("WORKGROUP_SIZE", QUADS_PER_WORKGROUP)
+			.with("MAX_VERTICES", QUADS_PER_WORKGROUP * 4)
+			.with("MAX_PRIMITIVES", QUADS_PER_WORKGROUP * 2)
+	}
+}
+
+
+/// Index into `font::GLYPHS`, where zero is a space.
+fn glyph_index(character: char) -> u32 {
+	let index = (character as u32).wrapping_sub(font::FIRST_CHAR as u32);
+
+	match index < SOLID_GLYPH {
+		true => index,
+		false => '?' as u32 - font::FIRST_CHAR as u32,
+	}
+}
+
+/// Packs each glyph into two words, four rows per word with the top row in the lowest byte.
+fn pack_font() -> Vec<u32> {
+	font::GLYPHS.iter()
+		.flat_map(|rows| rows.chunks(4))
+		.map(|rows| u32::from_le_bytes([rows[0], rows[1], rows[2], rows[3]]))
+		.collect()
+}
+
+
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::gl::{Command, RecordingBackend};
+
+	#[test]
+	fn skips_spaces_and_replaces_unknown_characters() {
+		assert_eq!(glyph_index(' '), 0);
+		assert_eq!(glyph_index('A'), 'A' as u32 - 32);
+		assert_eq!(glyph_index('~'), SOLID_GLYPH - 1);
+		assert_eq!(glyph_index('\u{7f}'), glyph_index('?'));
+		assert_eq!(glyph_index('é'), glyph_index('?'));
+
+		let words = pack_font();
+		assert_eq!(words.len(), font::GLYPHS.len() * 2);
+		assert_eq!(words[SOLID_GLYPH as usize * 2], !0);
+		assert_eq!(words[glyph_index('!') as usize * 2], 0x183C3C18);
+	}
+
+	#[test]
+	fn draws_one_workgroup_per_32_quads() {
+		let gl_ctx = RecordingBackend::with_mesh_shaders();
+		let mut text = TextRenderer::new(&gl_ctx).unwrap();
+
+		for row in 0..5 {
+			text.text(Vec2::new(0.0, row as f32 * text.line_height()), "0123456 89", Vec4::splat(1.0));
+		}
+
+		assert_eq!(text.num_quads(), 45);
+
+		gl_ctx.take_commands();
+		text.draw(&gl_ctx, Vec2::new(700.0, 700.0));
+
+		let commands = gl_ctx.take_commands();
+		assert!(commands.contains(&Command::DrawMeshTasks { state: PIPELINE_STATE, offset: 0, count: 2 }));
+
+		// Queued quads are only drawn once
+		text.draw(&gl_ctx, Vec2::new(700.0, 700.0));
+		assert!(gl_ctx.commands().is_empty());
+	}
+
+	#[test]
+	fn draws_an_instance_per_quad_without_mesh_shaders() {
+		let gl_ctx = RecordingBackend::without_mesh_shaders();
+		let mut text = TextRenderer::new(&gl_ctx).unwrap();
+
+		text.text(Vec2::zero(), "abc", Vec4::splat(1.0));
+		text.rect(Vec2::zero(), Vec2::splat(10.0), Vec4::splat(0.5));
+
+		gl_ctx.take_commands();
+		text.draw(&gl_ctx, Vec2::new(700.0, 700.0));
+
+		let commands = gl_ctx.take_commands();
+		assert!(commands.contains(&Command::DrawInstanced { state: PIPELINE_STATE, vertex_count: 6, instance_count: 4 }));
+		assert!(!commands.iter().any(|command| matches!(command, Command::DrawMeshTasks{..})));
+	}
+}